@@ -0,0 +1,103 @@
+use crate::player::Player;
+use glam::Vec3;
+use std::path::Path;
+
+/// The file bookmarks are saved to, in the working directory.
+pub const BOOKMARKS_PATH: &str = "bookmarks.txt";
+pub const BOOKMARK_SLOTS: usize = 10;
+
+/// A saved camera viewpoint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bookmark {
+    pub pos: Vec3,
+    pub rot: Vec3,
+}
+impl Bookmark {
+    pub fn of(player: &Player) -> Self {
+        Self {
+            pos: player.pos,
+            rot: player.rot,
+        }
+    }
+
+    /// Moves `player` to this viewpoint, stopping any movement.
+    pub fn apply(&self, player: &mut Player) {
+        player.pos = self.pos;
+        player.rot = self.rot;
+        player.vel = Vec3::ZERO;
+    }
+}
+
+/// Numbered bookmark slots, saved as one `slot x y z rot_x rot_y rot_z` line per bookmark.
+#[derive(Default)]
+pub struct Bookmarks {
+    pub slots: [Option<Bookmark>; BOOKMARK_SLOTS],
+}
+impl Bookmarks {
+    /// Loads bookmarks from `path`, or returns no bookmarks if it can't be read.
+    /// Malformed lines are skipped.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let mut rs = Self::default();
+        let Ok(src) = std::fs::read_to_string(path) else {
+            return rs;
+        };
+        for line in src.lines() {
+            let nums: Vec<f32> = line
+                .split_whitespace()
+                .filter_map(|s| s.parse().ok())
+                .collect();
+            let [slot, x, y, z, rx, ry, rz] = nums[..] else {
+                continue;
+            };
+            if let Some(entry) = rs.slots.get_mut(slot as usize) {
+                *entry = Some(Bookmark {
+                    pos: Vec3::new(x, y, z),
+                    rot: Vec3::new(rx, ry, rz),
+                });
+            }
+        }
+        rs
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut src = String::new();
+        for (slot, bookmark) in self.slots.iter().enumerate() {
+            let Some(Bookmark { pos, rot }) = bookmark else {
+                continue;
+            };
+            src.push_str(&format!(
+                "{slot} {} {} {} {} {} {}\n",
+                pos.x, pos.y, pos.z, rot.x, rot.y, rot.z
+            ));
+        }
+        std::fs::write(path, src)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::vec3;
+
+    #[test]
+    fn restoring_a_bookmark_returns_to_it() {
+        let mut player = Player::new(vec3(12.5, 40.0, -3.25), 0.1);
+        player.rot = vec3(-20.0, 135.5, 0.0);
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.slots[3] = Some(Bookmark::of(&player));
+
+        // saved to disk and loaded back, like between sessions
+        let path = std::env::temp_dir().join(format!("vrt-bookmarks-{}", std::process::id()));
+        bookmarks.save(&path).unwrap();
+        let loaded = Bookmarks::load(&path);
+        _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.slots, bookmarks.slots);
+
+        let (pos, rot) = (player.pos, player.rot);
+        player.pos = vec3(-100.0, 5.0, 60.0);
+        player.rot = vec3(45.0, -10.0, 0.0);
+        player.vel = vec3(0.0, -1.0, 0.5);
+        loaded.slots[3].unwrap().apply(&mut player);
+        assert_eq!((player.pos, player.rot, player.vel), (pos, rot, Vec3::ZERO));
+    }
+}
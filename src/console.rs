@@ -0,0 +1,250 @@
+use crate::player::GameMode;
+use crate::world::export::ExportFormat;
+use crate::world::gen::{GenSeeds, WorldGen, WorldPreset};
+use crate::world::{PlaceMode, Voxel};
+use crate::GameState;
+use glam::vec3;
+
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    pub input: String,
+    pub output: Vec<String>,
+    pub history: Vec<String>,
+    /// The history entry currently shown in `input`, if browsing history.
+    pub history_idx: Option<usize>,
+}
+impl Console {
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.output.push(line.into());
+    }
+
+    pub fn history_prev(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        let idx = match self.history_idx {
+            Some(idx) => idx.saturating_sub(1),
+            None => self.history.len() - 1,
+        };
+        self.history_idx = Some(idx);
+        self.input = self.history[idx].clone();
+    }
+
+    pub fn history_next(&mut self) {
+        let Some(idx) = self.history_idx else {
+            return;
+        };
+        if idx + 1 < self.history.len() {
+            self.history_idx = Some(idx + 1);
+            self.input = self.history[idx + 1].clone();
+        } else {
+            self.history_idx = None;
+            self.input.clear();
+        }
+    }
+
+    /// Takes the current input line, records it in the history and returns it.
+    pub fn submit(&mut self) -> Option<String> {
+        let line = std::mem::take(&mut self.input).trim().to_owned();
+        self.history_idx = None;
+        if line.is_empty() {
+            return None;
+        }
+        if self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        self.print(format!("> {line}"));
+        Some(line)
+    }
+}
+
+/// Runs a console command against the game state,
+/// returning the text to print or an error message.
+pub fn execute(state: &mut GameState, line: &str) -> Result<String, String> {
+    let mut args = line.split_whitespace();
+    let cmd = args.next().unwrap_or_default();
+    let args: Vec<&str> = args.collect();
+
+    match (cmd, args.as_slice()) {
+        ("help", []) => Ok("commands: help, clear, tp <x> <y> <z>, give <voxel>, \
+            seed [<terrain|biomes|features|caves> <seed>], \
+            gamemode <creative|survival>, export <radius> <path> [crop], \
+            hitboxes <on|off>, preset <normal|flat|void>, sea [<level>], \
+            fill <voxel>, replace <from> <to>, deselect, still <frames|stop>, \
+            save <path>, load <path>, screenshot"
+            .to_owned()),
+        ("seed", []) => {
+            let GenSeeds {
+                terrain,
+                biomes,
+                features,
+                caves,
+            } = state.world_gen.seeds();
+            Ok(format!(
+                "seed: {} (terrain: {terrain}, biomes: {biomes}, features: {features}, caves: {caves})",
+                state.world_gen.seed()
+            ))
+        }
+        ("seed", [category @ ("terrain" | "biomes" | "features" | "caves"), seed]) => {
+            let seed: i64 = seed.parse().map_err(|_| format!("invalid seed {seed:?}"))?;
+            let mut seeds = state.world_gen.seeds();
+            match *category {
+                "terrain" => seeds.terrain = seed,
+                "biomes" => seeds.biomes = seed,
+                "features" => seeds.features = seed,
+                _ => seeds.caves = seed,
+            }
+            let world_gen = WorldGen::new(state.world_gen.seed())
+                .with_preset(state.world_gen.preset().clone())
+                .with_sea_level(state.world_gen.sea_level())
+                .with_seeds(seeds);
+            state.regenerate_world(world_gen);
+            Ok(format!("regenerating world with {category} seed {seed}"))
+        }
+        ("tp", [x, y, z]) => {
+            let parse = |v: &str| {
+                v.parse::<f32>()
+                    .map_err(|_| format!("invalid number {v:?}"))
+            };
+            let pos = vec3(parse(x)?, parse(y)?, parse(z)?);
+            state.player.pos = pos;
+            Ok(format!("teleported to {}/{}/{}", pos.x, pos.y, pos.z))
+        }
+        ("give", [name]) => {
+            let voxel = state
+                .voxels
+                .get(name)
+                .ok_or(format!("unknown voxel {name:?}"))?;
+            // hold it if it's in the inventory, otherwise put it in the selected slot
+            match state.inventory.find(voxel) {
+                Some(idx) => state.inventory.select(idx),
+                None => {
+                    let idx = state.inventory.selected_idx();
+                    state.inventory.set(idx, voxel);
+                }
+            }
+            Ok(format!("holding {}", state.voxels.name(voxel)))
+        }
+        ("gamemode", [name]) => {
+            let mode = GameMode::from_name(name).ok_or(format!("unknown game mode {name:?}"))?;
+            state.player.set_mode(mode);
+            // creative players start out flying, as before
+            state.player.flying = mode.can_fly();
+            Ok(format!("gamemode: {}", mode.name()))
+        }
+        ("export", [radius, path, flags @ ..]) if matches!(flags, [] | ["crop"]) => {
+            let radius: i32 = radius
+                .parse()
+                .map_err(|_| format!("invalid radius {radius:?}"))?;
+            let center = state.player.pos.floor().as_ivec3();
+            let (mut min, mut max) = (center - radius, center + radius);
+            if flags == ["crop"] {
+                (min, max) = state
+                    .world
+                    .crop_region(min, max)
+                    .ok_or("nothing to export, the region is empty")?;
+            }
+            let path = std::path::Path::new(path);
+            let format = ExportFormat::from_path(path);
+            state
+                .world
+                .export_region(min, max, path, format)
+                .map_err(|err| format!("failed to export: {err}"))?;
+            Ok(format!("exported {format:?} to {}", path.display()))
+        }
+        ("save", [path]) => {
+            let path = std::path::Path::new(path);
+            state
+                .save_world(path)
+                .map_err(|err| format!("failed to save: {err}"))?;
+            Ok(format!("saved the world to {}", path.display()))
+        }
+        ("load", [path]) => {
+            let path = std::path::Path::new(path);
+            state
+                .load_world(path)
+                .map_err(|err| format!("failed to load: {err}"))?;
+            Ok(format!("loaded the world from {}", path.display()))
+        }
+        ("hitboxes", [toggle @ ("on" | "off")]) => {
+            state.show_hitboxes = *toggle == "on";
+            Ok(format!("hitboxes: {toggle}"))
+        }
+        ("preset", [name]) => {
+            let preset = WorldPreset::from_name(name).ok_or(format!("unknown preset {name:?}"))?;
+            let world_gen = WorldGen::new(state.world_gen.seed())
+                .with_seeds(state.world_gen.seeds())
+                .with_sea_level(state.world_gen.sea_level())
+                .with_preset(preset);
+            state.regenerate_world(world_gen);
+            Ok(format!("regenerating world with preset {name}"))
+        }
+        ("sea", []) => Ok(format!("sea level: {}", state.world_gen.sea_level())),
+        ("sea", [level]) => {
+            let level: i32 = level
+                .parse()
+                .map_err(|_| format!("invalid sea level {level:?}"))?;
+            let world_gen = WorldGen::new(state.world_gen.seed())
+                .with_seeds(state.world_gen.seeds())
+                .with_preset(state.world_gen.preset().clone())
+                .with_sea_level(level);
+            state.regenerate_world(world_gen);
+            Ok(format!("regenerating world with sea level {level}"))
+        }
+        ("fill", [to]) => {
+            let to = parse_voxel(state, to)?;
+            edit_selection(state, to, PlaceMode::ReplaceAny)
+        }
+        ("replace", [from, to]) => {
+            let from = parse_voxel(state, from)?;
+            let to = parse_voxel(state, to)?;
+            edit_selection(state, to, PlaceMode::ReplaceMatching(from))
+        }
+        ("deselect", []) => {
+            state.selection.clear();
+            Ok("cleared the selection".to_owned())
+        }
+        ("still", ["stop"]) => {
+            state.still = None;
+            Ok("stopped the still".to_owned())
+        }
+        ("still", [frames]) => {
+            let frames: u32 = frames
+                .parse()
+                .map_err(|_| format!("invalid frame count {frames:?}"))?;
+            state.start_still(frames);
+            Ok(format!("rendering a still of {frames} frames"))
+        }
+        ("screenshot", []) => {
+            let path = state.screenshot()?;
+            Ok(format!("saved a screenshot to {}", path.display()))
+        }
+        ("clear", []) => {
+            state.console.output.clear();
+            Ok(String::new())
+        }
+        ("", _) => Ok(String::new()),
+        _ => Err(format!(
+            "unknown command or bad arguments: {line:?} (try \"help\")"
+        )),
+    }
+}
+
+fn parse_voxel(state: &GameState, name: &str) -> Result<Voxel, String> {
+    state
+        .voxels
+        .get(name)
+        .ok_or(format!("unknown voxel {name:?}"))
+}
+
+fn edit_selection(state: &mut GameState, voxel: Voxel, mode: PlaceMode) -> Result<String, String> {
+    let Some((min, max)) = state.selection.region() else {
+        return Err("select a region first, by pressing B at two corners".to_owned());
+    };
+    state
+        .fill_selection(voxel, mode)
+        .map_err(|err| format!("failed to edit the selection ({err:?}), rolled back"))?;
+    let size = max - min + 1;
+    Ok(format!("edited {}x{}x{} voxels", size.x, size.y, size.z))
+}
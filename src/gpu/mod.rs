@@ -2,7 +2,8 @@ pub mod egui;
 pub mod texture;
 
 use crate::world::{data::Material, ChunkHeader, Node, World};
-use glam::{uvec2, Mat4, UVec2, Vec2, Vec3};
+use glam::{uvec2, Mat4, UVec2, UVec3, Vec2, Vec3};
+use std::time::{Duration, Instant};
 use texture::Texture;
 
 use wgpu::*;
@@ -11,7 +12,10 @@ static RAY_TRACER_SRC: &str = include_str!("ray_tracer.wgsl");
 static PATH_TRACER_SRC: &str = include_str!("path_tracer.wgsl");
 static SCREEN_SHADER_SRC: &str = include_str!("screen_shader.wgsl");
 
-const RESULT_TEX_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+/// A float format, so bright colors survive until the screen shader tone maps them.
+const RESULT_TEX_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// Stores the primary hit's normal in RGB and its distance from the camera in A (-1 for sky).
+const GBUFFER_TEX_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
 const RESULT_TEX_USAGES: TextureUsages = TextureUsages::COPY_DST
     .union(TextureUsages::COPY_SRC)
     .union(TextureUsages::STORAGE_BINDING)
@@ -57,15 +61,137 @@ impl<T> ArrayBuffer<T> {
         Self(handle, size, std::marker::PhantomData)
     }
 
+    /// The number of items the buffer can hold.
+    #[inline(always)]
+    pub fn len(&self) -> u32 {
+        self.1
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.1 == 0
+    }
+
+    /// Grows the buffer to hold at least `new_len` items, keeping its contents.
+    /// Returns true if the buffer was reallocated, in which case any bind groups
+    /// referencing it need to be recreated.
+    pub fn ensure_capacity(&mut self, gpu: &Gpu, new_len: u32) -> bool {
+        self.grow(&gpu.device, &gpu.queue, new_len)
+    }
+
+    fn grow(&mut self, device: &Device, queue: &Queue, new_len: u32) -> bool {
+        if new_len <= self.1 {
+            return false;
+        }
+        let item_size = std::mem::size_of::<T>() as u64;
+        let handle = device.create_buffer(&BufferDescriptor {
+            label: Some("array_buffer"),
+            size: new_len as u64 * item_size,
+            usage: self.0.usage(),
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&self.0, 0, &handle, 0, self.1 as u64 * item_size);
+        queue.submit([encoder.finish()]);
+
+        self.0 = handle;
+        self.1 = new_len;
+        true
+    }
+
     pub fn write(&self, gpu: &Gpu, offset: u64, items: &[T]) {
-        let items_cut = (items.len() as u64).min(self.1 as u64 - offset);
+        self.write_queued(&gpu.queue, offset, items)
+    }
+
+    fn write_queued(&self, queue: &Queue, offset: u64, items: &[T]) {
+        if offset + items.len() as u64 > self.1 as u64 {
+            log::warn!(
+                "ArrayBuffer write of {} items at {offset} exceeds its capacity of {}; truncating",
+                items.len(),
+                self.1,
+            );
+        }
+        let items_cut = (items.len() as u64).min((self.1 as u64).saturating_sub(offset));
         let items: &[T] = &items[0..items_cut as usize];
 
         let ptr = items.as_ptr() as *const u8;
         let size = items.len() * std::mem::size_of::<T>();
         let slice = unsafe { std::slice::from_raw_parts(ptr, size) };
         let offset = offset * std::mem::size_of::<T>() as u64;
-        gpu.queue.write_buffer(&self.0, offset, slice);
+        queue.write_buffer(&self.0, offset, slice);
+    }
+
+    /// Writes several `(offset, items)` regions at once, through a single staging buffer
+    /// and one submission, instead of a `write` per region. Regions that continue right
+    /// where the previous one ended are copied together. Later regions win where they
+    /// overlap earlier ones, and regions past the end are truncated like in `write`.
+    pub fn write_many(&self, gpu: &Gpu, regions: &[(u64, &[T])]) {
+        let item_size = std::mem::size_of::<T>() as u64;
+        if !item_size.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT) {
+            // buffer copies need aligned offsets and sizes
+            for (offset, items) in regions {
+                self.write(gpu, *offset, items);
+            }
+            return;
+        }
+        let regions: Vec<(u64, &[T])> = regions
+            .iter()
+            .filter_map(|(offset, items)| {
+                let items_cut = (items.len() as u64).min((self.1 as u64).saturating_sub(*offset));
+                if items_cut < items.len() as u64 {
+                    log::warn!(
+                        "ArrayBuffer write of {} items at {offset} exceeds its capacity of {}; truncating",
+                        items.len(),
+                        self.1,
+                    );
+                }
+                (items_cut > 0).then(|| (*offset, &items[..items_cut as usize]))
+            })
+            .collect();
+        let total_items: u64 = regions.iter().map(|(_, items)| items.len() as u64).sum();
+        if total_items == 0 {
+            return;
+        }
+
+        let staging = gpu.device.create_buffer(&BufferDescriptor {
+            label: Some("array_buffer_staging"),
+            size: total_items * item_size,
+            usage: BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        // (staging offset, destination offset, item count) of each copy
+        let mut copies: Vec<(u64, u64, u64)> = vec![];
+        {
+            let mut mapped = staging.slice(..).get_mapped_range_mut();
+            let mut staged = 0;
+            for (offset, items) in regions {
+                let ptr = items.as_ptr() as *const u8;
+                let size = items.len() * item_size as usize;
+                let bytes = unsafe { std::slice::from_raw_parts(ptr, size) };
+                let start = (staged * item_size) as usize;
+                mapped[start..start + size].copy_from_slice(bytes);
+
+                let len = items.len() as u64;
+                match copies.last_mut() {
+                    Some((_, dst, count)) if *dst + *count == offset => *count += len,
+                    _ => copies.push((staged, offset, len)),
+                }
+                staged += len;
+            }
+        }
+        staging.unmap();
+
+        let mut encoder = gpu.device.create_command_encoder(&Default::default());
+        for (src, dst, count) in copies {
+            encoder.copy_buffer_to_buffer(
+                &staging,
+                src * item_size,
+                &self.0,
+                dst * item_size,
+                count * item_size,
+            );
+        }
+        gpu.queue.submit([encoder.finish()]);
     }
 }
 
@@ -107,10 +233,17 @@ pub struct ScreenShader {
     pub pipeline: RenderPipeline,
     pub bind_group_layout: BindGroupLayout,
     pub bind_group: BindGroup,
+    pub settings: SimpleBuffer<ScreenSettings>,
 }
 impl ScreenShader {
     pub fn new(gpu: &Gpu, tex: &Texture, surface_format: TextureFormat) -> Self {
         let device = &gpu.device;
+        let settings = SimpleBuffer::new(
+            gpu,
+            "screen_settings",
+            BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+        );
+        settings.write(gpu, &ScreenSettings::default());
         let shader_module = device.create_shader_module(ShaderModuleDescriptor {
             label: Some("screen-shader.shader-module"),
             source: ShaderSource::Wgsl(SCREEN_SHADER_SRC.into()),
@@ -125,9 +258,10 @@ impl ScreenShader {
                     multisampled: false,
                 },
                 1 => (FRAGMENT) BindingType::Sampler(SamplerBindingType::Filtering),
+                2 => (FRAGMENT) uniform_binding_type(),
             ),
         });
-        let bind_group = Self::create_bind_group(gpu, &bind_group_layout, tex);
+        let bind_group = Self::create_bind_group(gpu, &bind_group_layout, tex, &settings);
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("screen-shader.pipeline-layout"),
@@ -161,22 +295,30 @@ impl ScreenShader {
             pipeline,
             bind_group,
             bind_group_layout,
+            settings,
         }
     }
 
-    pub fn create_bind_group(gpu: &Gpu, layout: &BindGroupLayout, tex: &Texture) -> BindGroup {
+    pub fn create_bind_group(
+        gpu: &Gpu,
+        layout: &BindGroupLayout,
+        tex: &Texture,
+        settings: &SimpleBuffer<ScreenSettings>,
+    ) -> BindGroup {
         gpu.device.create_bind_group(&BindGroupDescriptor {
             label: Some("screen-shader.bind_group"),
             layout,
             entries: &bind_group_entries!(
                 0 => BindingResource::TextureView(&tex.view),
                 1 => BindingResource::Sampler(&tex.sampler),
+                2 => settings.0.as_entire_binding(),
             ),
         })
     }
 
     pub fn recreate_bind_group(&mut self, gpu: &Gpu, tex: &Texture) {
-        self.bind_group = Self::create_bind_group(gpu, &self.bind_group_layout, tex);
+        self.bind_group =
+            Self::create_bind_group(gpu, &self.bind_group_layout, tex, &self.settings);
     }
 
     pub fn encode_pass(&self, encoder: &mut CommandEncoder, view: &TextureView) {
@@ -202,9 +344,19 @@ pub struct PixelShader {
     pub pipeline: ComputePipeline,
     pub bind_group_layout: BindGroupLayout,
     pub bind_group: BindGroup,
+    /// Like `bind_group`, but with `Buffers::split_settings` and `Buffers::split_viewport`,
+    /// for the right half of the screen in split screen mode.
+    pub split_bind_group: BindGroup,
 }
 impl PixelShader {
-    pub fn new(src: &str, gpu: &Gpu, tex: &Texture, prev_tex: &Texture, buffers: &Buffers) -> Self {
+    pub fn new(
+        src: &str,
+        gpu: &Gpu,
+        tex: &Texture,
+        prev_tex: &Texture,
+        gbuffer_tex: &Texture,
+        buffers: &Buffers,
+    ) -> Self {
         let device = &gpu.device;
         let shader_module = device.create_shader_module(ShaderModuleDescriptor {
             label: Some("#pixel-shader.shader-module"),
@@ -229,10 +381,33 @@ impl PixelShader {
                     sample_type: TextureSampleType::default(),
                     view_dimension: TextureViewDimension::D2,
                     multisampled: false,
-                }
+                },
+                9 => (COMPUTE) BindingType::StorageTexture {
+                    access: StorageTextureAccess::WriteOnly,
+                    format: GBUFFER_TEX_FORMAT,
+                    view_dimension: TextureViewDimension::D2,
+                },
+                10 => (COMPUTE) uniform_binding_type(),
             ),
         });
-        let bind_group = Self::create_bind_group(gpu, &bind_group_layout, tex, prev_tex, buffers);
+        let bind_group = Self::create_bind_group(
+            gpu,
+            &bind_group_layout,
+            tex,
+            prev_tex,
+            gbuffer_tex,
+            buffers,
+            false,
+        );
+        let split_bind_group = Self::create_bind_group(
+            gpu,
+            &bind_group_layout,
+            tex,
+            prev_tex,
+            gbuffer_tex,
+            buffers,
+            true,
+        );
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("#pixel-shader.pipeline-layout"),
@@ -249,30 +424,40 @@ impl PixelShader {
         Self {
             pipeline,
             bind_group,
+            split_bind_group,
             bind_group_layout,
         }
     }
 
+    /// With `split`, binds the settings and viewport of the right half of a split screen.
     pub fn create_bind_group(
         gpu: &Gpu,
         layout: &BindGroupLayout,
         output_tex: &Texture,
         prev_output_tex: &Texture,
+        gbuffer_tex: &Texture,
         buffers: &Buffers,
+        split: bool,
     ) -> BindGroup {
+        let (settings, viewport) = match split {
+            false => (&buffers.settings, &buffers.viewport),
+            true => (&buffers.split_settings, &buffers.split_viewport),
+        };
         gpu.device.create_bind_group(&BindGroupDescriptor {
             label: Some("#raytracer.bind-broup"),
             layout,
             entries: &bind_group_entries!(
                 0 => BindingResource::TextureView(&output_tex.view),
                 1 => buffers.cam_data.0.as_entire_binding(),
-                2 => buffers.settings.0.as_entire_binding(),
+                2 => settings.0.as_entire_binding(),
                 3 => buffers.voxel_materials.0.as_entire_binding(),
                 4 => buffers.frame_count.0.as_entire_binding(),
                 5 => buffers.world_data.0.as_entire_binding(),
                 6 => buffers.nodes.0.as_entire_binding(),
                 7 => buffers.chunks.0.as_entire_binding(),
                 8 => BindingResource::TextureView(&prev_output_tex.view),
+                9 => BindingResource::TextureView(&gbuffer_tex.view),
+                10 => viewport.0.as_entire_binding(),
             ),
         })
     }
@@ -282,18 +467,42 @@ impl PixelShader {
         gpu: &Gpu,
         tex: &Texture,
         prev_tex: &Texture,
+        gbuffer_tex: &Texture,
         buffers: &Buffers,
     ) {
-        self.bind_group =
-            Self::create_bind_group(gpu, &self.bind_group_layout, tex, prev_tex, buffers);
+        self.bind_group = Self::create_bind_group(
+            gpu,
+            &self.bind_group_layout,
+            tex,
+            prev_tex,
+            gbuffer_tex,
+            buffers,
+            false,
+        );
+        self.split_bind_group = Self::create_bind_group(
+            gpu,
+            &self.bind_group_layout,
+            tex,
+            prev_tex,
+            gbuffer_tex,
+            buffers,
+            true,
+        );
     }
 
-    pub fn encode_pass(&self, encoder: &mut CommandEncoder, workgroups: UVec2) {
+    /// Renders `viewport`, which has to be the one in the viewport buffer `bind_group` binds.
+    pub fn encode_pass(
+        &self,
+        encoder: &mut CommandEncoder,
+        bind_group: &BindGroup,
+        viewport: Viewport,
+    ) {
         let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
             label: Some("#raytracer-pass"),
         });
+        let workgroups = viewport.workgroups();
         pass.set_pipeline(&self.pipeline);
-        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.set_bind_group(0, bind_group, &[]);
         pass.dispatch_workgroups(workgroups.x, workgroups.y, 1);
     }
 }
@@ -301,6 +510,12 @@ impl PixelShader {
 pub struct Buffers {
     pub cam_data: SimpleBuffer<CamData>,
     pub settings: SimpleBuffer<Settings>,
+    /// The settings of the right half of the screen in split screen mode.
+    pub split_settings: SimpleBuffer<Settings>,
+    /// The part of the result rendered with `settings`.
+    pub viewport: SimpleBuffer<Viewport>,
+    /// The part of the result rendered with `split_settings`.
+    pub split_viewport: SimpleBuffer<Viewport>,
     pub world_data: SimpleBuffer<WorldData>,
     pub nodes: ArrayBuffer<Node>,
     pub voxel_materials: SimpleBuffer<[Material; 256]>,
@@ -308,17 +523,21 @@ pub struct Buffers {
     pub chunks: ArrayBuffer<ChunkHeader>,
 }
 impl Buffers {
-    pub fn new(gpu: &Gpu, max_nodes: u32, world_size: u32) -> Self {
+    pub fn new(gpu: &Gpu, max_nodes: u32, world_size: UVec3) -> Self {
         const COPY_DST: BufferUsages = BufferUsages::COPY_DST;
+        const COPY_SRC: BufferUsages = BufferUsages::COPY_SRC;
         const UNIFORM: BufferUsages = BufferUsages::UNIFORM;
         const STORAGE: BufferUsages = BufferUsages::STORAGE;
-        let chunk_count = world_size * world_size * world_size;
+        let chunk_count = world_size.x * world_size.y * world_size.z;
 
         Self {
             cam_data: SimpleBuffer::new(gpu, "cam_data", COPY_DST | UNIFORM),
             settings: SimpleBuffer::new(gpu, "settings", COPY_DST | UNIFORM),
+            split_settings: SimpleBuffer::new(gpu, "split_settings", COPY_DST | UNIFORM),
+            viewport: SimpleBuffer::new(gpu, "viewport", COPY_DST | UNIFORM),
+            split_viewport: SimpleBuffer::new(gpu, "split_viewport", COPY_DST | UNIFORM),
             world_data: SimpleBuffer::new(gpu, "world_data", COPY_DST | UNIFORM),
-            nodes: ArrayBuffer::new(gpu, "nodes", COPY_DST | STORAGE, max_nodes),
+            nodes: ArrayBuffer::new(gpu, "nodes", COPY_DST | COPY_SRC | STORAGE, max_nodes),
             voxel_materials: SimpleBuffer::new(gpu, "voxel_mats", COPY_DST | STORAGE),
             frame_count: SimpleBuffer::new(gpu, "frame_count", COPY_DST | UNIFORM),
             chunks: ArrayBuffer::new(gpu, "chunks", COPY_DST | STORAGE, chunk_count),
@@ -330,28 +549,170 @@ impl Buffers {
 #[repr(C)]
 pub struct CamData {
     pub pos: Vec3,
-    pub _padding0: u32,
+    /// Whether the path tracer should accumulate onto its reprojected previous
+    /// frame, because the camera only turned, as a bool.
+    pub reproject: u32,
     pub inv_view_mat: Mat4,
     pub inv_proj_mat: Mat4,
     pub proj_size: Vec2,
     pub _padding1: [u32; 2],
+    // The camera of the previous frame, used to reproject history for TAA.
+    pub prev_pos: Vec3,
+    pub _padding2: u32,
+    pub prev_inv_view_mat: Mat4,
+    pub prev_inv_proj_mat: Mat4,
+}
+impl CamData {
+    pub fn with_prev(mut self, prev: &CamData) -> Self {
+        self.prev_pos = prev.pos;
+        self.prev_inv_view_mat = prev.inv_view_mat;
+        self.prev_inv_proj_mat = prev.inv_proj_mat;
+        self
+    }
+}
+
+/// A rectangle of the result texture, in texels, that one tracer dispatch renders.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Viewport {
+    pub offset: UVec2,
+    pub size: UVec2,
+}
+impl Viewport {
+    pub fn full(size: UVec2) -> Self {
+        Self {
+            offset: UVec2::ZERO,
+            size,
+        }
+    }
+
+    /// The left and right halves of a texture of `size`, for split screen.
+    pub fn halves(size: UVec2) -> [Self; 2] {
+        let left = uvec2(size.x / 2, size.y);
+        [
+            Self::full(left),
+            Self {
+                offset: uvec2(left.x, 0),
+                size: uvec2(size.x - left.x, size.y),
+            },
+        ]
+    }
+
+    /// The 8x8 workgroups covering the viewport.
+    pub fn workgroups(&self) -> UVec2 {
+        (self.size + 7) / 8
+    }
 }
 
 #[derive(Clone, Copy, Default)]
 #[repr(C)]
 pub struct WorldData {
     pub min: [i32; 3],
-    pub size: u32,
-    pub size_in_chunks: u32,
-    _padding: [u32; 3],
+    _padding0: u32,
+    pub size: [u32; 3],
+    _padding1: u32,
+    pub size_in_chunks: [u32; 3],
+    _padding2: u32,
 }
 impl WorldData {
     pub fn from(world: &World) -> Self {
         Self {
             min: world.min().into(),
-            size: world.size(),
-            size_in_chunks: world.size_in_chunks(),
-            _padding: Default::default(),
+            size: world.size3().into(),
+            size_in_chunks: world.size_in_chunks3().into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Replaces the shaded output with a visualization of some render data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum DebugMode {
+    #[default]
+    Off = 0,
+    Normals = 1,
+    StepCount = 2,
+    Depth = 3,
+    Accumulation = 4,
+}
+impl DebugMode {
+    /// The order the debug modes are cycled through.
+    pub const ALL: &'static [Self] = &[
+        Self::Off,
+        Self::Normals,
+        Self::StepCount,
+        Self::Depth,
+        Self::Accumulation,
+    ];
+
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|m| *m == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Normals => "normals",
+            Self::StepCount => "step count",
+            Self::Depth => "depth",
+            Self::Accumulation => "accumulation",
+        }
+    }
+}
+
+/// How the screen shader maps the rendered colors onto the 0..1 range of the screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ToneMap {
+    /// Clips everything above 1.
+    #[default]
+    None = 0,
+    Reinhard = 1,
+    /// The fitted ACES filmic curve.
+    Aces = 2,
+}
+impl ToneMap {
+    /// The order the operators are cycled through.
+    pub const ALL: &'static [Self] = &[Self::None, Self::Reinhard, Self::Aces];
+
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|m| *m == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Reinhard => "reinhard",
+            Self::Aces => "ACES",
+        }
+    }
+}
+
+/// The settings of `ScreenShader`, applied when the result texture is drawn to the screen.
+/// Changing them doesn't invalidate accumulated samples.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct ScreenSettings {
+    /// A `ToneMap`, as a u32.
+    pub tone_map: u32,
+    /// In stops; colors are scaled by `2^exposure` before tone mapping.
+    pub exposure: f32,
+    /// Colors are raised to `1 / gamma` after tone mapping.
+    pub gamma: f32,
+    /// The result texture holds this many samples per pixel along each axis,
+    /// which are averaged together. Mirrors `Settings::supersample`.
+    pub supersample: u32,
+}
+impl Default for ScreenSettings {
+    fn default() -> Self {
+        Self {
+            tone_map: ToneMap::None as u32,
+            exposure: 0.0,
+            gamma: 1.0,
+            supersample: 1,
         }
     }
 }
@@ -361,23 +722,73 @@ impl WorldData {
 pub struct Settings {
     pub max_ray_bounces: u32,
     pub sun_intensity: f32,
-    pub show_step_count: u32,
+    /// A `DebugMode`, as a u32.
+    pub debug_mode: u32,
     _padding0: u32,
     pub sky_color: [f32; 3],
     pub _padding1: u32,
     pub sun_pos: [f32; 3],
     pub samples_per_pixel: u32,
+    pub cloud_coverage: f32,
+    pub cloud_altitude: f32,
+    /// How much of the reprojected previous frame to keep when the camera moves.
+    /// 0 disables temporal anti-aliasing.
+    pub taa_blend: f32,
+    /// The angular radius of the sun in radians. Shadow rays are jittered
+    /// within it for soft penumbras; 0 gives hard shadows.
+    pub sun_softness: f32,
+    /// Whether the ray tracer fills `GpuResources::gbuffer_texture`, as a bool.
+    pub write_gbuffer: u32,
+    /// How far (in voxels) the ray tracer searches for emissive voxels to light
+    /// each hit with. 0 disables local lights.
+    pub local_light_radius: u32,
+    /// Whether to render the right half of the screen with `Buffers::split_settings`,
+    /// to compare them side by side, as a bool.
+    pub split_screen: u32,
+    /// How many short rays the ray tracer casts around each hit to darken
+    /// creases and corners. 0 disables ambient occlusion.
+    pub ao_samples: u32,
+    /// The color of rays that leave the world heading down, below the horizon.
+    pub void_color: [f32; 3],
+    /// How far (in voxels) ambient occlusion rays look for occluders.
+    pub ao_radius: f32,
+    /// How many bounces a path tracer ray always makes before it may be
+    /// terminated early by Russian roulette, based on how much light it still carries.
+    pub rr_min_bounces: u32,
+    /// Whether the path tracer samples emissive voxels within `local_light_radius`
+    /// of each bounce directly, as a bool.
+    pub emissive_lighting: u32,
+    /// How many times the result resolution the tracers render at along each axis.
+    /// The screen shader averages the samples back down. Set through `GpuResources::set_supersample`.
+    pub supersample: u32,
+    pub _padding2: u32,
+}
+
+/// How a path tracer frame of `samples` samples per pixel is split into dispatches of at
+/// most `max_per_dispatch` samples, as `(dispatches, samples per dispatch)`.
+/// Each dispatch takes the same number of samples, so that accumulating them weighs
+/// them equally, which can round the total up by a few samples.
+pub fn split_samples(samples: u32, max_per_dispatch: u32) -> (u32, u32) {
+    let samples = samples.max(1);
+    let dispatches = samples.div_ceil(max_per_dispatch.max(1));
+    (dispatches, samples.div_ceil(dispatches))
 }
 
 pub struct GpuResources {
     pub prev_result_texture: Texture,
     pub result_texture: Texture,
+    /// The ray tracer's primary hit normals and depths, for post-processing and debugging.
+    pub gbuffer_texture: Texture,
     pub voxel_texture_atlas: Texture,
     pub buffers: Buffers,
 
     pub screen_shader: ScreenShader,
     pub ray_tracer: PixelShader,
     pub path_tracer: PixelShader,
+
+    /// The size of the result, before supersampling.
+    result_size: UVec2,
+    supersample: u32,
 }
 impl GpuResources {
     pub fn new(
@@ -385,7 +796,7 @@ impl GpuResources {
         surface_format: TextureFormat,
         result_size: UVec2,
         max_nodes: u32,
-        world_size: u32,
+        world_size: UVec3,
     ) -> Self {
         let buffers = Buffers::new(gpu, max_nodes, world_size);
 
@@ -401,6 +812,12 @@ impl GpuResources {
             RESULT_TEX_FORMAT,
             RESULT_TEX_USAGES,
         );
+        let gbuffer_texture = Texture::new(
+            &gpu.device,
+            result_size,
+            GBUFFER_TEX_FORMAT,
+            RESULT_TEX_USAGES,
+        );
         let voxel_texture_atlas = Texture::new(
             &gpu.device,
             uvec2(5, 5),
@@ -414,6 +831,7 @@ impl GpuResources {
             gpu,
             &result_texture,
             &prev_result_texture,
+            &gbuffer_texture,
             &buffers,
         );
         let path_tracer = PixelShader::new(
@@ -421,26 +839,155 @@ impl GpuResources {
             gpu,
             &result_texture,
             &prev_result_texture,
+            &gbuffer_texture,
             &buffers,
         );
 
         Self {
             result_texture,
             prev_result_texture,
+            gbuffer_texture,
             voxel_texture_atlas,
             buffers,
             screen_shader,
             ray_tracer,
             path_tracer,
+            result_size,
+            supersample: 1,
         }
     }
 
+    /// The size of the result, before supersampling.
+    /// The result textures are `supersample` times larger along each axis.
+    pub fn result_size(&self) -> UVec2 {
+        self.result_size
+    }
+
+    /// Recreates the result textures for a result of `new_size`, before supersampling,
+    /// clearing them.
     pub fn resize_result_texture(&mut self, gpu: &Gpu, new_size: UVec2) {
+        // a texture can't be created with a zero size (e.g. while minimized)
+        if new_size.x == 0 || new_size.y == 0 {
+            return;
+        }
+        self.result_size = new_size;
+        let new_size = new_size * self.supersample;
         self.result_texture =
             Texture::new(&gpu.device, new_size, RESULT_TEX_FORMAT, RESULT_TEX_USAGES);
         self.prev_result_texture =
             Texture::new(&gpu.device, new_size, RESULT_TEX_FORMAT, RESULT_TEX_USAGES);
+        self.gbuffer_texture =
+            Texture::new(&gpu.device, new_size, GBUFFER_TEX_FORMAT, RESULT_TEX_USAGES);
+        self.recreate_bind_groups(gpu);
+    }
+
+    /// Renders `factor` times as many samples along each axis of the result,
+    /// recreating the result textures. `ScreenSettings::supersample` has to match.
+    pub fn set_supersample(&mut self, gpu: &Gpu, factor: u32) {
+        self.supersample = factor.max(1);
+        self.resize_result_texture(gpu, self.result_size);
+    }
+
+    /// Encodes `shader` over the whole result, or with `split`, over its left half
+    /// with `Buffers::settings` and its right half with `Buffers::split_settings`.
+    pub fn encode_tracer(
+        &self,
+        gpu: &Gpu,
+        encoder: &mut CommandEncoder,
+        shader: &PixelShader,
+        split: bool,
+    ) {
+        let size = self.result_texture.size();
+        if !split {
+            let viewport = Viewport::full(size);
+            self.buffers.viewport.write(gpu, &viewport);
+            shader.encode_pass(encoder, &shader.bind_group, viewport);
+            return;
+        }
+        let [left, right] = Viewport::halves(size);
+        self.buffers.viewport.write(gpu, &left);
+        self.buffers.split_viewport.write(gpu, &right);
+        shader.encode_pass(encoder, &shader.bind_group, left);
+        shader.encode_pass(encoder, &shader.split_bind_group, right);
+    }
+
+    /// Runs the path tracer `dispatches` times, each taking `settings.samples_per_pixel`
+    /// samples per pixel and accumulating them onto the result like another frame would,
+    /// with its own `frame_count` so each draws different samples.
+    /// With `split_settings`, the right half of the screen is rendered with those instead.
+    /// With more than one, each dispatch is submitted and waited on alone, and once `budget`
+    /// has passed the rest are dropped, so a high sample count can't stall the GPU.
+    /// At least one dispatch always runs. Returns how many ran.
+    #[allow(clippy::too_many_arguments)]
+    pub fn dispatch_path_tracer(
+        &self,
+        gpu: &Gpu,
+        settings: &Settings,
+        split_settings: Option<&Settings>,
+        cam_data: CamData,
+        frame_count: &mut u32,
+        dispatches: u32,
+        budget: Duration,
+    ) -> u32 {
+        let start = Instant::now();
+        self.buffers.settings.write(gpu, settings);
+        if let Some(split_settings) = split_settings {
+            self.buffers.split_settings.write(gpu, split_settings);
+        }
+        self.buffers.cam_data.write(gpu, &cam_data);
+
+        let mut ran = 0;
+        while ran < dispatches.max(1) {
+            if ran == 1 && cam_data.reproject != 0 {
+                // the first dispatch already moved the samples to where the camera turned
+                let cam_data = CamData {
+                    reproject: 0,
+                    ..cam_data
+                };
+                self.buffers.cam_data.write(gpu, &cam_data);
+            }
+            self.buffers.frame_count.write(gpu, frame_count);
+            let mut encoder = gpu.create_command_encoder();
+            self.encode_tracer(
+                gpu,
+                &mut encoder,
+                &self.path_tracer,
+                split_settings.is_some(),
+            );
+            // the next dispatch accumulates onto this one
+            self.encode_copy_to_prev(&mut encoder);
+            gpu.queue.submit([encoder.finish()]);
+            *frame_count += 1;
+            ran += 1;
 
+            if dispatches > 1 {
+                gpu.device.poll(Maintain::Wait);
+                if start.elapsed() >= budget {
+                    break;
+                }
+            }
+        }
+        ran
+    }
+
+    /// Copies the result texture to `prev_result_texture`, which the path tracer
+    /// accumulates onto and reprojects.
+    fn encode_copy_to_prev(&self, encoder: &mut CommandEncoder) {
+        encoder.copy_texture_to_texture(
+            self.result_texture.handle.as_image_copy(),
+            self.prev_result_texture.handle.as_image_copy(),
+            self.result_texture.handle.size(),
+        );
+    }
+
+    /// Grows the node buffer to hold at least `len` nodes if it can't already.
+    pub fn ensure_node_capacity(&mut self, gpu: &Gpu, len: u32) {
+        if self.buffers.nodes.ensure_capacity(gpu, len) {
+            self.recreate_bind_groups(gpu);
+        }
+    }
+
+    fn recreate_bind_groups(&mut self, gpu: &Gpu) {
         self.screen_shader
             .recreate_bind_group(gpu, &self.result_texture);
 
@@ -448,12 +995,14 @@ impl GpuResources {
             gpu,
             &self.result_texture,
             &self.prev_result_texture,
+            &self.gbuffer_texture,
             &self.buffers,
         );
         self.path_tracer.recreate_bind_group(
             gpu,
             &self.result_texture,
             &self.prev_result_texture,
+            &self.gbuffer_texture,
             &self.buffers,
         );
     }
@@ -462,8 +1011,10 @@ impl GpuResources {
 pub struct Gpu {
     pub device: Device,
     pub queue: Queue,
-    pub surface: Surface,
+    /// The window's surface. `None` when rendering offscreen, without a window.
+    pub surface: Option<Surface>,
     pub surface_config: SurfaceConfiguration,
+    pub present_modes: Vec<PresentMode>,
 }
 impl Gpu {
     pub async fn new(window: &winit::window::Window) -> Self {
@@ -508,19 +1059,72 @@ impl Gpu {
             .get_default_config(&adapter, size.x, size.y)
             .unwrap();
         surface.configure(&device, &surface_config);
+        let present_modes = surface.get_capabilities(&adapter).present_modes;
 
         Self {
-            surface,
+            surface: Some(surface),
             device,
             surface_config,
             queue,
+            present_modes,
+        }
+    }
+
+    /// A `Gpu` on any adapter, like a software renderer, without a window.
+    /// It renders offscreen, to a pretend 64x64 surface.
+    #[cfg(test)]
+    pub fn headless() -> Option<Self> {
+        let instance = Instance::new(Default::default());
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default()))?;
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&Default::default(), None)).ok()?;
+        Some(Self {
+            device,
+            queue,
+            surface: None,
+            surface_config: SurfaceConfiguration {
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                format: TextureFormat::Rgba8Unorm,
+                width: 64,
+                height: 64,
+                present_mode: PresentMode::Fifo,
+                alpha_mode: CompositeAlphaMode::Auto,
+                view_formats: vec![],
+            },
+            present_modes: vec![],
+        })
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.surface_config.present_mode == PresentMode::Fifo
+    }
+
+    /// Reconfigures the surface to wait for vertical blanks or not.
+    /// Without vsync, `Mailbox` is preferred over `Immediate` to avoid tearing.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        let present_mode = match vsync {
+            true => PresentMode::Fifo,
+            false => [PresentMode::Mailbox, PresentMode::Immediate]
+                .into_iter()
+                .find(|mode| self.present_modes.contains(mode))
+                .unwrap_or(PresentMode::Fifo),
+        };
+        self.surface_config.present_mode = present_mode;
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.surface_config);
         }
     }
 
     pub fn resize(&mut self, new_size: UVec2) {
+        // a surface can't be configured with a zero size (e.g. while minimized)
+        if new_size.x == 0 || new_size.y == 0 {
+            return;
+        }
         self.surface_config.width = new_size.x;
         self.surface_config.height = new_size.y;
-        self.surface.configure(&self.device, &self.surface_config);
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.surface_config);
+        }
     }
 
     pub fn create_command_encoder(&self) -> CommandEncoder {
@@ -531,9 +1135,423 @@ impl Gpu {
         uvec2(self.surface_config.width, self.surface_config.height)
     }
 
+    /// Gets the texture to draw the next frame to. If the surface was lost or is out of date
+    /// (e.g. after the system slept), it's reconfigured and asked once more.
     pub fn get_output(&self) -> Result<(SurfaceTexture, TextureView), SurfaceError> {
-        let output = self.surface.get_current_texture()?;
+        let Some(surface) = &self.surface else {
+            return Err(SurfaceError::Lost);
+        };
+        let output = retry_lost_surface(
+            || surface.get_current_texture(),
+            || surface.configure(&self.device, &self.surface_config),
+        )?;
         let view = output.texture.create_view(&Default::default());
         Ok((output, view))
     }
 }
+
+/// Calls `acquire`, and if the surface was lost or is out of date, calls `reconfigure`
+/// and `acquire` once more. Other errors are returned as they are.
+pub fn retry_lost_surface<T>(
+    mut acquire: impl FnMut() -> Result<T, SurfaceError>,
+    reconfigure: impl FnOnce(),
+) -> Result<T, SurfaceError> {
+    match acquire() {
+        Err(SurfaceError::Lost | SurfaceError::Outdated) => {
+            log::warn!("surface lost or outdated, reconfiguring");
+            reconfigure();
+            acquire()
+        }
+        output => output,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::Player;
+    use crate::world::{data::VoxelRegistry, Voxel, NODES_PER_CHUNK};
+    use glam::{ivec3, vec2, vec3, vec4, IVec3, Vec4};
+    use half::f16;
+
+    /// Resources for rendering `world` offscreen at `size`, with the world,
+    /// `settings` and the camera of `player` uploaded.
+    fn scene(
+        gpu: &Gpu,
+        world: &World,
+        settings: &Settings,
+        player: &Player,
+        size: UVec2,
+    ) -> GpuResources {
+        let res = GpuResources::new(
+            gpu,
+            gpu.surface_config.format,
+            size,
+            world.nodes().len() as u32,
+            world.size_in_chunks3(),
+        );
+        res.buffers.nodes.write(gpu, 0, world.nodes());
+        res.buffers.chunks.write(gpu, 0, &world.chunks);
+        res.buffers.world_data.write(gpu, &WorldData::from(world));
+        let voxels = VoxelRegistry::default();
+        res.buffers
+            .voxel_materials
+            .write_slice(gpu, 0, voxels.materials());
+        res.buffers.settings.write(gpu, settings);
+        res.buffers
+            .cam_data
+            .write(gpu, &player.create_cam_data(size.as_vec2(), world));
+        res.buffers.frame_count.write(gpu, &0);
+        res
+    }
+
+    /// The texels of a `Rgba16Float` texture, row by row.
+    fn read_texels(gpu: &Gpu, tex: &Texture) -> Vec<Vec4> {
+        (tex.read_to_cpu(gpu).chunks_exact(8))
+            .map(|texel| {
+                let channel = |i: usize| f16::from_le_bytes([texel[i], texel[i + 1]]).to_f32();
+                vec4(channel(0), channel(2), channel(4), channel(6))
+            })
+            .collect()
+    }
+
+    /// Runs `shader` once over the result, and waits for it.
+    fn dispatch(gpu: &Gpu, res: &GpuResources, shader: &PixelShader, split: bool) {
+        let mut encoder = gpu.create_command_encoder();
+        res.encode_tracer(gpu, &mut encoder, shader, split);
+        gpu.queue.submit([encoder.finish()]);
+        gpu.device.poll(Maintain::Wait);
+    }
+
+    /// A one chunk world with a stone floor 4 voxels thick, and a player standing on it.
+    fn floor_scene() -> (World, Player) {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        world
+            .set_voxels_collected(IVec3::ZERO, ivec3(31, 3, 31), Voxel::STONE)
+            .unwrap();
+        (world, Player::new(vec3(16.5, 4.0, 16.5), 0.1))
+    }
+
+    fn read_back(device: &Device, queue: &Queue, buffer: &ArrayBuffer<u32>) -> Vec<u32> {
+        let size = buffer.len() as u64 * 4;
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some("readback"),
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&buffer.0, 0, &readback, 0, size);
+        queue.submit([encoder.finish()]);
+        readback
+            .slice(..)
+            .map_async(MapMode::Read, |result| result.unwrap());
+        device.poll(Maintain::Wait);
+        let bytes = readback.slice(..).get_mapped_range();
+        (bytes.chunks_exact(4))
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn growing_keeps_the_contents() {
+        let Some(Gpu { device, queue, .. }) = Gpu::headless() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let usage = BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::STORAGE;
+        let handle = device.create_buffer(&BufferDescriptor {
+            label: Some("test"),
+            size: 4 * 4,
+            usage,
+            mapped_at_creation: false,
+        });
+        let mut buffer = ArrayBuffer::<u32>(handle, 4, std::marker::PhantomData);
+        buffer.write_queued(&queue, 0, &[1, 2, 3, 4]);
+
+        assert!(!buffer.grow(&device, &queue, 4));
+        assert!(buffer.grow(&device, &queue, 10));
+        assert_eq!(buffer.len(), 10);
+        // past the old capacity, which would have been truncated before growing
+        buffer.write_queued(&queue, 4, &[5, 6, 7, 8, 9, 10]);
+        assert_eq!(
+            read_back(&device, &queue, &buffer),
+            (1..=10).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn lost_surfaces_are_reconfigured_once() {
+        use std::cell::Cell;
+
+        // a surface that fails with `errors` in order, then gives texture 7
+        let run = |errors: Vec<SurfaceError>| {
+            let mut errors = errors.into_iter();
+            let (acquired, reconfigured) = (Cell::new(0), Cell::new(0));
+            let output = retry_lost_surface(
+                || {
+                    acquired.set(acquired.get() + 1);
+                    errors.next().map_or(Ok(7), Err)
+                },
+                || reconfigured.set(reconfigured.get() + 1),
+            );
+            (output, acquired.get(), reconfigured.get())
+        };
+
+        assert_eq!(run(vec![]), (Ok(7), 1, 0));
+        assert_eq!(run(vec![SurfaceError::Lost]), (Ok(7), 2, 1));
+        assert_eq!(run(vec![SurfaceError::Outdated]), (Ok(7), 2, 1));
+        // still lost after reconfiguring, which is left to the caller
+        assert_eq!(
+            run(vec![SurfaceError::Lost, SurfaceError::Lost]),
+            (Err(SurfaceError::Lost), 2, 1)
+        );
+        assert_eq!(
+            run(vec![SurfaceError::Timeout]),
+            (Err(SurfaceError::Timeout), 1, 0)
+        );
+    }
+
+    #[test]
+    fn samples_split_evenly_under_the_cap() {
+        assert_eq!(split_samples(4, 8), (1, 4));
+        assert_eq!(split_samples(8, 8), (1, 8));
+        assert_eq!(split_samples(20, 8), (3, 7));
+        assert_eq!(split_samples(16, 1), (16, 1));
+        assert_eq!(split_samples(0, 0), (1, 1));
+    }
+
+    #[test]
+    fn high_sample_counts_take_several_dispatches() {
+        let Some(gpu) = Gpu::headless() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let (world, mut player) = floor_scene();
+        // looking down at the floor
+        player.rot.x = 60.0;
+        let mut settings = Settings {
+            max_ray_bounces: 2,
+            sun_intensity: 1.0,
+            sky_color: [0.5, 0.7, 1.0],
+            sun_pos: [16.0, 500.0, 16.0],
+            ..Default::default()
+        };
+        let (dispatches, samples) = split_samples(20, 8);
+        settings.samples_per_pixel = samples;
+        let res = scene(&gpu, &world, &settings, &player, uvec2(16, 16));
+        let cam_data = player.create_cam_data(vec2(16.0, 16.0), &world);
+
+        let mut frame_count = 0;
+        let ran = res.dispatch_path_tracer(
+            &gpu,
+            &settings,
+            None,
+            cam_data,
+            &mut frame_count,
+            dispatches,
+            Duration::MAX,
+        );
+        assert_eq!((ran, frame_count), (3, 3));
+        let texels = read_texels(&gpu, &res.prev_result_texture);
+        assert!(texels.iter().all(|t| t.is_finite()));
+        let lit = texels.iter().filter(|t| t.truncate().max_element() > 0.0);
+        assert!(lit.count() > texels.len() / 2, "the floor should be lit");
+
+        // past the budget, the rest are dropped, but one always runs
+        let ran = res.dispatch_path_tracer(
+            &gpu,
+            &settings,
+            None,
+            cam_data,
+            &mut frame_count,
+            dispatches,
+            Duration::ZERO,
+        );
+        assert_eq!((ran, frame_count), (1, 4));
+    }
+
+    #[test]
+    fn zero_sizes_keep_the_textures() {
+        let Some(mut gpu) = Gpu::headless() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let (world, player) = floor_scene();
+        let mut res = scene(&gpu, &world, &Settings::default(), &player, uvec2(16, 8));
+
+        for size in [uvec2(0, 64), uvec2(64, 0), UVec2::ZERO] {
+            gpu.resize(size);
+            res.resize_result_texture(&gpu, size);
+            assert_eq!(gpu.surface_size(), uvec2(64, 64));
+            assert_eq!(res.result_size(), uvec2(16, 8));
+            for tex in [
+                &res.result_texture,
+                &res.prev_result_texture,
+                &res.gbuffer_texture,
+            ] {
+                assert_eq!(tex.size(), uvec2(16, 8));
+            }
+        }
+    }
+
+    #[test]
+    fn gbuffer_has_the_primary_hit_depth() {
+        let Some(gpu) = Gpu::headless() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let size = uvec2(16, 16);
+        let settings = Settings {
+            write_gbuffer: 1,
+            ..Default::default()
+        };
+        let (world, mut player) = floor_scene();
+        let eye_height = player.eye_pos().y - 4.0;
+
+        // looking straight down at the floor, every pixel hits it
+        player.rot.x = 90.0;
+        let res = scene(&gpu, &world, &settings, &player, size);
+        dispatch(&gpu, &res, &res.ray_tracer, false);
+        let texels = read_texels(&gpu, &res.gbuffer_texture);
+        // the pixel at the center of the screen
+        let center = texels[(size.x * size.y / 2 + size.x / 2) as usize];
+        assert!(
+            (center.w - eye_height).abs() < 1e-2,
+            "depth {} below an eye {eye_height} above the floor",
+            center.w
+        );
+        assert_eq!(center.truncate(), vec3(0.0, 1.0, 0.0));
+        for texel in &texels {
+            assert!(
+                texel.w >= eye_height - 1e-2,
+                "closer than the floor: {texel}"
+            );
+        }
+
+        // looking straight up, every pixel is sky
+        player.rot.x = -90.0;
+        let res = scene(&gpu, &world, &settings, &player, size);
+        dispatch(&gpu, &res, &res.ray_tracer, false);
+        for texel in read_texels(&gpu, &res.gbuffer_texture) {
+            assert_eq!(texel, vec4(0.0, 0.0, 0.0, -1.0));
+        }
+    }
+
+    #[test]
+    fn rays_below_the_world_see_the_void() {
+        let Some(gpu) = Gpu::headless() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let size = uvec2(16, 16);
+        // exactly representable in the result texture
+        let void_color = [0.125, 0.25, 0.375];
+        let settings = Settings {
+            max_ray_bounces: 2,
+            samples_per_pixel: 1,
+            sky_color: [0.5, 0.7, 1.0],
+            sun_intensity: 1.0,
+            sun_pos: [16.0, -500.0, 16.0],
+            cloud_coverage: 0.5,
+            void_color,
+            ..Default::default()
+        };
+        let (world, mut player) = floor_scene();
+        // under the world, looking straight down
+        player.pos.y = -20.0;
+        player.rot.x = 90.0;
+        let res = scene(&gpu, &world, &settings, &player, size);
+        let expected = Vec3::from(void_color);
+
+        for shader in [&res.ray_tracer, &res.path_tracer] {
+            dispatch(&gpu, &res, shader, false);
+            for texel in read_texels(&gpu, &res.result_texture) {
+                assert_eq!(texel.truncate(), expected, "{texel} isn't the void");
+            }
+        }
+    }
+
+    #[test]
+    fn split_screen_renders_each_half_with_its_settings() {
+        let Some(gpu) = Gpu::headless() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        // halves that aren't a multiple of the workgroup size wide
+        let size = uvec2(24, 16);
+        let [left, right] = Viewport::halves(size);
+        assert_eq!((left.size.x, right.offset.x, right.size.x), (12, 12, 12));
+
+        let (world, mut player) = floor_scene();
+        // looking straight up, at nothing but the sky
+        player.rot.x = -90.0;
+        let settings = Settings {
+            max_ray_bounces: 1,
+            samples_per_pixel: 1,
+            sky_color: [0.5, 0.25, 0.0],
+            sun_pos: [16.0, -500.0, 16.0],
+            split_screen: 1,
+            ..Default::default()
+        };
+        let split_settings = Settings {
+            sky_color: [0.0, 0.25, 0.5],
+            ..settings
+        };
+        let res = scene(&gpu, &world, &settings, &player, size);
+        res.buffers.split_settings.write(&gpu, &split_settings);
+
+        for shader in [&res.ray_tracer, &res.path_tracer] {
+            dispatch(&gpu, &res, shader, true);
+            let texels = read_texels(&gpu, &res.result_texture);
+            for (i, texel) in texels.iter().enumerate() {
+                let x = i as u32 % size.x;
+                let expected = match x < left.size.x {
+                    true => settings.sky_color,
+                    false => split_settings.sky_color,
+                };
+                assert_eq!(texel.truncate(), Vec3::from(expected), "at x {x}");
+            }
+        }
+    }
+
+    #[test]
+    fn local_lights_light_nearby_surfaces() {
+        let Some(gpu) = Gpu::headless() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let size = uvec2(16, 16);
+        let (mut world, mut player) = floor_scene();
+        world
+            .set_voxel(ivec3(19, 4, 16), Voxel::BRIGHT, |_| {})
+            .unwrap();
+        // looking straight down at the floor around the light
+        player.pos = vec3(20.5, 4.0, 16.5);
+        player.rot.x = 90.0;
+
+        let render = |local_light_radius| {
+            let settings = Settings {
+                // the sun is down, so nothing else lights the floor
+                sun_pos: [16.0, -500.0, 16.0],
+                local_light_radius,
+                ..Default::default()
+            };
+            let res = scene(&gpu, &world, &settings, &player, size);
+            dispatch(&gpu, &res, &res.ray_tracer, false);
+            read_texels(&gpu, &res.result_texture)
+        };
+        let unlit = render(0);
+        let lit = render(3);
+        let brighter = (lit.iter().zip(&unlit))
+            .filter(|(lit, unlit)| lit.truncate().length() > unlit.truncate().length() + 1e-3)
+            .count();
+        assert!(brighter > lit.len() / 4, "only {brighter} texels were lit");
+        for (lit, unlit) in lit.iter().zip(&unlit) {
+            assert!(
+                lit.truncate().cmpge(unlit.truncate()).all(),
+                "{lit} is darker than {unlit}"
+            );
+        }
+    }
+}
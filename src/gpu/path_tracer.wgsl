@@ -1,23 +1,40 @@
 struct CamData {
     pos: vec3<f32>,
+    reproject: u32,
     inv_view_mat: mat4x4<f32>,
     inv_proj_mat: mat4x4<f32>,
     proj_size: vec2<f32>,
+    prev_pos: vec3<f32>,
+    prev_inv_view_mat: mat4x4<f32>,
+    prev_inv_proj_mat: mat4x4<f32>,
 }
 
 struct Settings {
     max_ray_bounces: u32,
     sun_intensity: f32,
-    show_step_count: u32,
+    debug_mode: u32,
     sky_color: vec3<f32>,
     sun_pos: vec3<f32>,
     samples_per_pixel: u32,
+    cloud_coverage: f32,
+    cloud_altitude: f32,
+    taa_blend: f32,
+    sun_softness: f32,
+    write_gbuffer: u32,
+    local_light_radius: u32,
+    split_screen: u32,
+    ao_samples: u32,
+    void_color: vec3<f32>,
+    ao_radius: f32,
+    rr_min_bounces: u32,
+    emissive_lighting: u32,
+    supersample: u32,
 }
 
 struct World {
     min: vec3<i32>,
-    size: u32,
-    size_in_chunks: u32,
+    size: vec3<u32>,
+    size_in_chunks: vec3<u32>,
 }
 
 struct Material {
@@ -29,6 +46,8 @@ struct Material {
     translucency: f32,
     polish_color: vec3<f32>,
     polish_scatter: f32,
+    metallic: f32,
+    roughness: f32,
 }
 
 struct ChunkHeader {
@@ -36,7 +55,13 @@ struct ChunkHeader {
     alloc: u32,
 }
 
-@group(0) @binding(0) var output_texture_: texture_storage_2d<rgba8unorm, write>;
+// The rectangle of the output this dispatch renders, in texels.
+struct Viewport {
+    offset: vec2<u32>,
+    size: vec2<u32>,
+}
+
+@group(0) @binding(0) var output_texture_: texture_storage_2d<rgba16float, write>;
 @group(0) @binding(1) var<uniform> cam_data_: CamData;
 @group(0) @binding(2) var<uniform> settings_: Settings;
 @group(0) @binding(3) var<storage, read> voxel_mats: array<Material>;
@@ -46,6 +71,7 @@ struct ChunkHeader {
 @group(0) @binding(6) var<storage, read> nodes_: array<u32>;
 @group(0) @binding(7) var<storage, read> chunks_: array<ChunkHeader>;
 @group(0) @binding(8) var prev_output_texture_: texture_2d<f32>;
+@group(0) @binding(10) var<uniform> viewport_: Viewport;
 
 fn get_node(idx: u32) -> u32 {
     return nodes_[idx];
@@ -139,8 +165,8 @@ fn find_node(pos: vec3<f32>, max_depth: u32) -> FoundNode {
     let chunk_coords = vec3<i32>(floor(pos / 32.0));
     let min = vec3<f32>(chunk_coords * 32);
     let chunk_idx = u32(chunk_coords.x)
-        + u32(chunk_coords.y) * world_chunk_w
-        + u32(chunk_coords.z) * world_chunk_w * world_chunk_w;
+        + u32(chunk_coords.y) * world_chunk_w.x
+        + u32(chunk_coords.z) * world_chunk_w.x * world_chunk_w.y;
     let root = chunks_[chunk_idx].root;
     return find_chunk_node(pos, max_depth, min, root);
 }
@@ -158,6 +184,11 @@ fn ray_color(rng: ptr<function, u32>, ray: Ray) -> vec3<f32> {
     var ray_color: vec3<f32> = vec3(1.0);
     var incoming_light: vec3<f32> = vec3(0.0);
     
+    // How much of the light from emitters near the last bounce was already
+    // sampled directly, so a ray that happens to hit one doesn't count it twice.
+    var sampled_emitters = 0.0;
+    var last_bounce = vec3<i32>(floor(ray.origin));
+
     var bounce_count = 0u;
     while bounce_count < settings_.max_ray_bounces {
         let rs: HitResult = ray_world(ray);
@@ -181,25 +212,141 @@ fn ray_color(rng: ptr<function, u32>, ray: Ray) -> vec3<f32> {
 
         // let is_polish_bounce = rng_next(rng) < rs.material.polish_bounce_chance;
         
-        let specular_dir = ray.dir - 2.0 * rs.norm * dot(rs.norm, ray.dir);
-        let scattered_dir = normalize(rs.norm + rng_next_dir(rng));
-        
-        // let scatter = mix(rs.material.scatter, rs.material.polish_scatter, f32(is_polish_bounce));
-        let scatter = rs.material.scatter;
-        
-        let emitted_light = rs.material.color * rs.material.emission;
+        var emitted_light = rs.material.color * rs.material.emission;
+        if in_emitter_range(vec3<i32>(floor(rs.pos)), last_bounce) {
+            emitted_light *= 1.0 - sampled_emitters;
+        }
         incoming_light += emitted_light * ray_color;
-        // ray_color *= mix(rs.material.color, rs.material.polish_color, f32(is_polish_bounce));
-        ray_color *= rs.material.color;
 
-        ray.dir = normalize(mix(specular_dir, scattered_dir, scatter));
+        if settings_.emissive_lighting != 0u {
+            // direct light only makes sense for the diffuse part of the surface
+            sampled_emitters = 1.0 - rs.material.metallic;
+            let direct = sample_emitters(rng, rs.pos, rs.norm);
+            incoming_light += direct * rs.material.color * sampled_emitters * ray_color;
+            last_bounce = vec3<i32>(floor(rs.pos + rs.norm * 0.01));
+        }
+
+        ray.dir = sample_brdf(rng, rs.material, rs.norm, ray.dir, &ray_color);
         ray.origin = rs.pos + ray.dir * 0.001;
         
         bounce_count += 1u;
+
+        // Russian roulette: rays that carry little light are likely to stop here,
+        // and the survivors are brightened to make up for the ones that stopped.
+        if bounce_count >= settings_.rr_min_bounces {
+            let survive = clamp(max(ray_color.x, max(ray_color.y, ray_color.z)), 0.05, 1.0);
+            if rng_next(rng) >= survive {
+                break;
+            }
+            ray_color /= survive;
+        }
     }
     return incoming_light;
 }
 
+// How many random voxels around each bounce `sample_emitters` checks for light.
+const EMITTER_CANDIDATES: u32 = 8u;
+
+// Whether `voxel` is within `local_light_radius` of `center`, where `sample_emitters` looks.
+fn in_emitter_range(voxel: vec3<i32>, center: vec3<i32>) -> bool {
+    let r = i32(settings_.local_light_radius);
+    return settings_.emissive_lighting != 0u && all(abs(voxel - center) <= vec3(r));
+}
+
+// Estimates the light that emissive voxels within `local_light_radius` shine on a
+// diffuse surface at `pos`, by checking a few random voxels around it and treating
+// the emissive ones as point lights at their centers.
+fn sample_emitters(rng: ptr<function, u32>, pos: vec3<f32>, norm: vec3<f32>) -> vec3<f32> {
+    let r = i32(settings_.local_light_radius);
+    let side = 2 * r + 1;
+    let origin = pos + norm * 0.01;
+    let center = vec3<i32>(floor(origin));
+    let world_max = vec3<f32>(world_.size);
+
+    var light = vec3(0.0);
+    for (var i = 0u; i < EMITTER_CANDIDATES; i++) {
+        let rand = vec3(rng_next(rng), rng_next(rng), rng_next(rng));
+        let offset = min(vec3<i32>(rand * f32(side)), vec3(side - 1)) - r;
+        let light_pos = center + offset;
+        let light_center = vec3<f32>(light_pos) + 0.5;
+        if any(light_center < vec3(0.0)) || any(light_center >= world_max) {
+            continue;
+        }
+        let mat = voxel_mats[node_voxel(get_node(find_node(light_center, 5u).idx))];
+        if mat.emission <= 0.0 {
+            continue;
+        }
+        let to_light = light_center - origin;
+        let dist = length(to_light);
+        let n_dot_l = dot(norm, to_light / dist);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+        // the light is visible if it's the first thing a ray towards it hits
+        var shadow_ray: Ray;
+        shadow_ray.origin = origin;
+        shadow_ray.dir = to_light / dist;
+        let hit = ray_world(shadow_ray);
+        if !hit.hit || any(vec3<i32>(floor(hit.pos)) != light_pos) {
+            continue;
+        }
+        light += mat.color * mat.emission * n_dot_l / (dist * dist);
+    }
+    // each candidate stands in for every voxel in range, divided between the candidates
+    let voxels_in_range = f32(side * side * side);
+    return light * voxels_in_range / (f32(EMITTER_CANDIDATES) * 3.14159265);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(1.0 - clamp(cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Importance samples a microfacet normal from the GGX distribution around `norm`.
+fn sample_ggx(rng: ptr<function, u32>, norm: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = max(roughness * roughness, 0.001);
+    let u1 = rng_next(rng);
+    let u2 = rng_next(rng);
+
+    let phi = 2.0 * 3.14159265 * u1;
+    let cos_theta = sqrt((1.0 - u2) / (1.0 + (a * a - 1.0) * u2));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+
+    let up = select(vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), abs(norm.y) < 0.999);
+    let tangent = normalize(cross(up, norm));
+    let bitangent = cross(norm, tangent);
+    return normalize(
+        tangent * cos(phi) * sin_theta + bitangent * sin(phi) * sin_theta + norm * cos_theta
+    );
+}
+
+// Picks the next bounce direction for a metallic/roughness material, and
+// attenuates `throughput` by the lobe that was chosen.
+// A rough dielectric bounces diffusely most of the time, a smooth metal reflects like a mirror.
+fn sample_brdf(
+    rng: ptr<function, u32>,
+    mat: Material,
+    norm: vec3<f32>,
+    in_dir: vec3<f32>,
+    throughput: ptr<function, vec3<f32>>,
+) -> vec3<f32> {
+    let f0 = mix(vec3(0.04), mat.color, mat.metallic);
+    let fresnel = fresnel_schlick(dot(norm, -in_dir), f0);
+    let specular_chance = max(mat.metallic, (fresnel.x + fresnel.y + fresnel.z) / 3.0);
+
+    if rng_next(rng) < specular_chance {
+        let half_vec = sample_ggx(rng, norm, mat.roughness);
+        var out_dir = reflect(in_dir, half_vec);
+        if dot(out_dir, norm) <= 0.0 {
+            out_dir = reflect(in_dir, norm);
+        }
+        // metals tint their reflections, dielectrics don't
+        *throughput *= mix(vec3(1.0), mat.color, mat.metallic);
+        return normalize(out_dir);
+    }
+    *throughput *= mat.color;
+    return normalize(norm + rng_next_dir(rng));
+}
+
 fn ray_world(start_ray: Ray) -> HitResult {
     let dir = start_ray.dir;
     let mask = vec3<f32>(dir >= 0.0);
@@ -208,7 +355,7 @@ fn ray_world(start_ray: Ray) -> HitResult {
     var ray_pos = start_ray.origin;
     
     let world_min = vec3(0.0);
-    let world_max = world_min + f32(world_.size);
+    let world_max = world_min + vec3<f32>(world_.size);
     
     var result: HitResult;
     
@@ -292,7 +439,7 @@ fn ray_world(start_ray: Ray) -> HitResult {
         result.material.color *= 0.2;
     }
 
-    if settings_.show_step_count == 1u {
+    if settings_.debug_mode == 2u {
         let f = f32(iter_count) / 200.0;
         result.material.color = vec3(clamp(f, 0.0, 1.0));
     }
@@ -300,18 +447,68 @@ fn ray_world(start_ray: Ray) -> HitResult {
 }
 
 fn ray_sky(ray: Ray) -> vec3<f32> {
+    // below the horizon, rays leaving the world only see the void under it
+    if ray.dir.y <= -0.01 {
+        return settings_.void_color;
+    }
     let horizon_color = vec3(1.0, 0.3, 0.0);
-    let void_color = vec3(0.03);
-    let sun_size = 0.01;
+    // the sun disc can't shrink to nothing, or random bounces would never find it
+    let sun_radius = max(settings_.sun_softness, 0.02);
     
     let ground_to_sky_t = smoothstep(-0.01, 0.0, ray.dir.y);
     let sky_gradient_t = pow(smoothstep(0.0, 0.4, ray.dir.y), 0.35);
     let sky_gradient = mix(horizon_color, settings_.sky_color, sky_gradient_t);
     let sun_dir = normalize(settings_.sun_pos - vec3<f32>(world_.min) - ray.origin);
     
-    let sun = f32(dot(ray.dir, sun_dir) > (1.0 - sun_size) && ground_to_sky_t >= 1.0);
+    let sun = f32(dot(ray.dir, sun_dir) > cos(sun_radius) && ground_to_sky_t >= 1.0);
     
-    return mix(void_color, sky_gradient, ground_to_sky_t) + sun * settings_.sun_intensity;
+    let sky = ray_clouds(ray, sky_gradient, sun_dir);
+    
+    return mix(settings_.void_color, sky, ground_to_sky_t) + sun * settings_.sun_intensity;
+}
+
+fn hash2(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+fn value_noise(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash2(i);
+    let b = hash2(i + vec2(1.0, 0.0));
+    let c = hash2(i + vec2(0.0, 1.0));
+    let d = hash2(i + vec2(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+fn cloud_density(p: vec2<f32>) -> f32 {
+    var sum = 0.0;
+    var amp = 0.5;
+    var q = p;
+    for (var i = 0; i < 4; i += 1) {
+        sum += value_noise(q) * amp;
+        q *= 2.0;
+        amp *= 0.5;
+    }
+    return sum;
+}
+
+// Blends a flat layer of clouds at `settings_.cloud_altitude` over the sky color.
+fn ray_clouds(ray: Ray, sky: vec3<f32>, sun_dir: vec3<f32>) -> vec3<f32> {
+    if settings_.cloud_coverage <= 0.0 || ray.dir.y <= 0.0 {
+        return sky;
+    }
+    let origin_y = ray.origin.y + f32(world_.min.y);
+    let t = (settings_.cloud_altitude - origin_y) / ray.dir.y;
+    if t <= 0.0 {
+        return sky;
+    }
+    let p = (ray.origin.xz + vec2<f32>(world_.min.xz) + ray.dir.xz * t) * 0.01;
+    let density = smoothstep(1.0 - settings_.cloud_coverage, 1.0, cloud_density(p));
+    // thin the clouds out towards the horizon
+    let fade = smoothstep(0.0, 0.15, ray.dir.y);
+    let sun_glow = pow(max(dot(ray.dir, sun_dir), 0.0), 8.0);
+    let cloud_color = mix(vec3(0.85), vec3(1.0, 0.9, 0.75), sun_glow);
+    return mix(sky, cloud_color, density * fade);
 }
 
 fn create_ray_from_screen(screen_pos: vec2<i32>) -> Ray {
@@ -328,13 +525,91 @@ fn create_ray_from_screen(screen_pos: vec2<i32>) -> Ray {
     return ray;
 }
 
+// The hit distance of the primary ray is stored in the alpha channel of the output,
+// so the next frame can tell if reprojected history belongs to the same surface.
+const TAA_MAX_DIST: f32 = 1024.0;
+
+fn encode_dist(dist: f32) -> f32 {
+    return sqrt(clamp(dist / TAA_MAX_DIST, 0.0, 1.0));
+}
+fn decode_dist(a: f32) -> f32 {
+    return a * a * TAA_MAX_DIST;
+}
+
+// Finds the pixel `world_pos` was at in the previous frame.
+// Returns -1 if it was behind the previous camera.
+fn reproject(world_pos: vec3<f32>) -> vec2<i32> {
+    let local = (cam_data_.prev_inv_view_mat * vec4(world_pos - cam_data_.prev_pos, 0.0)).xyz;
+    if local.z >= 0.0 {
+        return vec2(-1);
+    }
+    let eye = local.xy / -local.z;
+    let x = eye.x / cam_data_.prev_inv_proj_mat[0][0];
+    let y = -eye.y / cam_data_.prev_inv_proj_mat[1][1];
+    let screen = (vec2(x, y) + 1.0) * 0.5 * cam_data_.proj_size;
+    // primary rays pass through pixel corners (see create_ray_from_screen), so round to the nearest
+    return vec2<i32>(round(screen));
+}
+
+// Looks up the previous frame's color for the surface at `hit_dist` along `ray`.
+// Returns a negative alpha if there is no usable history (off-screen or disoccluded).
+fn taa_history(ray: Ray, hit_dist: f32) -> vec4<f32> {
+    let world_pos = ray.origin + ray.dir * hit_dist + vec3<f32>(world_.min);
+    let prev_px = reproject(world_pos);
+    if any(prev_px < vec2(0)) || any(prev_px >= vec2<i32>(cam_data_.proj_size)) {
+        return vec4(-1.0);
+    }
+    let history = textureLoad(prev_output_texture_, prev_px, 0);
+    let expected_dist = distance(world_pos, cam_data_.prev_pos);
+    if abs(decode_dist(history.a) - expected_dist) > max(1.0, expected_dist * 0.05) {
+        return vec4(-1.0);
+    }
+    return history;
+}
+
+fn debug_color(ray: Ray) -> vec3<f32> {
+    let rs = ray_world(ray);
+    switch settings_.debug_mode {
+        case 1u: {
+            return (rs.norm * 0.5 + 0.5) * f32(rs.hit);
+        }
+        case 2u: {
+            // ray_world replaces the material color with the step count in this mode
+            return rs.material.color;
+        }
+        case 3u: {
+            let depth = 1.0 - clamp(distance(rs.pos, ray.origin) / 256.0, 0.0, 1.0);
+            return vec3(depth * f32(rs.hit));
+        }
+        case 4u: {
+            return vec3(clamp(f32(frame_count_) / 64.0, 0.0, 1.0));
+        }
+        default: {
+            return vec3(0.0);
+        }
+    }
+}
+
 @compute @workgroup_size(8, 8, 1)
 fn update(@builtin(global_invocation_id) inv_id: vec3<u32>) {
-    let screen_pos = vec2<i32>(inv_id.xy);
-    var rng = inv_id.y * u32(cam_data_.proj_size.x) + inv_id.x + frame_count_ * 27927421u;
+    // the dispatch covers whole workgroups, past the edges of the viewport
+    if any(inv_id.xy >= viewport_.size) {
+        return;
+    }
+    let pixel = viewport_.offset + inv_id.xy;
+    let screen_pos = vec2<i32>(pixel);
+    var rng = pixel.y * u32(cam_data_.proj_size.x) + pixel.x + frame_count_ * 27927421u;
     
     let ray = create_ray_from_screen(screen_pos);
     // let color = ray_color(&rng, ray);
+    if settings_.debug_mode != 0u {
+        textureStore(output_texture_, screen_pos, vec4(debug_color(ray), 1.0));
+        return;
+    }
+
+    let primary = ray_world(ray);
+    let hit_dist = select(TAA_MAX_DIST, distance(primary.pos, ray.origin), primary.hit);
+    let encoded_dist = encode_dist(hit_dist);
 
     var color = vec3(0.0);
     var ray_count = 0u;
@@ -344,9 +619,25 @@ fn update(@builtin(global_invocation_id) inv_id: vec3<u32>) {
     }
     color /= f32(ray_count);
 
-    let old_render = textureLoad(prev_output_texture_, screen_pos, 0);
-    let weight = 1.0 / f32(frame_count_ + 1u);
-    let result = old_render * (1.0 - weight) + vec4(color, 1.0) * weight;
+    var result: vec4<f32>;
+    if frame_count_ == 0u && settings_.taa_blend > 0.0 {
+        // the camera moved, so blend with the reprojected previous frame
+        let history = taa_history(ray, hit_dist);
+        let blend = settings_.taa_blend * f32(history.a >= 0.0);
+        result = vec4(mix(color, history.rgb, blend), encoded_dist);
+    } else {
+        var old_render = textureLoad(prev_output_texture_, screen_pos, 0);
+        var weight = 1.0 / f32(frame_count_ + 1u);
+        if cam_data_.reproject != 0u {
+            // the camera turned, so the accumulated samples moved to another pixel
+            old_render = taa_history(ray, hit_dist);
+            if old_render.a < 0.0 {
+                // newly revealed, so there's nothing to accumulate onto
+                weight = 1.0;
+            }
+        }
+        result = old_render * (1.0 - weight) + vec4(color, encoded_dist) * weight;
+    }
     // let result = vec4(color, 1.0);
     
     textureStore(output_texture_, screen_pos, result);
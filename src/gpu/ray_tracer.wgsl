@@ -8,15 +8,29 @@ struct CamData {
 struct Settings {
     max_ray_bounces: u32,
     sun_intensity: f32,
-    show_step_count: u32,
+    debug_mode: u32,
     sky_color: vec3<f32>,
     sun_pos: vec3<f32>,
+    samples_per_pixel: u32,
+    cloud_coverage: f32,
+    cloud_altitude: f32,
+    taa_blend: f32,
+    sun_softness: f32,
+    write_gbuffer: u32,
+    local_light_radius: u32,
+    split_screen: u32,
+    ao_samples: u32,
+    void_color: vec3<f32>,
+    ao_radius: f32,
+    rr_min_bounces: u32,
+    emissive_lighting: u32,
+    supersample: u32,
 }
 
 struct World {
     min: vec3<i32>,
-    size: u32,
-    size_in_chunks: u32,
+    size: vec3<u32>,
+    size_in_chunks: vec3<u32>,
 }
 
 struct Material {
@@ -25,8 +39,11 @@ struct Material {
     scatter: f32,
     emission: f32,
     polish_bounce_chance: f32,
+    translucency: f32,
     polish_color: vec3<f32>,
     polish_scatter: f32,
+    metallic: f32,
+    roughness: f32,
 }
 
 struct ChunkHeader {
@@ -34,7 +51,13 @@ struct ChunkHeader {
     alloc: u32,
 }
 
-@group(0) @binding(0) var output_texture_: texture_storage_2d<rgba8unorm, write>;
+// The rectangle of the output this dispatch renders, in texels.
+struct Viewport {
+    offset: vec2<u32>,
+    size: vec2<u32>,
+}
+
+@group(0) @binding(0) var output_texture_: texture_storage_2d<rgba16float, write>;
 @group(0) @binding(1) var<uniform> cam_data_: CamData;
 @group(0) @binding(2) var<uniform> settings_: Settings;
 @group(0) @binding(3) var<storage, read> voxel_mats: array<Material>;
@@ -44,6 +67,8 @@ struct ChunkHeader {
 @group(0) @binding(6) var<storage, read> nodes_: array<u32>;
 @group(0) @binding(7) var<storage, read> chunks_: array<ChunkHeader>;
 @group(0) @binding(8) var prev_output_texture_: texture_2d<f32>;
+@group(0) @binding(9) var gbuffer_texture_: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(10) var<uniform> viewport_: Viewport;
 
 fn get_node(idx: u32) -> u32 {
     return nodes_[idx];
@@ -122,22 +147,139 @@ fn find_node(pos: vec3<f32>, max_depth: u32) -> FoundNode {
     let chunk_coords = vec3<i32>(floor(pos / 32.0));
     let min = vec3<f32>(chunk_coords * 32);
     let chunk_idx = u32(chunk_coords.x)
-        + u32(chunk_coords.y) * world_chunk_w
-        + u32(chunk_coords.z) * world_chunk_w * world_chunk_w;
+        + u32(chunk_coords.y) * world_chunk_w.x
+        + u32(chunk_coords.z) * world_chunk_w.x * world_chunk_w.y;
     let root = chunks_[chunk_idx].root;
     return find_chunk_node(pos, max_depth, min, root);
 }
 
-fn ray_color(ray: Ray) -> vec3<f32> {
+fn ray_color(ray: Ray, screen_pos: vec2<i32>) -> vec3<f32> {
     let rs = ray_world(ray);
-    let sky_color = ray_sky(ray);
-    var vox_color = rs.material.color;
-    return vox_color * f32(rs.hit) + sky_color * f32(!rs.hit);
+    if settings_.write_gbuffer != 0u {
+        let depth = select(-1.0, distance(ray.origin, rs.pos), rs.hit);
+        textureStore(gbuffer_texture_, screen_pos, vec4(rs.norm * f32(rs.hit), depth));
+    }
+    if !rs.hit {
+        return ray_sky(ray);
+    }
+    let ambient = 0.4 * ambient_occlusion(rs.pos, rs.norm);
+    let shade = mix(ambient, 1.0, sun_visibility(rs.pos, rs.norm));
+    return rs.material.color * (shade + local_light(rs.pos, rs.norm));
+}
+
+// Approximates the light that emissive voxels within `local_light_radius`
+// shine on `pos`, treating each one as a point light at its center.
+fn local_light(pos: vec3<f32>, norm: vec3<f32>) -> vec3<f32> {
+    let r = i32(settings_.local_light_radius);
+    let origin = pos + norm * 0.01;
+    let center = vec3<i32>(floor(origin));
+    let world_max = vec3<f32>(world_.size);
+
+    var light = vec3(0.0);
+    for (var x = -r; x <= r; x++) {
+        for (var y = -r; y <= r; y++) {
+            for (var z = -r; z <= r; z++) {
+                let light_pos = center + vec3(x, y, z);
+                let light_center = vec3<f32>(light_pos) + 0.5;
+                if any(light_center < vec3(0.0)) || any(light_center >= world_max) {
+                    continue;
+                }
+                let mat = voxel_mats[node_voxel(get_node(find_node(light_center, 5u).idx))];
+                if mat.emission <= 0.0 {
+                    continue;
+                }
+                let to_light = light_center - origin;
+                let dist = length(to_light);
+                let n_dot_l = dot(norm, to_light / dist);
+                if n_dot_l <= 0.0 {
+                    continue;
+                }
+                // the light is visible if it's the first thing a ray towards it hits,
+                // like `World::raymarch_to_light` checks on the CPU
+                var shadow_ray: Ray;
+                shadow_ray.origin = origin;
+                shadow_ray.dir = to_light / dist;
+                let hit = ray_world(shadow_ray);
+                if !hit.hit || any(vec3<i32>(floor(hit.pos)) != light_pos) {
+                    continue;
+                }
+                light += mat.color * mat.emission * n_dot_l / (dist * dist);
+            }
+        }
+    }
+    return light;
+}
+
+const SHADOW_SAMPLES: u32 = 4u;
+
+fn hash3(p: vec3<f32>) -> f32 {
+    return fract(sin(dot(p, vec3(12.9898, 78.233, 37.719))) * 43758.5453);
+}
+
+// The fraction of shadow rays from `pos` that reach the sun.
+// The rays are spread over the sun's disc, so a larger `sun_softness` gives wider penumbras.
+fn sun_visibility(pos: vec3<f32>, norm: vec3<f32>) -> f32 {
+    let sun_dir = normalize(settings_.sun_pos - vec3<f32>(world_.min) - pos);
+    if dot(norm, sun_dir) <= 0.0 {
+        return 0.0;
+    }
+    let up = select(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), abs(sun_dir.y) > 0.99);
+    let tangent = normalize(cross(up, sun_dir));
+    let bitangent = cross(sun_dir, tangent);
+    let disc_radius = tan(settings_.sun_softness);
+
+    // hard shadows only need the one ray
+    let samples = select(SHADOW_SAMPLES, 1u, settings_.sun_softness <= 0.0);
+    var lit = 0.0;
+    for (var i = 0u; i < samples; i++) {
+        let seed = pos * 7.31 + f32(i) * 1.37;
+        let angle = hash3(seed) * 6.2831853;
+        let r = sqrt(hash3(seed + 3.1)) * disc_radius;
+        let offset = (tangent * cos(angle) + bitangent * sin(angle)) * r;
+
+        var shadow_ray: Ray;
+        shadow_ray.origin = pos + norm * 0.01;
+        shadow_ray.dir = normalize(sun_dir + offset);
+        lit += f32(!ray_world(shadow_ray).hit);
+    }
+    return lit / f32(samples);
+}
+
+// The fraction of `ao_samples` short rays from `pos`, spread over the hemisphere
+// around `norm`, that don't hit anything within `ao_radius`. 1 when they're disabled.
+fn ambient_occlusion(pos: vec3<f32>, norm: vec3<f32>) -> f32 {
+    let samples = settings_.ao_samples;
+    if samples == 0u {
+        return 1.0;
+    }
+    let up = select(vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), abs(norm.y) > 0.99);
+    let tangent = normalize(cross(up, norm));
+    let bitangent = cross(norm, tangent);
+    let origin = pos + norm * 0.01;
+
+    var open = 0.0;
+    for (var i = 0u; i < samples; i++) {
+        // cosine weighted, so rays near the surface count for less
+        let seed = pos * 5.17 + f32(i) * 2.91;
+        let angle = hash3(seed) * 6.2831853;
+        let r = sqrt(hash3(seed + 1.7));
+        let side = (tangent * cos(angle) + bitangent * sin(angle)) * r;
+
+        var ao_ray: Ray;
+        ao_ray.origin = origin;
+        ao_ray.dir = normalize(side + norm * sqrt(max(1.0 - r * r, 0.0)));
+        let hit = ray_world(ao_ray);
+        open += f32(!hit.hit || distance(hit.pos, origin) > settings_.ao_radius);
+    }
+    return open / f32(samples);
 }
 
 fn ray_sky(ray: Ray) -> vec3<f32> {
+    // below the horizon, rays leaving the world only see the void under it
+    if ray.dir.y <= -0.01 {
+        return settings_.void_color;
+    }
     let horizon_color = vec3(1.0, 0.3, 0.0);
-    let void_color = vec3(0.03);
     let sun_size = 0.01;
     
     let ground_to_sky_t = smoothstep(-0.01, 0.0, ray.dir.y);
@@ -147,7 +289,53 @@ fn ray_sky(ray: Ray) -> vec3<f32> {
     
     let sun = f32(dot(ray.dir, sun_dir) > (1.0 - sun_size) && ground_to_sky_t >= 1.0);
     
-    return mix(void_color, sky_gradient, ground_to_sky_t) + sun * settings_.sun_intensity;
+    let sky = ray_clouds(ray, sky_gradient, sun_dir);
+    
+    return mix(settings_.void_color, sky, ground_to_sky_t) + sun * settings_.sun_intensity;
+}
+
+fn hash2(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+fn value_noise(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash2(i);
+    let b = hash2(i + vec2(1.0, 0.0));
+    let c = hash2(i + vec2(0.0, 1.0));
+    let d = hash2(i + vec2(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+fn cloud_density(p: vec2<f32>) -> f32 {
+    var sum = 0.0;
+    var amp = 0.5;
+    var q = p;
+    for (var i = 0; i < 4; i += 1) {
+        sum += value_noise(q) * amp;
+        q *= 2.0;
+        amp *= 0.5;
+    }
+    return sum;
+}
+
+// Blends a flat layer of clouds at `settings_.cloud_altitude` over the sky color.
+fn ray_clouds(ray: Ray, sky: vec3<f32>, sun_dir: vec3<f32>) -> vec3<f32> {
+    if settings_.cloud_coverage <= 0.0 || ray.dir.y <= 0.0 {
+        return sky;
+    }
+    let origin_y = ray.origin.y + f32(world_.min.y);
+    let t = (settings_.cloud_altitude - origin_y) / ray.dir.y;
+    if t <= 0.0 {
+        return sky;
+    }
+    let p = (ray.origin.xz + vec2<f32>(world_.min.xz) + ray.dir.xz * t) * 0.01;
+    let density = smoothstep(1.0 - settings_.cloud_coverage, 1.0, cloud_density(p));
+    // thin the clouds out towards the horizon
+    let fade = smoothstep(0.0, 0.15, ray.dir.y);
+    let sun_glow = pow(max(dot(ray.dir, sun_dir), 0.0), 8.0);
+    let cloud_color = mix(vec3(0.85), vec3(1.0, 0.9, 0.75), sun_glow);
+    return mix(sky, cloud_color, density * fade);
 }
 
 fn create_ray_from_screen(screen_pos: vec2<i32>) -> Ray {
@@ -164,12 +352,43 @@ fn create_ray_from_screen(screen_pos: vec2<i32>) -> Ray {
     return ray;
 }
 
+fn debug_color(ray: Ray) -> vec3<f32> {
+    let rs = ray_world(ray);
+    switch settings_.debug_mode {
+        case 1u: {
+            return (rs.norm * 0.5 + 0.5) * f32(rs.hit);
+        }
+        case 2u: {
+            // ray_world replaces the material color with the step count in this mode
+            return rs.material.color;
+        }
+        case 3u: {
+            let depth = 1.0 - clamp(distance(rs.pos, ray.origin) / 256.0, 0.0, 1.0);
+            return vec3(depth * f32(rs.hit));
+        }
+        case 4u: {
+            return vec3(clamp(f32(frame_count_) / 64.0, 0.0, 1.0));
+        }
+        default: {
+            return vec3(0.0);
+        }
+    }
+}
+
 @compute @workgroup_size(8, 8, 1)
 fn update(@builtin(global_invocation_id) inv_id: vec3<u32>) {
-    let screen_pos = vec2<i32>(inv_id.xy);
+    // the dispatch covers whole workgroups, past the edges of the viewport
+    if any(inv_id.xy >= viewport_.size) {
+        return;
+    }
+    let screen_pos = vec2<i32>(viewport_.offset + inv_id.xy);
     
     let ray = create_ray_from_screen(screen_pos);
-    let color = ray_color(ray);
+    if settings_.debug_mode != 0u {
+        textureStore(output_texture_, screen_pos, vec4(debug_color(ray), 1.0));
+        return;
+    }
+    let color = ray_color(ray, screen_pos);
     textureStore(output_texture_, screen_pos, vec4(color, 1.0));
 }
 
@@ -181,7 +400,7 @@ fn ray_world(start_ray: Ray) -> HitResult {
     var ray_pos = start_ray.origin;
     
     let world_min = vec3(0.0);
-    let world_max = world_min + f32(world_.size);
+    let world_max = world_min + vec3<f32>(world_.size);
     
     var result: HitResult;
     
@@ -265,7 +484,7 @@ fn ray_world(start_ray: Ray) -> HitResult {
         result.material.color *= 0.2;
     }
 
-    if settings_.show_step_count == 1u {
+    if settings_.debug_mode == 2u {
         let f = f32(iter_count) / 500.0;
         result.material.color = vec3(clamp(f, 0.0, 1.0));
     }
@@ -1,5 +1,14 @@
 @group(0) @binding(0) var tex: texture_2d<f32>;
 @group(0) @binding(1) var tex_s: sampler;
+@group(0) @binding(2) var<uniform> settings: ScreenSettings;
+
+struct ScreenSettings {
+	// 0: none, 1: reinhard, 2: ACES
+	tone_map: u32,
+	exposure: f32,
+	gamma: f32,
+	supersample: u32,
+}
 
 struct FsInput {
 	@builtin(position) pos: vec4<f32>,
@@ -31,11 +40,50 @@ fn vs_main(@builtin(vertex_index) index: u32) -> FsInput {
 	return out;
 }
 
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(c: vec3<f32>) -> vec3<f32> {
+	let a = 2.51;
+	let b = 0.03;
+	let c2 = 2.43;
+	let d = 0.59;
+	let e = 0.14;
+	return clamp((c * (a * c + b)) / (c * (c2 * c + d) + e), vec3(0.0), vec3(1.0));
+}
+
+fn tone_map(color: vec3<f32>) -> vec3<f32> {
+	var c = max(color, vec3(0.0)) * exp2(settings.exposure);
+	switch settings.tone_map {
+		case 1u: { c = c / (c + 1.0); }
+		case 2u: { c = aces(c); }
+		default: { c = min(c, vec3(1.0)); }
+	}
+	return pow(c, vec3(1.0 / max(settings.gamma, 0.01)));
+}
+
+// Averages the `supersample` x `supersample` texels that make up the result pixel at `tex_coord`.
+fn resolve(tex_coord: vec2<f32>) -> vec3<f32> {
+	let n = settings.supersample;
+	if n <= 1u {
+		return textureSample(tex, tex_s, tex_coord).rgb;
+	}
+	let size = textureDimensions(tex) / n;
+	let pixel = min(vec2<u32>(tex_coord * vec2<f32>(size)), size - 1u) * n;
+	var sum = vec3(0.0);
+	for (var y = 0u; y < n; y += 1u) {
+		for (var x = 0u; x < n; x += 1u) {
+			sum += textureLoad(tex, pixel + vec2(x, y), 0).rgb;
+		}
+	}
+	return sum / f32(n * n);
+}
+
 @fragment
 fn fs_main(in: FsInput) -> @location(0) vec4<f32> {
 	let crosshair_mask = f32(distance(vec2(0.5, 0.5), in.tex_coord) < 0.003);
+	// the alpha channel of the result texture holds depth, not coverage
+	let color = vec4(tone_map(resolve(in.tex_coord)), 1.0);
 	return 
-		textureSample(tex, tex_s, in.tex_coord) * (1.0 - crosshair_mask) +
+		color * (1.0 - crosshair_mask) +
 		vec4(5.0, 5.0, 5.0, 1.0) * crosshair_mask
 	;
 }
@@ -1,3 +1,4 @@
+use super::Gpu;
 use glam::{uvec2, UVec2};
 
 pub struct Texture {
@@ -53,4 +54,59 @@ impl Texture {
         let size = self.handle.size();
         uvec2(size.width, size.height)
     }
+
+    /// Copies the texture back from the GPU, blocking until it arrives.
+    /// Returns its texels row by row without any padding, in the texture's format
+    /// (RGBA for every texture this renders to). The texture needs `COPY_SRC` usage.
+    pub fn read_to_cpu(&self, gpu: &Gpu) -> Vec<u8> {
+        let size = self.size();
+        let texel_size = self.handle.format().block_size(None).unwrap_or(4);
+        let row_size = size.x * texel_size;
+        // texture to buffer copies need rows aligned to 256 bytes
+        let padded_row_size = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("texture_readback"),
+            size: padded_row_size as u64 * size.y as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = gpu.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.handle,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(size.y),
+                },
+            },
+            self.handle.size(),
+        );
+        gpu.queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| _ = sender.send(result));
+        gpu.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .expect("the readback buffer was dropped while mapping")
+            .expect("failed to map the readback buffer");
+
+        let mapped = slice.get_mapped_range();
+        let mut texels = Vec::with_capacity((row_size * size.y) as usize);
+        for row in mapped.chunks(padded_row_size as usize) {
+            texels.extend_from_slice(&row[..row_size as usize]);
+        }
+        drop(mapped);
+        buffer.unmap();
+        texels
+    }
 }
@@ -1,10 +1,359 @@
 use glam::{vec2, Vec2};
+use serde::de::{self, Deserialize, Deserializer, EnumAccess, MapAccess, VariantAccess, Visitor};
 use std::collections::HashSet;
+use std::path::Path;
 use winit::event::*;
 
 pub type Key = winit::event::VirtualKeyCode;
 pub type MouseButton = winit::event::MouseButton;
 
+/// The file key bindings are loaded from, in the working directory.
+pub const KEY_BINDINGS_PATH: &str = "keybindings.ron";
+
+/// Something the player does, that can be bound to a key or mouse button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    /// Jump, swim up, or fly up.
+    Jump,
+    /// Fly down.
+    Descend,
+    ToggleFly,
+    ToggleNoclip,
+    Break,
+    Place,
+    /// Show the cursor and side panel.
+    ToggleOverlay,
+    ToggleConsole,
+    /// Save the rendered frame to a PNG.
+    Screenshot,
+    ToggleFullscreen,
+    /// Freeze the world and the day cycle.
+    Pause,
+    /// Whether the world follows the player.
+    ToggleMoveWorld,
+    /// Whether dirty chunks get rebuilt.
+    ToggleBuildChunks,
+    ToggleHitboxes,
+    CycleDebugView,
+    /// Set a corner of the selection to the targeted voxel.
+    PickCorner,
+    ClearSelection,
+    /// Select the next inventory slot.
+    NextSlot,
+    /// Select the previous inventory slot.
+    PrevSlot,
+    /// Held with break or place to keep editing every frame.
+    HoldEdit,
+    /// Held with a slot key to save the viewpoint to that bookmark.
+    SaveBookmark,
+    /// Held with a slot key to jump to that bookmark.
+    LoadBookmark,
+    /// Select this slot of the active inventory row (1-9), or bookmark (0-9).
+    Slot0,
+    Slot1,
+    Slot2,
+    Slot3,
+    Slot4,
+    Slot5,
+    Slot6,
+    Slot7,
+    Slot8,
+    Slot9,
+}
+impl Action {
+    pub const ALL: &'static [Self] = &[
+        Self::MoveForward,
+        Self::MoveBack,
+        Self::MoveLeft,
+        Self::MoveRight,
+        Self::Jump,
+        Self::Descend,
+        Self::ToggleFly,
+        Self::ToggleNoclip,
+        Self::Break,
+        Self::Place,
+        Self::ToggleOverlay,
+        Self::ToggleConsole,
+        Self::Screenshot,
+        Self::ToggleFullscreen,
+        Self::Pause,
+        Self::ToggleMoveWorld,
+        Self::ToggleBuildChunks,
+        Self::ToggleHitboxes,
+        Self::CycleDebugView,
+        Self::PickCorner,
+        Self::ClearSelection,
+        Self::NextSlot,
+        Self::PrevSlot,
+        Self::HoldEdit,
+        Self::SaveBookmark,
+        Self::LoadBookmark,
+        Self::Slot0,
+        Self::Slot1,
+        Self::Slot2,
+        Self::Slot3,
+        Self::Slot4,
+        Self::Slot5,
+        Self::Slot6,
+        Self::Slot7,
+        Self::Slot8,
+        Self::Slot9,
+    ];
+    /// The name of each action in a key bindings file, in the order of `ALL`.
+    pub const NAMES: &'static [&'static str] = &[
+        "move_forward",
+        "move_back",
+        "move_left",
+        "move_right",
+        "jump",
+        "descend",
+        "toggle_fly",
+        "toggle_noclip",
+        "break",
+        "place",
+        "toggle_overlay",
+        "toggle_console",
+        "screenshot",
+        "toggle_fullscreen",
+        "pause",
+        "toggle_move_world",
+        "toggle_build_chunks",
+        "toggle_hitboxes",
+        "cycle_debug_view",
+        "pick_corner",
+        "clear_selection",
+        "next_slot",
+        "prev_slot",
+        "hold_edit",
+        "save_bookmark",
+        "load_bookmark",
+        "slot_0",
+        "slot_1",
+        "slot_2",
+        "slot_3",
+        "slot_4",
+        "slot_5",
+        "slot_6",
+        "slot_7",
+        "slot_8",
+        "slot_9",
+    ];
+    /// The number keys, 0 to 9.
+    pub const SLOTS: [Self; 10] = [
+        Self::Slot0,
+        Self::Slot1,
+        Self::Slot2,
+        Self::Slot3,
+        Self::Slot4,
+        Self::Slot5,
+        Self::Slot6,
+        Self::Slot7,
+        Self::Slot8,
+        Self::Slot9,
+    ];
+
+    /// The name of the action in a key bindings file.
+    pub fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let idx = Self::NAMES.iter().position(|n| *n == name)?;
+        Some(Self::ALL[idx])
+    }
+}
+
+/// A physical key or mouse button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(Key),
+    Mouse(MouseButton),
+}
+impl Binding {
+    /// Parses a `Key` variant name (like `W` or `LShift`), or `MouseLeft`, `MouseRight` or `MouseMiddle`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "MouseLeft" => Some(Self::Mouse(MouseButton::Left)),
+            "MouseRight" => Some(Self::Mouse(MouseButton::Right)),
+            "MouseMiddle" => Some(Self::Mouse(MouseButton::Middle)),
+            _ => key_from_name(name).map(Self::Key),
+        }
+    }
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        /// The `Key` with the variant name `name`, for the keys that can be bound.
+        fn key_from_name(name: &str) -> Option<Key> {
+            match name {
+                $(stringify!($key) => Some(Key::$key),)*
+                _ => None,
+            }
+        }
+    };
+}
+key_names!(
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1, Key2,
+    Key3, Key4, Key5, Key6, Key7, Key8, Key9, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Space, Tab, Escape, Return, Back, Capital, Up, Down, Left, Right, LShift, RShift, LControl,
+    RControl, LAlt, RAlt, Grave, Minus, Equals, LBracket, RBracket, Semicolon, Apostrophe, Comma,
+    Period, Slash, Backslash,
+);
+
+/// Which key or mouse button performs each `Action`.
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    /// Indexed by `Action as usize`, in the order of `Action::ALL`.
+    bindings: Vec<Binding>,
+}
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .map(|action| match action {
+                Action::MoveForward => Binding::Key(Key::W),
+                Action::MoveBack => Binding::Key(Key::S),
+                Action::MoveLeft => Binding::Key(Key::A),
+                Action::MoveRight => Binding::Key(Key::D),
+                Action::Jump => Binding::Key(Key::Space),
+                Action::Descend => Binding::Key(Key::LShift),
+                Action::ToggleFly => Binding::Key(Key::Z),
+                Action::ToggleNoclip => Binding::Key(Key::X),
+                Action::Break => Binding::Mouse(MouseButton::Left),
+                Action::Place => Binding::Mouse(MouseButton::Right),
+                Action::ToggleOverlay => Binding::Key(Key::T),
+                Action::ToggleConsole => Binding::Key(Key::Grave),
+                Action::Screenshot => Binding::Key(Key::F2),
+                Action::ToggleFullscreen => Binding::Key(Key::F),
+                Action::Pause => Binding::Key(Key::P),
+                Action::ToggleMoveWorld => Binding::Key(Key::N),
+                Action::ToggleBuildChunks => Binding::Key(Key::M),
+                Action::ToggleHitboxes => Binding::Key(Key::F4),
+                Action::CycleDebugView => Binding::Key(Key::F3),
+                Action::PickCorner => Binding::Key(Key::B),
+                Action::ClearSelection => Binding::Key(Key::C),
+                Action::NextSlot => Binding::Key(Key::Up),
+                Action::PrevSlot => Binding::Key(Key::Down),
+                Action::HoldEdit => Binding::Key(Key::LControl),
+                Action::SaveBookmark => Binding::Key(Key::LControl),
+                Action::LoadBookmark => Binding::Key(Key::LAlt),
+                Action::Slot0 => Binding::Key(Key::Key0),
+                Action::Slot1 => Binding::Key(Key::Key1),
+                Action::Slot2 => Binding::Key(Key::Key2),
+                Action::Slot3 => Binding::Key(Key::Key3),
+                Action::Slot4 => Binding::Key(Key::Key4),
+                Action::Slot5 => Binding::Key(Key::Key5),
+                Action::Slot6 => Binding::Key(Key::Key6),
+                Action::Slot7 => Binding::Key(Key::Key7),
+                Action::Slot8 => Binding::Key(Key::Key8),
+                Action::Slot9 => Binding::Key(Key::Key9),
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+impl KeyBindings {
+    pub fn get(&self, action: Action) -> Binding {
+        self.bindings[action as usize]
+    }
+
+    pub fn set(&mut self, action: Action, binding: Binding) {
+        self.bindings[action as usize] = binding;
+    }
+
+    /// Loads bindings from a RON struct at `path`, like `(jump: Space, break: MouseLeft)`.
+    /// Actions it doesn't mention keep their default binding, and the defaults are
+    /// returned if it can't be read or parsed.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let Ok(src) = std::fs::read_to_string(path) else {
+            return Self::default();
+        };
+        match ron::from_str(&src) {
+            Ok(bindings) => bindings,
+            Err(err) => {
+                log::warn!("failed to load key bindings from {}: {err}", path.display());
+                Self::default()
+            }
+        }
+    }
+}
+
+/// A bare identifier, like the `jump` and `Space` in `(jump: Space)`.
+struct Ident(String);
+impl<'de> Deserialize<'de> for Ident {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct IdentVisitor;
+        impl<'de> Visitor<'de> for IdentVisitor {
+            type Value = Ident;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an identifier")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Ident, E> {
+                Ok(Ident(v.to_owned()))
+            }
+        }
+        d.deserialize_identifier(IdentVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Action {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let Ident(name) = Ident::deserialize(d)?;
+        Self::from_name(&name).ok_or_else(|| de::Error::unknown_field(&name, Self::NAMES))
+    }
+}
+
+/// Bindings are written like unit enum variants (`Space`, `MouseLeft`).
+impl<'de> Deserialize<'de> for Binding {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct BindingVisitor;
+        impl<'de> Visitor<'de> for BindingVisitor {
+            type Value = Binding;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a key or mouse button name")
+            }
+
+            fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Binding, A::Error> {
+                let (Ident(name), variant) = data.variant()?;
+                variant.unit_variant()?;
+                Binding::from_name(&name)
+                    .ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(&name), &self))
+            }
+        }
+        d.deserialize_enum("Binding", &[], BindingVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBindings {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct BindingsVisitor;
+        impl<'de> Visitor<'de> for BindingsVisitor {
+            type Value = KeyBindings;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a struct of actions and their keys")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<KeyBindings, A::Error> {
+                let mut rs = KeyBindings::default();
+                while let Some(action) = map.next_key::<Action>()? {
+                    rs.set(action, map.next_value()?);
+                }
+                Ok(rs)
+            }
+        }
+        d.deserialize_struct("KeyBindings", Action::NAMES, BindingsVisitor)
+    }
+}
+
 #[derive(Default)]
 pub struct InputState {
     pub pressed_keys: HashSet<Key>,
@@ -14,8 +363,30 @@ pub struct InputState {
     pub cursor_delta: Vec2,
     pub cursor_pos: Vec2,
     pub scroll_delta: Vec2,
+    pub bindings: KeyBindings,
 }
 impl InputState {
+    pub fn binding_pressed(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.key_pressed(key),
+            Binding::Mouse(button) => self.pressed_mouse_buttons.contains(&button),
+        }
+    }
+    pub fn binding_down(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.key_down(key),
+            Binding::Mouse(button) => self.down_mouse_buttons.contains(&button),
+        }
+    }
+    /// Whether the key bound to `action` was pressed this frame.
+    pub fn action_pressed(&self, action: Action) -> bool {
+        self.binding_pressed(self.bindings.get(action))
+    }
+    /// Whether the key bound to `action` is held down.
+    pub fn action_down(&self, action: Action) -> bool {
+        self.binding_down(self.bindings.get(action))
+    }
+
     pub fn key_pressed(&self, key: Key) -> bool {
         self.pressed_keys.contains(&key)
     }
@@ -98,3 +469,47 @@ impl InputState {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_anonymous_and_named_structs() {
+        for src in [
+            "(jump: Q, break: MouseMiddle)",
+            "KeyBindings(jump: Q, break: MouseMiddle,)",
+        ] {
+            let bindings: KeyBindings = ron::from_str(src).unwrap();
+            assert_eq!(bindings.get(Action::Jump), Binding::Key(Key::Q));
+            assert_eq!(
+                bindings.get(Action::Break),
+                Binding::Mouse(MouseButton::Middle)
+            );
+            // left out, so it keeps its default
+            assert_eq!(bindings.get(Action::MoveForward), Binding::Key(Key::W));
+        }
+    }
+
+    #[test]
+    fn skips_comments() {
+        let src = "/* AZERTY */ (\n    move_forward: Z, // was W\n    move_left: Q,\n)";
+        let bindings: KeyBindings = ron::from_str(src).unwrap();
+        assert_eq!(bindings.get(Action::MoveForward), Binding::Key(Key::Z));
+        assert_eq!(bindings.get(Action::MoveLeft), Binding::Key(Key::Q));
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        assert!(ron::from_str::<KeyBindings>("(jump: NotAKey)").is_err());
+        assert!(ron::from_str::<KeyBindings>("(fly_away: Space)").is_err());
+    }
+
+    #[test]
+    fn action_names_round_trip() {
+        for (idx, action) in Action::ALL.iter().enumerate() {
+            assert_eq!(*action as usize, idx);
+            assert_eq!(Action::from_name(action.name()), Some(*action));
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use crate::world::Voxel;
+
+/// What the inventory starts out holding, filling slots from the first row on.
+pub static DEFAULT_INVENTORY: &[Voxel] = &[
+    Voxel::STONE,
+    Voxel::DIRT,
+    Voxel::GRASS,
+    Voxel::SNOW,
+    Voxel::DEAD_GRASS,
+    Voxel::MOIST_GRASS,
+    Voxel::SAND,
+    Voxel::MUD,
+    Voxel::CLAY,
+    Voxel::FIRE,
+    Voxel::MAGMA,
+    Voxel::WATER,
+    Voxel::OAK_WOOD,
+    Voxel::OAK_LEAVES,
+    Voxel::BIRCH_WOOD,
+    Voxel::BIRCH_LEAVES,
+    Voxel::SPRUCE_WOOD,
+    Voxel::SPRUCE_LEAVES,
+    Voxel::CACTUS,
+    Voxel::GOLD,
+    Voxel::MIRROR,
+    Voxel::BRIGHT,
+];
+
+/// Rows of voxel slots, one of which is selected.
+/// The row holding the selected slot is the active row, which the number keys pick from.
+pub struct Inventory {
+    /// `ROWS * ROW_LEN` slots, row by row. Empty slots hold `Voxel::AIR`.
+    slots: Vec<Voxel>,
+    /// How many voxels each slot holds, for game modes where placing uses them up.
+    counts: Vec<u32>,
+    selected: usize,
+}
+impl Default for Inventory {
+    fn default() -> Self {
+        let mut inventory = Self {
+            slots: vec![Voxel::AIR; Self::ROWS * Self::ROW_LEN],
+            counts: vec![0; Self::ROWS * Self::ROW_LEN],
+            selected: 0,
+        };
+        for (idx, voxel) in DEFAULT_INVENTORY.iter().enumerate() {
+            inventory.set(idx, *voxel);
+        }
+        inventory
+    }
+}
+impl Inventory {
+    /// Slots per row, one for each of the number keys 1-9.
+    pub const ROW_LEN: usize = 9;
+    pub const ROWS: usize = 4;
+    /// How many voxels a slot holds when a voxel is put in it.
+    pub const STACK_SIZE: u32 = 64;
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn slots(&self) -> &[Voxel] {
+        &self.slots
+    }
+
+    /// The voxel in slot `idx`, or `None` if the slot is empty or out of range.
+    pub fn get(&self, idx: usize) -> Option<Voxel> {
+        self.slots.get(idx).copied().filter(|v| *v != Voxel::AIR)
+    }
+
+    /// How many voxels slot `idx` holds. 0 if it's empty or out of range.
+    pub fn count(&self, idx: usize) -> u32 {
+        match self.get(idx) {
+            Some(_) => self.counts[idx],
+            None => 0,
+        }
+    }
+
+    /// Puts a full stack of `voxel` in slot `idx` (`Voxel::AIR` empties it),
+    /// unless it already holds `voxel`. Out of range slots are ignored.
+    pub fn set(&mut self, idx: usize, voxel: Voxel) {
+        let Some(slot) = self.slots.get_mut(idx) else {
+            return;
+        };
+        if *slot != voxel {
+            *slot = voxel;
+            self.counts[idx] = match voxel {
+                Voxel::AIR => 0,
+                _ => Self::STACK_SIZE,
+            };
+        }
+    }
+
+    /// Takes one voxel out of the selected slot, emptying it when it runs out.
+    /// Returns the voxel taken, or `None` if the slot was empty.
+    pub fn take_selected(&mut self) -> Option<Voxel> {
+        let voxel = self.selected()?;
+        self.counts[self.selected] -= 1;
+        if self.counts[self.selected] == 0 {
+            self.slots[self.selected] = Voxel::AIR;
+        }
+        Some(voxel)
+    }
+
+    /// Adds `count` of `voxel`, topping up the slots already holding it to `STACK_SIZE`
+    /// first, then spilling into empty slots in order.
+    /// Returns how many didn't fit.
+    pub fn add(&mut self, voxel: Voxel, mut count: u32) -> u32 {
+        if voxel == Voxel::AIR {
+            return count;
+        }
+        let holding = (0..self.slots.len()).filter(|&idx| self.slots[idx] == voxel);
+        let empty = (0..self.slots.len()).filter(|&idx| self.slots[idx] == Voxel::AIR);
+        let slots: Vec<usize> = holding.chain(empty).collect();
+        for idx in slots {
+            if count == 0 {
+                break;
+            }
+            let added = count.min(Self::STACK_SIZE.saturating_sub(self.counts[idx]));
+            if added > 0 {
+                self.slots[idx] = voxel;
+                self.counts[idx] += added;
+                count -= added;
+            }
+        }
+        count
+    }
+
+    /// The first slot holding `voxel`.
+    pub fn find(&self, voxel: Voxel) -> Option<usize> {
+        self.slots.iter().position(|v| *v == voxel)
+    }
+
+    pub fn selected_idx(&self) -> usize {
+        self.selected
+    }
+
+    /// The voxel in the selected slot, or `None` if it's empty.
+    pub fn selected(&self) -> Option<Voxel> {
+        self.get(self.selected)
+    }
+
+    /// The row holding the selected slot.
+    pub fn active_row(&self) -> usize {
+        self.selected / Self::ROW_LEN
+    }
+
+    /// Selects slot `idx`, clamped to the inventory.
+    pub fn select(&mut self, idx: usize) {
+        self.selected = idx.min(self.slots.len() - 1);
+    }
+
+    /// Selects the slot in column `col` of the active row.
+    pub fn select_in_row(&mut self, col: usize) {
+        self.select(self.active_row() * Self::ROW_LEN + col.min(Self::ROW_LEN - 1));
+    }
+
+    /// Moves the selection by `delta` slots, carrying over into the next or previous row
+    /// and stopping at the first and last slots.
+    pub fn scroll(&mut self, delta: i32) {
+        let idx = (self.selected as i64 + delta as i64).max(0) as usize;
+        self.select(idx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placing_uses_up_the_selected_slot() {
+        let mut inventory = Inventory::default();
+        inventory.select(1);
+        for _ in 0..Inventory::STACK_SIZE {
+            assert_eq!(inventory.take_selected(), Some(Voxel::DIRT));
+        }
+        assert_eq!(inventory.selected(), None);
+        assert_eq!(inventory.take_selected(), None);
+        // collecting dirt again fills the emptied slot
+        assert_eq!(inventory.add(Voxel::DIRT, 1), 0);
+        assert_eq!(inventory.count(1), 1);
+        assert_eq!(inventory.get(1), Some(Voxel::DIRT));
+    }
+
+    #[test]
+    fn adding_spills_full_stacks_into_empty_slots() {
+        let mut inventory = Inventory::default();
+        let first_empty = DEFAULT_INVENTORY.len();
+        // dirt's slot is full, so it all goes to the empty slots after the defaults
+        assert_eq!(inventory.add(Voxel::DIRT, 100), 0);
+        assert_eq!(inventory.count(1), Inventory::STACK_SIZE);
+        assert_eq!(inventory.count(first_empty), Inventory::STACK_SIZE);
+        assert_eq!(inventory.get(first_empty + 1), Some(Voxel::DIRT));
+        assert_eq!(
+            inventory.count(first_empty + 1),
+            100 - Inventory::STACK_SIZE
+        );
+
+        // partial stacks are topped up before taking another slot
+        inventory.select(1);
+        inventory.take_selected();
+        assert_eq!(inventory.add(Voxel::DIRT, 10), 0);
+        assert_eq!(inventory.count(1), Inventory::STACK_SIZE);
+        assert_eq!(
+            inventory.count(first_empty + 1),
+            100 - Inventory::STACK_SIZE + 9
+        );
+        assert_eq!(inventory.get(first_empty + 2), None);
+
+        // once every slot is full, the rest is returned
+        let empty_slots = (inventory.len() - first_empty - 2) as u32;
+        let overflow = 5;
+        let sand = empty_slots * Inventory::STACK_SIZE + overflow;
+        assert_eq!(inventory.add(Voxel::SAND, sand), overflow);
+        assert!((0..inventory.len()).all(|idx| inventory.count(idx) <= Inventory::STACK_SIZE));
+        assert_eq!(inventory.add(Voxel::STONE, 3), 3);
+        assert_eq!(inventory.add(Voxel::AIR, 3), 3);
+    }
+
+    #[test]
+    fn setting_the_same_voxel_keeps_the_count() {
+        let mut inventory = Inventory::default();
+        inventory.take_selected();
+        inventory.set(0, Voxel::STONE);
+        assert_eq!(inventory.count(0), Inventory::STACK_SIZE - 1);
+        inventory.set(0, Voxel::SAND);
+        assert_eq!(inventory.count(0), Inventory::STACK_SIZE);
+        inventory.set(0, Voxel::AIR);
+        assert_eq!(inventory.count(0), 0);
+    }
+
+    #[test]
+    fn selecting_resolves_to_the_assigned_voxel() {
+        let mut inventory = Inventory::default();
+        let row_len = Inventory::ROW_LEN;
+        inventory.set(2 * row_len + 4, Voxel::MIRROR);
+        inventory.set(2 * row_len + 7, Voxel::GOLD);
+
+        // the number keys pick from the active row
+        inventory.select(2 * row_len);
+        inventory.select_in_row(4);
+        assert_eq!(inventory.selected_idx(), 2 * row_len + 4);
+        assert_eq!(inventory.selected(), Some(Voxel::MIRROR));
+        inventory.select_in_row(7);
+        assert_eq!(inventory.selected(), Some(Voxel::GOLD));
+
+        // scrolling carries into the next row and stops at the ends
+        inventory.scroll(2);
+        assert_eq!(inventory.active_row(), 3);
+        assert_eq!(inventory.selected(), None);
+        inventory.scroll(100);
+        assert_eq!(inventory.selected_idx(), inventory.len() - 1);
+        inventory.scroll(-1000);
+        assert_eq!(inventory.selected_idx(), 0);
+        assert_eq!(inventory.selected(), Some(DEFAULT_INVENTORY[0]));
+    }
+}
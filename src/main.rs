@@ -1,24 +1,45 @@
+pub mod bookmarks;
+pub mod console;
 pub mod gpu;
 pub mod input;
+pub mod inventory;
 pub mod math;
 pub mod player;
+pub mod profiler;
+pub mod quality;
+pub mod resolution;
+pub mod screenshot;
+pub mod selection;
 pub mod ui;
 pub mod world;
 
-use crate::gpu::{egui::Egui, Gpu, GpuResources, Settings, WorldData};
-use crate::input::{InputState, Key};
+use crate::bookmarks::{Bookmark, Bookmarks, BOOKMARKS_PATH};
+use crate::console::Console;
+use crate::gpu::{
+    egui::Egui, CamData, DebugMode, Gpu, GpuResources, ScreenSettings, Settings, ToneMap, WorldData,
+};
+use crate::input::{Action as InputAction, InputState, KeyBindings, KEY_BINDINGS_PATH};
+use crate::inventory::Inventory;
 use crate::math::dda::HitResult;
 use crate::player::Player;
+use crate::profiler::{profile_scope, FrameTimings};
+use crate::quality::QualityManager;
+use crate::resolution::DynamicResolution;
+use crate::selection::Selection;
 use crate::world::{
-    data::Material,
-    gen::{Feature, WorldGen},
-    vox_to_chunk_pos, ChunkHeader, Node, Voxel, World, CHUNK_SIZE,
+    data::VoxelRegistry,
+    gen::{self, Feature, WorldGen},
+    vox_to_chunk_pos, ChunkHeader, Node, NodeAddr, PlaceMode, Voxel, World, WorldErr, CHUNK_SIZE,
+    MAX_REBUILDS_PER_FRAME, NODES_PER_CHUNK,
 };
 use glam::{ivec3, uvec2, uvec3, vec3, IVec3, UVec2, UVec3};
 use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
-use std::{thread::JoinHandle, time::SystemTime};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
 use winit::event::*;
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::{CursorGrabMode, Fullscreen, Window, WindowBuilder};
@@ -61,14 +82,60 @@ pub fn win_size(window: &Window) -> UVec2 {
     UVec2::from(<[u32; 2]>::from(window.inner_size()))
 }
 
+/// A minimized window has a zero sized surface, which can't be rendered to or resized to.
+pub fn is_minimized(win_size: UVec2) -> bool {
+    win_size.x == 0 || win_size.y == 0
+}
+
+/// When the frame after one started at `last_frame` may start, with at most `max_fps`
+/// frames a second (0 for no cap).
+pub fn next_frame(last_frame: Instant, max_fps: u32) -> Instant {
+    match max_fps {
+        0 => last_frame,
+        max_fps => last_frame + Duration::from_secs_f64(1.0 / max_fps as f64),
+    }
+}
+
+/// Generates a world of `size_in_chunks` chunks along each axis without a window or GPU,
+/// and prints how long each phase of generation took.
+fn bench_world_gen(size_in_chunks: u32, seed: i64) {
+    crate::world::noise::init_gradients();
+
+    let mut world = World::new(size_in_chunks.pow(3) * NODES_PER_CHUNK, size_in_chunks);
+    let world_gen = WorldGen::new(seed);
+    let stats = world_gen.populate_timed(world.min(), world.max(), &mut world);
+
+    println!("seed {seed}, {} chunks", stats.chunks);
+    println!("  noise:    {:?}", stats.noise);
+    println!("  fill:     {:?}", stats.fill);
+    println!(
+        "  features: {:?} ({} placed)",
+        stats.place_features, stats.features
+    );
+    println!("  total:    {:?}", stats.total());
+    println!("  voxels set: {}", stats.voxels_set);
+}
+
 pub fn main() {
     env_logger::init();
 
+    // `--bench-gen [chunks] [seed]` profiles world generation and exits
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("--bench-gen") {
+        let size = args.get(1).and_then(|v| v.parse().ok()).unwrap_or(8);
+        let seed = args.get(2).and_then(|v| v.parse().ok()).unwrap_or(0);
+        bench_world_gen(size, seed);
+        return;
+    }
+
     let mut fps_temp: u32 = 0;
     let mut fps: u32 = 0;
     let mut last_second = SystemTime::now();
-    let mut last_frame = SystemTime::now();
-    let mut input = InputState::default();
+    let mut last_frame = Instant::now();
+    let mut input = InputState {
+        bindings: KeyBindings::load(KEY_BINDINGS_PATH),
+        ..Default::default()
+    };
     let mut cursor_hidden = true;
 
     let event_loop = EventLoop::new();
@@ -99,16 +166,17 @@ pub fn main() {
             _ => {}
         },
         Event::RedrawRequested(_) => {
-            let last_frame_age = SystemTime::now()
-                .duration_since(last_frame)
-                .unwrap()
-                .as_millis();
-
-            if last_frame_age < (1000 / 60) {
+            if Instant::now() < next_frame(last_frame, game_state.max_fps) {
+                // redrawn early by the OS, the frame rate cap will ask again in time
                 return;
             }
-            last_frame = SystemTime::now();
             let win_size = win_size(&window);
+            if is_minimized(win_size) {
+                // there's no surface to render to, so wait until the window is restored
+                input.finish_frame();
+                return;
+            }
+            last_frame = Instant::now();
 
             let update_rs = if cursor_hidden {
                 game_state.update(&input)
@@ -116,12 +184,26 @@ pub fn main() {
                 UpdateResult::default()
             };
 
-            if input.key_pressed(Key::T) {
-                cursor_hidden = !cursor_hidden;
+            if input.action_pressed(InputAction::ToggleConsole) {
+                game_state.console.open ^= true;
+                cursor_hidden = !game_state.console.open;
                 hide_cursor(&window, cursor_hidden);
             }
-            if input.key_pressed(Key::F) {
-                toggle_fullscreen(&window);
+            // While the console is open, keys are typed into it instead.
+            if !game_state.console.open {
+                if input.action_pressed(InputAction::ToggleOverlay) {
+                    cursor_hidden = !cursor_hidden;
+                    hide_cursor(&window, cursor_hidden);
+                }
+                if input.action_pressed(InputAction::ToggleFullscreen) {
+                    toggle_fullscreen(&window);
+                }
+                if input.action_pressed(InputAction::Screenshot) {
+                    match game_state.screenshot() {
+                        Ok(path) => log::info!("saved a screenshot to {}", path.display()),
+                        Err(err) => log::error!("failed to save a screenshot: {err}"),
+                    }
+                }
             }
 
             let frame_in = FrameInput {
@@ -134,13 +216,24 @@ pub fn main() {
             let frame_rs = game_state.frame(&window, &update_rs, &frame_in, &input, &mut egui);
             match frame_rs {
                 Ok(_) => {}
-                Err(wgpu::SurfaceError::Lost) => println!("SurfaceError: Lost"),
+                // still lost after reconfiguring once, so try again at the window's
+                // current size next frame
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                    log::warn!("surface still lost after reconfiguring");
+                    game_state.gpu.resize(win_size);
+                }
                 Err(wgpu::SurfaceError::OutOfMemory) => *flow = ControlFlow::Exit,
                 Err(e) => eprintln!("{e:?}"),
             };
 
             input.finish_frame();
 
+            // Only the time this frame took to update and render, not the time spent waiting
+            // on the frame rate cap, or a cap below what the hardware manages looks like load.
+            // The GPU's time shows up here too, as waiting for the next surface texture.
+            let work_time = last_frame.elapsed();
+            game_state.adapt_to_frame_time(work_time);
+
             fps_temp += 1;
             let now = SystemTime::now();
             if now.duration_since(last_second).unwrap().as_secs() >= 1 {
@@ -150,36 +243,35 @@ pub fn main() {
             }
         }
         Event::MainEventsCleared => {
-            window.request_redraw();
+            // Sleep until the next event while minimized, instead of spinning on empty frames.
+            // Likewise, sleep until the frame rate cap allows the next frame.
+            let next_frame = next_frame(last_frame, game_state.max_fps);
+            if is_minimized(win_size(&window)) {
+                *flow = ControlFlow::Wait;
+            } else if Instant::now() < next_frame {
+                *flow = ControlFlow::WaitUntil(next_frame);
+            } else {
+                *flow = ControlFlow::Poll;
+                window.request_redraw();
+            }
         }
         _ => {}
     });
 }
 
-pub static INVENTORY: &[Voxel] = &[
-    Voxel::STONE,
-    Voxel::DIRT,
-    Voxel::GRASS,
-    Voxel::SNOW,
-    Voxel::DEAD_GRASS,
-    Voxel::MOIST_GRASS,
-    Voxel::SAND,
-    Voxel::MUD,
-    Voxel::CLAY,
-    Voxel::FIRE,
-    Voxel::MAGMA,
-    Voxel::WATER,
-    Voxel::OAK_WOOD,
-    Voxel::OAK_LEAVES,
-    Voxel::BIRCH_WOOD,
-    Voxel::BIRCH_LEAVES,
-    Voxel::SPRUCE_WOOD,
-    Voxel::SPRUCE_LEAVES,
-    Voxel::CACTUS,
-    Voxel::GOLD,
-    Voxel::MIRROR,
-    Voxel::BRIGHT,
-];
+/// A reproducible path traced still, accumulating samples while the world,
+/// player and sun are frozen.
+pub struct StillRender {
+    /// How many frames of samples to accumulate before stopping.
+    /// Frames split over several dispatches (see `GameState::max_samples_per_dispatch`)
+    /// count each of them.
+    pub target_frames: u32,
+    pub finished: bool,
+}
+
+/// Turning the camera further than this (in degrees) in one frame restarts the
+/// path tracer's accumulated samples, instead of reprojecting them.
+pub const MAX_REPROJECT_TURN: f32 = 15.0;
 
 pub struct FrameInput {
     pub fps: u32,
@@ -192,15 +284,35 @@ pub struct UpdateResult {
     pub hit_result: Option<HitResult>,
     pub world_changed: bool,
     pub player_moved: bool,
+    /// How far (in degrees) the camera turned, if the player only turned and didn't move.
+    pub camera_turn: f32,
 }
 
 pub struct GameState {
     pub gpu: Gpu,
     pub gpu_res: GpuResources,
     pub settings: Settings,
+    /// The settings of the right half of the screen when `settings.split_screen` is set,
+    /// to compare against `settings` side by side. The sun, debug view, g-buffer and
+    /// supersampling are shared, and always come from `settings`.
+    pub split_settings: Settings,
+    /// Whether the shader settings panel edits `split_settings` instead of `settings`.
+    pub editing_split: bool,
 
     pub player: Player,
-    pub inv_sel: u8,
+    pub prev_cam_data: Option<CamData>,
+    pub inventory: Inventory,
+    pub last_edit_pos: Option<IVec3>,
+    /// When non-zero, edits place/break a sphere of this radius.
+    pub brush_radius: u32,
+    /// Which voxels placing is allowed to overwrite. Breaking always replaces anything.
+    pub place_mode: PlaceMode,
+    /// A box picked with B (and cleared with C), for region edits from the console.
+    pub selection: Selection,
+    /// Show the coordinate and compass HUD.
+    pub show_compass: bool,
+    /// The still being rendered, if any. See `start_still`.
+    pub still: Option<StillRender>,
 
     pub world: World,
     pub world_depth: u32,
@@ -208,23 +320,67 @@ pub struct GameState {
 
     pub resize_result_tex: bool,
     pub vertical_samples: u32,
+    pub dynamic_res: DynamicResolution,
+    pub quality: QualityManager,
+    /// The frame rate to limit rendering to, or 0 for no limit.
+    pub max_fps: u32,
     pub path_tracing: bool,
+    pub debug_mode: DebugMode,
+    pub tone_map: ToneMap,
+    pub screen_settings: ScreenSettings,
 
     pub world_gen: Arc<WorldGen>,
+    /// How much chunk building favors the view direction over distance,
+    /// from 0 (only distance) to 1 (only view direction).
+    pub build_view_weight: f32,
     pub sun_angle: f32,
+    /// Place the sun from a latitude, day of the year and time of day, instead of `sun_angle`.
+    pub sun_from_time: bool,
+    pub latitude: f32,
+    pub day_of_year: f32,
+    /// In hours since midnight.
+    pub time_of_day: f32,
+    /// In-game minutes that pass every frame. 0 stops the day/night cycle.
+    pub day_cycle_speed: f32,
     pub frame_count: u32,
-    pub voxel_materials: Vec<Material>,
+    pub voxels: VoxelRegistry,
     pub dirty_chunks: Vec<IVec3>,
     pub chunk_builders: Vec<ChunkBuilder>,
     pub max_threads: u32,
     pub feature_receiver: Receiver<Feature>,
     pub feature_sender: Sender<Feature>,
     pub features_queue: Vec<Feature>,
+    /// The chunk (as an index into `World::chunks`) that was last checked for compaction.
+    pub compact_cursor: u32,
     pub build_chunks: bool,
     pub move_world: bool,
+    /// Freezes the world (chunk streaming, building and features) while
+    /// the player and renderer keep running.
+    pub paused: bool,
+    /// Turn the player toward open terrain once the chunk they spawned in is built.
+    pub face_open_on_spawn: bool,
+    pub xray: bool,
+    /// Draw the player's collision box and the voxels it could collide with.
+    pub show_hitboxes: bool,
+    /// The most samples per pixel the path tracer takes in a single dispatch, so one
+    /// dispatch can't run long enough to trigger a GPU timeout. Frames with more
+    /// `samples_per_pixel` are split over several dispatches, accumulated together.
+    pub max_samples_per_dispatch: u32,
+    /// How long the dispatches of a frame split by `max_samples_per_dispatch` may take.
+    /// The ones left once it has passed are dropped; accumulating over later frames
+    /// makes up for them while the camera stays still.
+    pub sample_time_budget: Duration,
+    pub bookmarks: Bookmarks,
+    pub console: Console,
+    pub timings: FrameTimings,
 }
 impl GameState {
     pub fn new(win_size: UVec2, gpu: Gpu, max_nodes: u32) -> Self {
+        Self::with_world_size(win_size, gpu, max_nodes, UVec3::splat(15))
+    }
+
+    /// Like `new`, with a world of `world_size` chunks along each axis.
+    pub fn with_world_size(win_size: UVec2, gpu: Gpu, max_nodes: u32, world_size: UVec3) -> Self {
         let win_aspect = win_size.x as f32 / win_size.y as f32;
 
         let mut settings = Settings::default();
@@ -232,19 +388,27 @@ impl GameState {
         settings.sun_intensity = 4.0;
         settings.sky_color = [0.81, 0.93, 1.0];
         settings.samples_per_pixel = 1;
+        settings.cloud_coverage = 0.4;
+        settings.cloud_altitude = 200.0;
+        settings.taa_blend = 0.8;
+        settings.sun_softness = 0.14;
+        settings.ao_samples = 4;
+        settings.ao_radius = 1.5;
+        settings.void_color = [0.03; 3];
+        settings.rr_min_bounces = 2;
+        settings.supersample = 1;
 
         let world_depth = 9;
-        let world_size = 15;
         let vertical_samples = 800;
 
-        let world = World::new(max_nodes, world_size);
+        let world = World::new_dims(max_nodes, world_size);
 
         let world_gen = WorldGen::new(fastrand::i64(..));
         let mut dirty_chunks = vec![];
 
-        for x in 0..world_size {
-            for y in 0..world_size {
-                for z in 0..world_size {
+        for x in 0..world_size.x {
+            for y in 0..world_size.y {
+                for z in 0..world_size.z {
                     dirty_chunks.push(uvec3(x, y, z).as_ivec3());
                 }
             }
@@ -254,13 +418,15 @@ impl GameState {
             vertical_samples,
         );
 
-        let center = UVec3::splat(world_size * CHUNK_SIZE).as_vec3() * 0.5;
-        let player = Player::new(center, 0.2);
+        let center = world.size3().as_vec3() * 0.5;
+        let mut player = Player::new(center, 0.2);
+        // far enough to see across the whole world
+        player.far = player.far.max(world.size3().as_vec3().length());
 
         settings.sun_pos = vec3(
             0.0f32.to_radians().sin() * 500.0,
             0.0f32.to_radians().cos() * 500.0,
-            world.size() as f32 * 0.5,
+            world.size3().z as f32 * 0.5,
         )
         .to_array();
 
@@ -279,11 +445,11 @@ impl GameState {
             .world_data
             .write(&gpu, &WorldData::from(&world));
 
-        let voxel_materials = world::data::VOXEL_MATERIALS.to_vec();
+        let voxels = VoxelRegistry::default();
         gpu_res
             .buffers
             .voxel_materials
-            .write_slice(&gpu, 0, &voxel_materials);
+            .write_slice(&gpu, 0, voxels.materials());
 
         let max_threads = std::thread::available_parallelism()
             .map(|n| n.get() as u32)
@@ -295,47 +461,137 @@ impl GameState {
             gpu,
             gpu_res,
             settings,
+            split_settings: settings,
+            editing_split: false,
 
             player,
-            inv_sel: 0,
+            prev_cam_data: None,
+            inventory: Inventory::default(),
+            last_edit_pos: None,
+            brush_radius: 0,
+            place_mode: PlaceMode::ReplaceEmpty,
+            selection: Selection::default(),
+            show_compass: true,
+            still: None,
             world,
 
             world_gen: Arc::new(world_gen),
+            build_view_weight: 0.5,
             world_depth,
             world_dirty: false,
 
             vertical_samples,
+            dynamic_res: DynamicResolution::default(),
+            quality: QualityManager::default(),
+            max_fps: 60,
             resize_result_tex: false,
             path_tracing: false,
+            debug_mode: DebugMode::Off,
+            tone_map: ToneMap::None,
+            screen_settings: ScreenSettings::default(),
             sun_angle: 0.0,
+            sun_from_time: false,
+            latitude: 45.0,
+            day_of_year: 172.0,
+            time_of_day: 12.0,
+            day_cycle_speed: 0.0,
             frame_count: 0,
-            voxel_materials,
+            voxels,
             dirty_chunks,
             chunk_builders: vec![],
             max_threads,
             feature_sender,
             feature_receiver,
             features_queue: vec![],
+            compact_cursor: 0,
             build_chunks: true,
             move_world: true,
+            paused: false,
+            face_open_on_spawn: true,
+            xray: false,
+            show_hitboxes: false,
+            max_samples_per_dispatch: 8,
+            sample_time_budget: Duration::from_millis(50),
+            bookmarks: Bookmarks::load(BOOKMARKS_PATH),
+            console: Console::default(),
+            timings: FrameTimings::default(),
         }
     }
 
+    /// Whether the path tracer renders the screen, instead of the ray tracer.
+    pub fn uses_path_tracer(&self) -> bool {
+        self.path_tracing
+    }
+
+    /// Whether the right half of the screen is rendered with `split_settings`.
+    pub fn split_screen(&self) -> bool {
+        self.settings.split_screen != 0
+    }
+
+    /// Moves the sun to where it is at `latitude`, `day_of_year` and `time_of_day`.
+    /// The settings still have to be written afterwards.
+    pub fn update_sun_pos(&mut self) {
+        const SUN_DIST: f32 = 500.0;
+        let dir = crate::math::sun_dir(self.latitude, self.day_of_year, self.time_of_day);
+        let center = self.world.size3().as_vec3() * vec3(0.5, 0.0, 0.5);
+        self.settings.sun_pos = (center + dir * SUN_DIST).to_array();
+    }
+
+    /// `settings`, with the features the quality manager turned off disabled.
+    pub fn gpu_settings(&self) -> Settings {
+        let mut settings = self.settings;
+        self.quality.apply(&mut settings);
+        settings
+    }
+
+    /// `split_settings` like `gpu_settings`, with the settings both halves share from `settings`.
+    pub fn split_gpu_settings(&self) -> Settings {
+        let mut settings = self.split_settings;
+        settings.sun_pos = self.settings.sun_pos;
+        settings.debug_mode = self.settings.debug_mode;
+        settings.write_gbuffer = self.settings.write_gbuffer;
+        settings.split_screen = self.settings.split_screen;
+        settings.supersample = self.settings.supersample;
+        self.quality.apply(&mut settings);
+        settings
+    }
+
+    /// Uploads `settings` and `split_settings` to the GPU.
+    pub fn write_settings(&self) {
+        let buffers = &self.gpu_res.buffers;
+        buffers.settings.write(&self.gpu, &self.gpu_settings());
+        buffers
+            .split_settings
+            .write(&self.gpu, &self.split_gpu_settings());
+    }
+
+    /// CPU time spent on world operations during the last update.
+    pub fn frame_timings(&self) -> &FrameTimings {
+        &self.timings
+    }
+
     pub fn move_world(&mut self) -> bool {
+        // the chunks are queued to rebuild by `World::update`, and handed out a batch a frame
         let regenerate = self.world.update(self.player.pos.as_ivec3());
         let world_moved = !regenerate.is_empty();
+        let mut roots = Vec::with_capacity(regenerate.len());
         for pos in regenerate {
-            self.dirty_chunks.push(pos);
-
             let idx = self.world.chunk_idx(pos).unwrap();
             let root = self.world.chunks[idx as usize].root;
             *self.world.mut_node(root) = Node::new(Voxel::AIR);
-            self.gpu_res.buffers.nodes.write(
-                &self.gpu,
-                self.world.chunk_nodes_offset(idx) as u64,
-                &self.world.chunk_nodes(idx)[0..1],
-            );
+            roots.push(root);
         }
+        // cleared straight away, so stale chunks don't show until they're rebuilt
+        let regions: Vec<_> = roots
+            .iter()
+            .map(|&root| {
+                (
+                    root as u64,
+                    &self.world.nodes()[root as usize..root as usize + 1],
+                )
+            })
+            .collect();
+        self.gpu_res.buffers.nodes.write_many(&self.gpu, &regions);
         if world_moved {
             self.gpu_res
                 .buffers
@@ -349,17 +605,108 @@ impl GameState {
         world_moved
     }
 
+    /// Replaces the world generator and rebuilds every chunk with it.
+    pub fn regenerate_world(&mut self, world_gen: WorldGen) {
+        // builders write straight into the world, so let them finish first
+        for builder in self.chunk_builders.drain(..) {
+            _ = builder.thread.join();
+        }
+        while self.feature_receiver.try_recv().is_ok() {}
+        self.features_queue.clear();
+        self.world_gen = Arc::new(world_gen);
+
+        self.dirty_chunks.clear();
+        for pos in self.world.all_chunk_positions() {
+            self.dirty_chunks.push(pos);
+
+            let idx = self.world.chunk_idx(pos).unwrap();
+            let chunk = self.world.chunks[idx as usize].clone();
+            self.world.set_chunk_loaded(&chunk, false);
+            *self.world.mut_node(chunk.root) = Node::new(Voxel::AIR);
+            self.gpu_res.buffers.nodes.write(
+                &self.gpu,
+                self.world.chunk_nodes_offset(idx) as u64,
+                &self.world.chunk_nodes(idx)[0..1],
+            );
+        }
+    }
+
+    /// Saves the world with `World::save_to_file`.
+    pub fn save_world(&mut self, path: &Path) -> Result<(), String> {
+        // builders write straight into the world, so let them finish first.
+        // They're collected as usual next frame, so chunks still building are saved unloaded.
+        while self.chunk_builders.iter().any(|b| !b.thread.is_finished()) {
+            std::thread::yield_now();
+        }
+        self.world.save_to_file(path).map_err(|err| err.to_string())
+    }
+
+    /// Replaces the world with one saved by `World::save_to_file`, which must be the
+    /// same size. Chunks that weren't generated when it was saved are built again.
+    pub fn load_world(&mut self, path: &Path) -> Result<(), String> {
+        let world = World::load_from_file(path).map_err(|err| err.to_string())?;
+        let (size, expected) = (world.size_in_chunks3(), self.world.size_in_chunks3());
+        if size != expected {
+            return Err(format!(
+                "saved world is {}x{}x{} chunks, this one is {}x{}x{}",
+                size.x, size.y, size.z, expected.x, expected.y, expected.z
+            ));
+        }
+        // builders write straight into the world, so let them finish first
+        for builder in self.chunk_builders.drain(..) {
+            _ = builder.thread.join();
+        }
+        while self.feature_receiver.try_recv().is_ok() {}
+        self.features_queue.clear();
+        self.world = world;
+
+        self.dirty_chunks.clear();
+        for pos in self.world.all_chunk_positions() {
+            if !self.world.is_chunk_loaded(pos) {
+                self.dirty_chunks.push(pos);
+            }
+        }
+        self.gpu_res
+            .ensure_node_capacity(&self.gpu, self.world.nodes().len() as u32);
+        self.gpu_res
+            .buffers
+            .nodes
+            .write(&self.gpu, 0, self.world.nodes());
+        self.gpu_res
+            .buffers
+            .chunks
+            .write(&self.gpu, 0, &self.world.chunks);
+        self.gpu_res
+            .buffers
+            .world_data
+            .write(&self.gpu, &WorldData::from(&self.world));
+        self.upload_edit(vec![]);
+        Ok(())
+    }
+
+    /// How urgently the chunk at `pos` should be built, lower being more urgent.
+    /// Blends the distance from the player with how far the chunk is from
+    /// the view direction, according to `build_view_weight`.
+    pub fn chunk_build_priority(&self, pos: IVec3) -> f32 {
+        let facing = self.player.facing();
+        let weight = self.build_view_weight;
+        self.world
+            .chunk_build_priority(pos, self.player.pos, facing, weight)
+    }
+
     pub fn build_dirty_chunks(&mut self) {
+        // Drop chunks that left the world, and duplicates queued by repeated world moves.
+        let mut queued = HashSet::new();
         self.dirty_chunks
-            .retain(|pos| self.world.chunk_idx(*pos).is_some());
-        let player_pos = self.player.pos;
-        self.dirty_chunks.sort_by(|a, b| {
-            let a_center = (*a * CHUNK_SIZE as i32 + CHUNK_SIZE as i32 / 2).as_vec3();
-            let b_center = (*b * CHUNK_SIZE as i32 + CHUNK_SIZE as i32 / 2).as_vec3();
-            let a_dist = a_center.distance_squared(player_pos);
-            let b_dist = b_center.distance_squared(player_pos);
-            b_dist.total_cmp(&a_dist)
+            .retain(|pos| self.world.chunk_idx(*pos).is_some() && queued.insert(*pos));
+        // Sorted so the most important chunk is last, to be popped first.
+        let mut dirty_chunks = std::mem::take(&mut self.dirty_chunks);
+        dirty_chunks.sort_by(|a, b| {
+            let a = self.chunk_build_priority(*a);
+            let b = self.chunk_build_priority(*b);
+            b.total_cmp(&a)
         });
+        self.dirty_chunks = dirty_chunks;
 
         while self.chunk_builders.len() < self.max_threads as usize && self.dirty_chunks.len() > 0 {
             let pos = self.dirty_chunks.pop().unwrap();
@@ -367,11 +714,18 @@ impl GameState {
             let max = min + IVec3::splat(CHUNK_SIZE as i32);
             let idx = self.world.chunk_idx(pos).unwrap();
             let chunk = self.world.chunks[idx as usize].clone();
+            // held until the chunk is built, so edits wait for the builder instead of racing it
+            if !self.world.lock_chunk(chunk.alloc, "chunk builder") {
+                self.dirty_chunks.push(pos);
+                break;
+            }
             self.world.reset_alloc(chunk.alloc);
 
             if let Some(voxel) = self.world_gen.chunk_voxel(min, max) {
                 // the world gen determined this chunk can be represented by a single voxel type
                 *self.world.mut_node(chunk.root) = Node::new(voxel);
+                self.world.set_chunk_loaded(&chunk, true);
+                self.world.unlock_chunk(chunk.alloc);
                 self.gpu_res.buffers.nodes.write(
                     &self.gpu,
                     chunk.root as u64,
@@ -387,8 +741,7 @@ impl GameState {
 
             let thread = std::thread::spawn(move || {
                 let world = world_ptr.get();
-                // let alloc = chunk_clone.alloc;
-                // world.lock_chunk(alloc);
+                let alloc = chunk_clone.alloc;
 
                 let rs = match world_gen.build_chunk(chunk_clone, min, world, feature_sender) {
                     Ok(_) => Some(pos),
@@ -406,7 +759,7 @@ impl GameState {
                 //     .is_ok()
                 //     .then_some(pos);
 
-                // world.unlock_chunk(alloc);
+                world.unlock_chunk(alloc);
                 rs
             });
             self.chunk_builders
@@ -414,6 +767,22 @@ impl GameState {
         }
     }
 
+    /// While no chunks are being built, compacts one chunk per call, cycling through
+    /// the world, so nodes left unreachable by edits are reclaimed before they run out.
+    pub fn compact_next_chunk(&mut self, upload_chunks: &mut HashSet<ChunkHeader>) {
+        self.compact_cursor = (self.compact_cursor + 1) % self.world.chunk_count();
+        let chunk = self.world.chunks[self.compact_cursor as usize].clone();
+        if !self.world.lock_chunk(chunk.alloc, "compact_next_chunk") {
+            return;
+        }
+        let freed = self.world.compact_chunk(&chunk);
+        self.world.unlock_chunk(chunk.alloc);
+        if freed > 0 {
+            log::debug!("compacted chunk {}, freeing {freed} nodes", chunk.alloc);
+            upload_chunks.insert(chunk);
+        }
+    }
+
     pub fn finish_chunk_builders(&mut self, upload_chunks: &mut HashSet<ChunkHeader>) {
         for i in (0..self.chunk_builders.len()).rev() {
             if !self.chunk_builders[i].thread.is_finished() {
@@ -429,10 +798,20 @@ impl GameState {
             // `pos` was the global chunk coordinate we started writing to.
             // But if the wolrd moved, the region we wrote to may now
             // represent a chunk at a different coordinate.
+            self.world.set_chunk_loaded(&builder.chunk, true);
             upload_chunks.insert(builder.chunk);
         }
     }
 
+    /// Drops features that have waited too long to be placed, and the oldest
+    /// features once the queue is over capacity, so it can't grow without bound.
+    pub fn evict_features(&mut self) {
+        let evicted = gen::evict_features(&mut self.features_queue);
+        if evicted > 0 {
+            log::debug!("evicted {evicted} features that couldn't be placed");
+        }
+    }
+
     pub fn place_features(&mut self, upload_chunks: &mut HashSet<ChunkHeader>) {
         // if self.chunk_builders.len() > 0 {
         //     return;
@@ -492,10 +871,26 @@ impl GameState {
                     }
                 }
             }
+            // Skip features that would grow into terrain or another feature.
+            if self.features_queue[i].is_obstructed(&self.world) {
+                _ = self.features_queue.remove(i);
+                continue;
+            }
             // We can now remove it from the queue and place it.
 
+            let mut locked = vec![];
             for idx in &lock_chunks {
-                self.world.lock_chunk(*idx);
+                if !self.world.lock_chunk(*idx, "place_features") {
+                    break;
+                }
+                locked.push(*idx);
+            }
+            if locked.len() != lock_chunks.len() {
+                // Couldn't get every lock, try placing this feature again next frame.
+                for idx in locked {
+                    self.world.unlock_chunk(idx);
+                }
+                continue;
             }
             let feature = self.features_queue.remove(i);
             feature.place(|pos, vox| {
@@ -521,6 +916,43 @@ impl GameState {
         }
     }
 
+    /// Ctrl + a number key saves the viewpoint to that bookmark slot,
+    /// and Alt + a number key jumps back to it.
+    fn handle_bookmark_keys(&mut self, input: &InputState) {
+        for (slot, action) in InputAction::SLOTS.into_iter().enumerate() {
+            if !input.action_pressed(action) {
+                continue;
+            }
+            if input.action_down(InputAction::SaveBookmark) {
+                self.bookmarks.slots[slot] = Some(Bookmark::of(&self.player));
+                if let Err(err) = self.bookmarks.save(BOOKMARKS_PATH) {
+                    log::warn!("failed to save bookmarks: {err}");
+                }
+            } else if input.action_down(InputAction::LoadBookmark) {
+                if let Some(bookmark) = self.bookmarks.slots[slot] {
+                    bookmark.apply(&mut self.player);
+                }
+            }
+        }
+    }
+
+    /// A number key 1-9 (without Ctrl or Alt, which are for bookmarks) selects that
+    /// slot of the active inventory row.
+    fn handle_hotbar_keys(&mut self, input: &InputState) {
+        if self.console.open
+            || input.action_down(InputAction::SaveBookmark)
+            || input.action_down(InputAction::LoadBookmark)
+        {
+            return;
+        }
+        let keys = &InputAction::SLOTS[1..=Inventory::ROW_LEN];
+        for (col, action) in keys.iter().enumerate() {
+            if input.action_pressed(*action) {
+                self.inventory.select_in_row(col);
+            }
+        }
+    }
+
     fn check_player_interactions(&mut self, input: &InputState) -> Option<HitResult> {
         let hit_result = self.player.cast_ray(&self.world);
 
@@ -528,23 +960,44 @@ impl GameState {
             Place,
             Break,
         }
-        let action = if input.left_button_pressed()
-            || (input.left_button_down() & input.key_down(Key::LControl))
-        {
+        let breaking = match self.player.mode.break_frames() {
+            0 => {
+                input.action_pressed(InputAction::Break)
+                    || (input.action_down(InputAction::Break)
+                        & input.action_down(InputAction::HoldEdit))
+            }
+            _ => self.player.mine(
+                hit_result.map(|hit| hit.pos),
+                input.action_down(InputAction::Break),
+            ),
+        };
+        // a voxel that took a while to mine starts a new stroke, like a fresh click
+        let pressed = input.action_pressed(InputAction::Break)
+            || input.action_pressed(InputAction::Place)
+            || breaking;
+        let action = if breaking {
             Some(Action::Break)
-        } else if input.right_button_pressed()
-            || (input.right_button_down() & input.key_down(Key::LControl))
+        } else if input.action_pressed(InputAction::Place)
+            || (input.action_down(InputAction::Place) & input.action_down(InputAction::HoldEdit))
         {
             Some(Action::Place)
         } else {
             None
         };
 
+        if action.is_none() {
+            self.last_edit_pos = None;
+        }
+
         let set_vox = match action {
             Some(Action::Break) => Some(Voxel::AIR),
-            Some(Action::Place) => Some(INVENTORY[self.inv_sel as usize]),
+            Some(Action::Place) => self.inventory.selected(),
             None => None,
         };
+        let mode = match action {
+            Some(Action::Place) => self.place_mode,
+            _ => PlaceMode::ReplaceAny,
+        };
         let set_pos = match (action, hit_result) {
             (Some(Action::Break), Some(hit)) => Some(hit.pos),
             (Some(Action::Place), Some(hit)) => Some(hit.pos + hit.face),
@@ -552,23 +1005,105 @@ impl GameState {
         };
 
         if let (Some(pos), Some(vox)) = (set_pos, set_vox) {
-            for range in self.world.set_voxel_collected(pos, vox).unwrap() {
-                self.gpu_res.buffers.nodes.write(
-                    &self.gpu,
-                    range.start as u64,
-                    &self.world.nodes()[range.start as usize..range.end as usize],
-                );
+            if !self.player.mode.unlimited_voxels() {
+                self.edit_counted(pos, vox, mode);
+                return hit_result;
             }
+            // While dragging, fill the gap between the last edited voxel and
+            // this one so fast cursor movement still paints a continuous line.
+            let line_start = match self.last_edit_pos {
+                Some(last) if !pressed => last,
+                _ => pos,
+            };
+            self.last_edit_pos = Some(pos);
 
-            self.gpu_res
-                .resize_result_texture(&self.gpu, self.gpu_res.result_texture.size());
-            self.frame_count = 0;
+            let (edit_min, edit_max) = match self.brush_radius {
+                0 => (line_start.min(pos), line_start.max(pos)),
+                r => (pos - r as i32, pos + r as i32),
+            };
+            // an edit that fails partway is rolled back, instead of leaving half a stroke
+            let rs = self
+                .world
+                .edit("player edit", |edit| match self.brush_radius {
+                    0 => edit.set_line(line_start, pos, vox, mode),
+                    // replacing anything can fill whole nodes at once
+                    r if mode == PlaceMode::ReplaceAny => edit.fill_sphere(pos, r, vox),
+                    r => edit.set_sphere(pos, r, vox, 0.0, mode),
+                });
+            let ranges = rs.unwrap_or_else(|err| {
+                log::warn!("failed to edit voxels ({err:?}), rolled back");
+                vec![]
+            });
+            self.world.update_light(edit_min, edit_max);
+            self.upload_edit(ranges);
         }
         hit_result
     }
 
+    /// Sets the one voxel at `pos`, for game modes where voxels are counted:
+    /// placing takes a voxel out of the selected slot, and breaking collects what was there.
+    /// The brush and drag lines don't apply.
+    fn edit_counted(&mut self, pos: IVec3, voxel: Voxel, mode: PlaceMode) {
+        let Ok(prev) = self.world.get_voxel(pos) else {
+            return;
+        };
+        if prev == voxel || !mode.can_replace(prev) {
+            return;
+        }
+        let rs = self
+            .world
+            .edit("player edit", |edit| edit.set_voxel(pos, voxel));
+        let ranges = match rs {
+            Ok(ranges) => ranges,
+            Err(err) => {
+                log::warn!("failed to edit voxels ({err:?}), rolled back");
+                return;
+            }
+        };
+        // only an edit that went through uses up or collects a voxel
+        match voxel {
+            Voxel::AIR => _ = self.inventory.add(prev, 1),
+            _ => _ = self.inventory.take_selected(),
+        }
+        self.world.update_light(pos, pos);
+        self.upload_edit(ranges);
+    }
+
+    /// Uploads the nodes changed by an edit, and restarts the accumulated image.
+    fn upload_edit(&mut self, ranges: Vec<Range<NodeAddr>>) {
+        let regions: Vec<_> = (ranges.into_iter())
+            .map(|range| {
+                let nodes = &self.world.nodes()[range.start as usize..range.end as usize];
+                (range.start as u64, nodes)
+            })
+            .collect();
+        self.gpu_res.buffers.nodes.write_many(&self.gpu, &regions);
+        self.gpu_res
+            .resize_result_texture(&self.gpu, self.gpu_res.result_size());
+        self.frame_count = 0;
+    }
+
+    /// Sets the selected voxels that `mode` allows to `voxel`.
+    /// Rolls the whole edit back if it fails partway.
+    pub fn fill_selection(&mut self, voxel: Voxel, mode: PlaceMode) -> Result<(), WorldErr> {
+        let Some((min, max)) = self.selection.region() else {
+            return Ok(());
+        };
+        let rs = self.world.edit("fill_selection", |edit| match mode {
+            PlaceMode::ReplaceAny => edit.set_voxels(min, max, voxel),
+            _ => edit.fill_box(min, max, voxel, mode),
+        });
+        let ranges = rs.clone().unwrap_or_default();
+        self.world.update_light(min, max);
+        self.upload_edit(ranges);
+        rs.map(|_| ())
+    }
+
     fn on_resize(&mut self, new_size: UVec2) {
-        let prev_result_size = self.gpu_res.result_texture.size();
+        if is_minimized(new_size) {
+            return;
+        }
+        let prev_result_size = self.gpu_res.result_size();
         let new_aspect = new_size.x as f32 / new_size.y as f32;
         let prev_aspect = prev_result_size.x as f32 / prev_result_size.y as f32;
 
@@ -584,13 +1119,112 @@ impl GameState {
         }
     }
 
-    pub fn update(&mut self, input: &InputState) -> UpdateResult {
-        let mut output = UpdateResult::default();
+    /// Runs the path tracer over as many dispatches as `samples_per_pixel` needs
+    /// under `max_samples_per_dispatch`, stopping at a still's target.
+    /// In split screen, the half with more samples decides how many dispatches there are.
+    fn dispatch_path_tracer(&mut self, cam_data: CamData) {
+        let mut settings = self.gpu_settings();
+        let mut split_settings = self.split_screen().then(|| self.split_gpu_settings());
+        let samples = match &split_settings {
+            Some(split) => settings.samples_per_pixel.max(split.samples_per_pixel),
+            None => settings.samples_per_pixel,
+        };
+        let (dispatches, _) = gpu::split_samples(samples, self.max_samples_per_dispatch);
+        for settings in std::iter::once(&mut settings).chain(&mut split_settings) {
+            settings.samples_per_pixel = settings.samples_per_pixel.max(1).div_ceil(dispatches);
+        }
+        let dispatches = match &self.still {
+            Some(still) => dispatches.min(still.target_frames.saturating_sub(self.frame_count)),
+            None => dispatches,
+        };
+        self.gpu_res.dispatch_path_tracer(
+            &self.gpu,
+            &settings,
+            split_settings.as_ref(),
+            cam_data,
+            &mut self.frame_count,
+            dispatches,
+            self.sample_time_budget,
+        );
+    }
+
+    /// Whether a still is being rendered and has all of its samples.
+    pub fn still_finished(&self) -> bool {
+        self.still
+            .as_ref()
+            .is_some_and(|still| still.finished || self.frame_count >= still.target_frames)
+    }
+
+    /// Saves the last rendered frame to a PNG in `screenshot::SCREENSHOTS_DIR`, returning its path.
+    /// With the path tracer, this is the accumulated `prev_result_texture`.
+    pub fn screenshot(&self) -> Result<std::path::PathBuf, String> {
+        let tex = match self.uses_path_tracer() {
+            true => &self.gpu_res.prev_result_texture,
+            false => &self.gpu_res.result_texture,
+        };
+        screenshot::save(&self.gpu, tex, &self.screen_settings)
+    }
+
+    /// Renders `factor` times as many samples along each axis of the result,
+    /// which the screen shader averages together.
+    pub fn set_supersample(&mut self, factor: u32) {
+        let factor = factor.max(1);
+        self.settings.supersample = factor;
+        self.screen_settings.supersample = factor;
+        self.gpu_res
+            .screen_shader
+            .settings
+            .write(&self.gpu, &self.screen_settings);
+        self.gpu_res.set_supersample(&self.gpu, factor);
+        self.write_settings();
+        self.frame_count = 0;
+    }
+
+    /// Freezes everything and accumulates `target_frames` frames of path traced samples.
+    pub fn start_still(&mut self, target_frames: u32) {
+        self.path_tracing = true;
+        self.still = Some(StillRender {
+            target_frames,
+            finished: false,
+        });
+        self.gpu_res
+            .resize_result_texture(&self.gpu, self.gpu_res.result_size());
+        self.frame_count = 0;
+    }
 
+    /// Lets the dynamic resolution controller adjust `vertical_samples`, and the
+    /// quality manager turn shader features on or off, for the time the last frame's
+    /// work took (not counting the frame rate cap).
+    fn adapt_to_frame_time(&mut self, frame_time: Duration) {
+        if self.still.is_some() {
+            // changing the quality or resolution would restart the still
+            return;
+        }
+        if self.quality.update(frame_time, self.dynamic_res.target_fps) {
+            self.write_settings();
+            self.gpu_res
+                .resize_result_texture(&self.gpu, self.gpu_res.result_size());
+            self.frame_count = 0;
+        }
+        let Some(samples) = self.dynamic_res.update(frame_time, self.vertical_samples) else {
+            return;
+        };
+        self.vertical_samples = samples;
+        let size = self.gpu_res.result_size();
+        let aspect = size.x as f32 / size.y as f32;
+        let result_size = uvec2((samples as f32 * aspect) as u32, samples);
+        self.gpu_res.resize_result_texture(&self.gpu, result_size);
+        self.frame_count = 0;
+    }
+
+    /// Moves the world, builds chunks, places features and uploads the results.
+    /// Skipped while the simulation is paused.
+    fn update_world(&mut self) {
         // Receive and store features from builder threads
         while let Ok(feature) = self.feature_receiver.try_recv() {
             self.features_queue.push(feature);
         }
+        self.evict_features();
 
         // -------- World Updates --------
         {
@@ -598,19 +1232,39 @@ impl GameState {
             // be uploaded to the GPU.
             let mut upload_chunks = HashSet::<ChunkHeader>::new();
 
+            self.timings = FrameTimings::default();
+
             // Move local world origin to follow player
-            let _world_moved = match self.move_world {
-                true => self.move_world(),
-                false => false,
-            };
+            let _world_moved = profile_scope!(
+                self.timings.move_world,
+                match self.move_world {
+                    true => self.move_world(),
+                    false => false,
+                }
+            );
 
-            self.finish_chunk_builders(&mut upload_chunks);
-            if self.build_chunks {
-                self.build_dirty_chunks();
+            profile_scope!(self.timings.build_chunks, {
+                let rebuilds = self.world.take_rebuilds(MAX_REBUILDS_PER_FRAME);
+                self.dirty_chunks.extend(rebuilds);
+                self.finish_chunk_builders(&mut upload_chunks);
+                if self.build_chunks {
+                    self.build_dirty_chunks();
+                }
+            });
+            profile_scope!(
+                self.timings.place_features,
+                self.place_features(&mut upload_chunks)
+            );
+            if self.chunk_builders.is_empty() && self.dirty_chunks.is_empty() {
+                self.compact_next_chunk(&mut upload_chunks);
             }
-            self.place_features(&mut upload_chunks);
 
             // Upload collected chunks in `upload_chunks`
+            self.gpu_res
+                .ensure_node_capacity(&self.gpu, self.world.nodes().len() as u32);
+            #[cfg(feature = "profiling")]
+            let upload_start = std::time::Instant::now();
+            let mut regions = Vec::with_capacity(upload_chunks.len());
             for chunk in upload_chunks {
                 if self.chunk_builders.iter().any(|b| b.chunk == chunk) {
                     panic!(
@@ -619,11 +1273,37 @@ impl GameState {
                 }
                 let node_range =
                     chunk.root as usize..self.world.allocs[chunk.alloc as usize].range.end as usize;
-                self.gpu_res.buffers.nodes.write(
-                    &self.gpu,
-                    chunk.root as u64,
-                    &self.world.nodes()[node_range],
-                );
+                regions.push((chunk.root as u64, &self.world.nodes()[node_range]));
+            }
+            // one submission for every chunk, instead of a write each
+            self.gpu_res.buffers.nodes.write_many(&self.gpu, &regions);
+            #[cfg(feature = "profiling")]
+            {
+                self.timings.upload_chunks += upload_start.elapsed();
+            }
+        }
+    }
+    pub fn update(&mut self, input: &InputState) -> UpdateResult {
+        let mut output = UpdateResult::default();
+        if self.still.is_some() {
+            // nothing may move while a still renders, or the accumulated samples are lost
+            return output;
+        }
+
+        if !self.paused {
+            self.update_world();
+        }
+        if self.sun_from_time && self.day_cycle_speed > 0.0 && !self.paused {
+            self.time_of_day = (self.time_of_day + self.day_cycle_speed / 60.0).rem_euclid(24.0);
+            self.update_sun_pos();
+            self.write_settings();
+            output.world_changed = true;
+        }
+        if self.face_open_on_spawn {
+            let chunk_pos = vox_to_chunk_pos(self.player.eye_pos().as_ivec3());
+            if self.world.is_chunk_loaded(chunk_pos) {
+                self.player.face_open_space(&self.world, 8, 32.0);
+                self.face_open_on_spawn = false;
             }
         }
 
@@ -634,24 +1314,122 @@ impl GameState {
             let prev_rot = self.player.rot;
             self.player.update(1.0, input, &self.world);
 
-            if prev_pos != self.player.pos || prev_rot != self.player.rot {
+            if prev_pos != self.player.pos {
                 output.player_moved = true;
+            } else if prev_rot != self.player.rot {
+                output.camera_turn = (self.player.rot - prev_rot).abs().max_element();
             }
         }
 
         // Toggle settings with key presses
-        if input.key_pressed(Key::N) {
+        if input.action_pressed(InputAction::ToggleMoveWorld) {
             self.move_world ^= true;
         }
-        if input.key_pressed(Key::M) {
+        if input.action_pressed(InputAction::ToggleBuildChunks) {
             self.build_chunks ^= true;
         }
+        self.handle_bookmark_keys(input);
+        if input.action_pressed(InputAction::Pause) {
+            self.paused ^= true;
+        }
+        if input.action_pressed(InputAction::ToggleHitboxes) {
+            self.show_hitboxes ^= true;
+        }
+        if input.action_pressed(InputAction::CycleDebugView) {
+            self.debug_mode = self.debug_mode.next();
+            self.settings.debug_mode = self.debug_mode as u32;
+            self.write_settings();
+            self.gpu_res
+                .resize_result_texture(&self.gpu, self.gpu_res.result_size());
+            self.frame_count = 0;
+        }
 
         // Handle player interactions with input
         output.hit_result = self.check_player_interactions(input);
+        if input.action_pressed(InputAction::PickCorner) {
+            if let Some(hit) = &output.hit_result {
+                self.selection.pick(hit.pos);
+            }
+        }
+        if input.action_pressed(InputAction::ClearSelection) {
+            self.selection.clear();
+        }
         output
     }
 
+    /// Renders the result for the frame after `update`, restarting the accumulated samples
+    /// if it moved anything, and counts the frame toward a still's target.
+    /// The path tracer is submitted right away, and the ray tracer encoded into `encoder`.
+    fn render(&mut self, update: &UpdateResult, encoder: &mut wgpu::CommandEncoder) {
+        let result_tex_size = self.gpu_res.result_texture.size();
+        // Once a still has all of its samples, the result is kept as it is.
+        let render = !self.still_finished();
+
+        {
+            // When the camera only turned a little, the path tracer keeps accumulating
+            // onto its reprojected samples, since they still see the same surfaces.
+            let reproject = self.uses_path_tracer()
+                && update.camera_turn > 0.0
+                && update.camera_turn <= MAX_REPROJECT_TURN;
+            let moved = update.player_moved || (update.camera_turn > 0.0 && !reproject);
+
+            // With TAA the previous frame is kept and reprojected when only the camera moved.
+            let taa = self.uses_path_tracer() && self.settings.taa_blend > 0.0;
+            if update.world_changed || (moved && !taa) {
+                self.frame_count = 0;
+                self.gpu_res
+                    .resize_result_texture(&self.gpu, self.gpu_res.result_size());
+            } else if moved {
+                self.frame_count = 0;
+            }
+
+            let buffers = &self.gpu_res.buffers;
+
+            if update.world_changed {
+                // buffers.world.write(&self.gpu, &self.world);
+            }
+
+            // Upload camera data to GPU
+            let cam_data = self
+                .player
+                .create_cam_data(result_tex_size.as_vec2(), &self.world);
+            let prev_cam_data = self.prev_cam_data.unwrap_or(cam_data);
+            let gpu_cam_data = CamData {
+                reproject: (reproject && !update.world_changed) as u32,
+                ..cam_data.with_prev(&prev_cam_data)
+            };
+            buffers.cam_data.write(&self.gpu, &gpu_cam_data);
+            self.prev_cam_data = Some(cam_data);
+
+            if render {
+                if self.uses_path_tracer() {
+                    self.dispatch_path_tracer(gpu_cam_data);
+                } else {
+                    buffers.frame_count.write(&self.gpu, &self.frame_count);
+                    self.frame_count += 1;
+                }
+            }
+        }
+
+        // The path tracer was already dispatched, by `dispatch_path_tracer`.
+        if render && !self.uses_path_tracer() {
+            self.gpu_res.encode_tracer(
+                &self.gpu,
+                encoder,
+                &self.gpu_res.ray_tracer,
+                self.split_screen(),
+            );
+        }
+        if let Some(still) = &mut self.still {
+            if !still.finished && self.frame_count >= still.target_frames {
+                still.finished = true;
+                let msg = format!("still finished after {} frames", self.frame_count);
+                log::info!("{msg}");
+                self.console.print(msg);
+            }
+        }
+    }
+
     pub fn frame(
         &mut self,
         window: &Window,
@@ -665,52 +1443,21 @@ impl GameState {
         }
 
         // Update voxel selection with scroll wheel or Up/Down
-        if (input.scroll_delta.y < 0.0 || input.key_pressed(Key::Down)) && self.inv_sel > 0 {
-            self.inv_sel -= 1;
+        // (Up/Down browse the console history while it's open)
+        let arrows = !self.console.open;
+        if input.scroll_delta.y < 0.0 || (arrows && input.action_pressed(InputAction::PrevSlot)) {
+            self.inventory.scroll(-1);
         }
-        if (input.scroll_delta.y > 0.0 || input.key_pressed(Key::Up))
-            && self.inv_sel < (INVENTORY.len() - 1) as u8
-        {
-            self.inv_sel += 1;
+        if input.scroll_delta.y > 0.0 || (arrows && input.action_pressed(InputAction::NextSlot)) {
+            self.inventory.scroll(1);
         }
+        self.handle_hotbar_keys(input);
 
         let (output, view) = self.gpu.get_output()?;
         let surface_size = self.gpu.surface_size();
         let mut encoder = self.gpu.create_command_encoder();
         let result_tex_size = self.gpu_res.result_texture.size();
-
-        {
-            if update.world_changed || update.player_moved {
-                self.frame_count = 0;
-                self.gpu_res
-                    .resize_result_texture(&self.gpu, result_tex_size);
-            }
-
-            let buffers = &self.gpu_res.buffers;
-
-            if update.world_changed {
-                // buffers.world.write(&self.gpu, &self.world);
-            }
-
-            buffers.frame_count.write(&self.gpu, &self.frame_count);
-            self.frame_count += 1;
-
-            // Upload camera data to GPU
-            let cam_data = self.player.create_cam_data(result_tex_size.as_vec2());
-            buffers.cam_data.write(&self.gpu, &cam_data);
-        }
-
-        let workgroups = result_tex_size / 8;
-        match self.path_tracing {
-            false => self
-                .gpu_res
-                .ray_tracer
-                .encode_pass(&mut encoder, workgroups),
-            true => self
-                .gpu_res
-                .path_tracer
-                .encode_pass(&mut encoder, workgroups),
-        }
+        self.render(update, &mut encoder);
 
         self.gpu_res.screen_shader.encode_pass(&mut encoder, &view);
 
@@ -771,26 +1518,6 @@ impl GameState {
             }
         };
 
-        encoder.copy_texture_to_texture(
-            wgpu::ImageCopyTexture {
-                texture: &self.gpu_res.result_texture.handle,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            wgpu::ImageCopyTexture {
-                texture: &self.gpu_res.prev_result_texture.handle,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            wgpu::Extent3d {
-                width: self.gpu_res.result_texture.size().x,
-                height: self.gpu_res.result_texture.size().y,
-                depth_or_array_layers: 1,
-            },
-        );
-
         // --- submit passes ---
         self.gpu.queue.submit(std::iter::once(encoder.finish()));
         output.present();
@@ -798,3 +1525,59 @@ impl GameState {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Binding;
+
+    #[test]
+    fn zero_sized_windows_are_minimized() {
+        assert!(is_minimized(UVec2::ZERO));
+        assert!(is_minimized(uvec2(0, 720)));
+        assert!(is_minimized(uvec2(1280, 0)));
+        assert!(!is_minimized(uvec2(1280, 720)));
+        assert!(!is_minimized(uvec2(1, 1)));
+    }
+
+    #[test]
+    fn stills_ignore_updates_until_they_finish() {
+        let Some(gpu) = Gpu::headless() else {
+            eprintln!("no GPU adapter, skipping");
+            return;
+        };
+        let mut state = GameState::with_world_size(uvec2(16, 16), gpu, NODES_PER_CHUNK, UVec3::ONE);
+        state
+            .gpu_res
+            .resize_result_texture(&state.gpu, uvec2(16, 16));
+        state.sun_from_time = true;
+        state.day_cycle_speed = 60.0;
+        state.max_samples_per_dispatch = 1;
+        state.settings.samples_per_pixel = 2;
+        state.start_still(5);
+
+        let mut input = InputState::default();
+        let Binding::Key(key) = input.bindings.get(InputAction::MoveForward) else {
+            panic!("moving forward isn't bound to a key");
+        };
+        input.down_keys.insert(key);
+        // the player walks, the sun moves and the world's chunks wait to be built
+        let (pos, sun_pos) = (state.player.pos, state.settings.sun_pos);
+        let dirty_chunks = state.dirty_chunks.len();
+        assert!(dirty_chunks > 0);
+
+        let mut encoder = state.gpu.create_command_encoder();
+        let mut frame_counts = vec![];
+        for _ in 0..5 {
+            let update = state.update(&input);
+            state.render(&update, &mut encoder);
+            frame_counts.push(state.frame_count);
+        }
+        // two dispatches a frame, up to the target
+        assert_eq!(frame_counts, [2, 4, 5, 5, 5]);
+        assert!(state.still.as_ref().is_some_and(|still| still.finished));
+        assert_eq!(state.player.pos, pos);
+        assert_eq!(state.settings.sun_pos, sun_pos);
+        assert_eq!(state.dirty_chunks.len(), dirty_chunks);
+    }
+}
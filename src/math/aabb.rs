@@ -15,6 +15,11 @@ impl Aabb {
         Self { from, to }
     }
 
+    #[inline(always)]
+    pub fn center(&self) -> Vec3 {
+        (self.from + self.to) * 0.5
+    }
+
     pub fn expand(&self, a: Vec3) -> Self {
         let mut from = self.from;
         let mut to = self.to;
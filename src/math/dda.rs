@@ -25,77 +25,277 @@ pub struct HitResult {
     pub pos: IVec3,
     pub face: IVec3,
 }
-pub fn cast_ray(
-    start: Vec3,
-    dir: Vec3,
+/// Walks the voxel grid along a ray, yielding each voxel position the ray
+/// enters (not including the starting voxel), in order.
+pub struct VoxelMarch {
+    unit_step_size: Vec3,
+    step: IVec3,
+    ray_len1d: Vec3,
+    map_check: IVec3,
+    dist: f32,
     max_dist: f32,
-    collides: impl Fn(IVec3) -> bool,
-) -> Option<HitResult> {
-    // -- DDA algorithm --
-
-    // length of a line in same direction as the ray,
-    // that travels 1 unit in the X, Y, Z
-    let unit_step_size = vec3(
-        (1.0 + (dir.y / dir.x) * (dir.y / dir.x) + (dir.z / dir.x) * (dir.z / dir.x)).sqrt(),
-        (1.0 + (dir.x / dir.y) * (dir.x / dir.y) + (dir.z / dir.y) * (dir.z / dir.y)).sqrt(),
-        (1.0 + (dir.x / dir.z) * (dir.x / dir.z) + (dir.y / dir.z) * (dir.y / dir.z)).sqrt(),
-    );
-
-    let mut map_check = start.floor().as_ivec3();
-
-    let (step, mut ray_len1d): (Vec3, Vec3) = {
-        let (step_x, ray_len_x) = {
-            if dir.x < 0.0 {
-                (-1.0, (start.x - map_check.x as f32) * unit_step_size.x)
-            } else {
-                (1.0, ((map_check.x + 1) as f32 - start.x) * unit_step_size.x)
-            }
+}
+impl VoxelMarch {
+    pub fn new(start: Vec3, dir: Vec3, max_dist: f32) -> Self {
+        // length of a line in same direction as the ray,
+        // that travels 1 unit in the X, Y, Z.
+        // Infinite along axes the ray doesn't move on, so they're never stepped
+        // (dividing the other components by them, rays along an axis got NaN and never ended).
+        let unit_step_size = Vec3::splat(dir.length()) / dir.abs();
+
+        let map_check = start.floor().as_ivec3();
+
+        let (step, ray_len1d): (IVec3, Vec3) = {
+            let (step_x, ray_len_x) = {
+                if dir.x < 0.0 {
+                    (-1, (start.x - map_check.x as f32) * unit_step_size.x)
+                } else {
+                    (1, ((map_check.x + 1) as f32 - start.x) * unit_step_size.x)
+                }
+            };
+            let (step_y, ray_len_y) = {
+                if dir.y < 0.0 {
+                    (-1, (start.y - map_check.y as f32) * unit_step_size.y)
+                } else {
+                    (1, ((map_check.y + 1) as f32 - start.y) * unit_step_size.y)
+                }
+            };
+            let (step_z, ray_len_z) = {
+                if dir.z < 0.0 {
+                    (-1, (start.z - map_check.z as f32) * unit_step_size.z)
+                } else {
+                    (1, ((map_check.z + 1) as f32 - start.z) * unit_step_size.z)
+                }
+            };
+            (
+                IVec3::new(step_x, step_y, step_z),
+                vec3(ray_len_x, ray_len_y, ray_len_z),
+            )
         };
-        let (step_y, ray_len_y) = {
-            if dir.y < 0.0 {
-                (-1.0, (start.y - map_check.y as f32) * unit_step_size.y)
-            } else {
-                (1.0, ((map_check.y + 1) as f32 - start.y) * unit_step_size.y)
+        Self {
+            unit_step_size,
+            step,
+            ray_len1d,
+            map_check,
+            dist: 0.0,
+            max_dist,
+        }
+    }
+
+    /// The voxel the ray is currently in.
+    #[inline(always)]
+    pub fn pos(&self) -> IVec3 {
+        self.map_check
+    }
+
+    /// Moves to the last voxel the ray passes through in the box from `min` (inclusive)
+    /// to `max` (exclusive), which must contain the current voxel, without visiting
+    /// the ones in between. The next voxel is the first one out of the box.
+    pub fn skip_box(&mut self, min: IVec3, max: IVec3) {
+        // where the ray leaves the box through each axis, summed up crossing by crossing
+        // like `next` does, so the march ends up exactly where stepping through would
+        let mut exit = self.ray_len1d;
+        for axis in 0..3 {
+            let mut cell = self.map_check[axis];
+            while (min[axis]..max[axis]).contains(&(cell + self.step[axis])) {
+                cell += self.step[axis];
+                exit[axis] += self.unit_step_size[axis];
             }
-        };
-        let (step_z, ray_len_z) = {
-            if dir.z < 0.0 {
-                (-1.0, (start.z - map_check.z as f32) * unit_step_size.z)
-            } else {
-                (1.0, ((map_check.z + 1) as f32 - start.z) * unit_step_size.z)
+        }
+        let exit = exit.min_element();
+        // take every crossing before the ray leaves the box, leaving the one out of it to `next`
+        for axis in 0..3 {
+            while self.ray_len1d[axis] < exit {
+                self.map_check[axis] += self.step[axis];
+                self.dist = self.dist.max(self.ray_len1d[axis]);
+                self.ray_len1d[axis] += self.unit_step_size[axis];
             }
-        };
-        (
-            vec3(step_x, step_y, step_z),
-            vec3(ray_len_x, ray_len_y, ray_len_z),
-        )
-    };
-    let mut dist: f32 = 0.0;
-    let mut prev_map_check;
-
-    while dist < max_dist {
-        prev_map_check = map_check;
-        // walk
-        if ray_len1d.x < ray_len1d.y && ray_len1d.x < ray_len1d.z {
-            map_check.x += step.x as i32;
-            dist = ray_len1d.x;
-            ray_len1d.x += unit_step_size.x;
-        } else if ray_len1d.z < ray_len1d.x && ray_len1d.z < ray_len1d.y {
-            map_check.z += step.z as i32;
-            dist = ray_len1d.z;
-            ray_len1d.z += unit_step_size.z;
+        }
+    }
+}
+impl Iterator for VoxelMarch {
+    type Item = IVec3;
+
+    fn next(&mut self) -> Option<IVec3> {
+        if self.dist >= self.max_dist {
+            return None;
+        }
+        // -- DDA algorithm --
+        let ray_len1d = &mut self.ray_len1d;
+        // ties go to y, then x; an exact x/z tie must not fall through to y,
+        // which the ray may not move on at all
+        if ray_len1d.y <= ray_len1d.x && ray_len1d.y <= ray_len1d.z {
+            self.map_check.y += self.step.y;
+            self.dist = ray_len1d.y;
+            ray_len1d.y += self.unit_step_size.y;
+        } else if ray_len1d.x <= ray_len1d.z {
+            self.map_check.x += self.step.x;
+            self.dist = ray_len1d.x;
+            ray_len1d.x += self.unit_step_size.x;
         } else {
-            map_check.y += step.y as i32;
-            dist = ray_len1d.y;
-            ray_len1d.y += unit_step_size.y;
+            self.map_check.z += self.step.z;
+            self.dist = ray_len1d.z;
+            ray_len1d.z += self.unit_step_size.z;
         }
-        // check
+        Some(self.map_check)
+    }
+}
+
+/// Yields every voxel a ray enters, along with the voxel found there by `get_voxel`.
+pub fn march_voxels<V>(
+    start: Vec3,
+    dir: Vec3,
+    max_dist: f32,
+    get_voxel: impl Fn(IVec3) -> V,
+) -> impl Iterator<Item = (IVec3, V)> {
+    VoxelMarch::new(start, dir, max_dist).map(move |pos| (pos, get_voxel(pos)))
+}
+
+pub fn cast_ray(
+    start: Vec3,
+    dir: Vec3,
+    max_dist: f32,
+    collides: impl Fn(IVec3) -> bool,
+) -> Option<HitResult> {
+    let march = VoxelMarch::new(start, dir, max_dist);
+    let mut prev_map_check = march.pos();
+
+    for map_check in march {
         if collides(map_check) {
             return Some(HitResult {
                 pos: map_check,
                 face: prev_map_check - map_check,
             });
         }
+        prev_map_check = map_check;
+    }
+    None
+}
+
+/// What a ray found in a voxel, for `cast_ray_skipping`.
+pub enum RayCell {
+    Hit,
+    /// Nothing to hit in the box from `min` (inclusive) to `max` (exclusive),
+    /// which contains the voxel.
+    Empty {
+        min: IVec3,
+        max: IVec3,
+    },
+}
+
+/// Like `cast_ray`, but `test` can report a whole empty box around a voxel
+/// (like an empty SVO node), and the ray crosses it without testing each voxel inside.
+/// Finds the same hits as `cast_ray`.
+pub fn cast_ray_skipping(
+    start: Vec3,
+    dir: Vec3,
+    max_dist: f32,
+    test: impl Fn(IVec3) -> RayCell,
+) -> Option<HitResult> {
+    let mut march = VoxelMarch::new(start, dir, max_dist);
+    let mut prev_map_check = march.pos();
+
+    while let Some(map_check) = march.next() {
+        match test(map_check) {
+            RayCell::Hit => {
+                return Some(HitResult {
+                    pos: map_check,
+                    face: prev_map_check - map_check,
+                })
+            }
+            RayCell::Empty { min, max } => march.skip_box(min, max),
+        }
+        prev_map_check = march.pos();
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Random solid voxels in a 64^3 region, with everything else empty.
+    fn random_solids(rng: &mut fastrand::Rng) -> HashSet<IVec3> {
+        (0..400)
+            .map(|_| IVec3::new(rng.i32(0..64), rng.i32(0..64), rng.i32(0..64)))
+            .collect()
+    }
+
+    /// Like an SVO lookup: the biggest aligned empty box around `pos`, up to 16 voxels wide.
+    fn test_cell(solids: &HashSet<IVec3>, pos: IVec3) -> RayCell {
+        if solids.contains(&pos) {
+            return RayCell::Hit;
+        }
+        for size in [16, 8, 4, 2] {
+            let min = pos.div_euclid(IVec3::splat(size)) * size;
+            let max = min + size;
+            let empty = !solids
+                .iter()
+                .any(|p| p.cmpge(min).all() && p.cmplt(max).all());
+            if empty {
+                return RayCell::Empty { min, max };
+            }
+        }
+        RayCell::Empty {
+            min: pos,
+            max: pos + 1,
+        }
+    }
+
+    fn random_dir(rng: &mut fastrand::Rng) -> Vec3 {
+        let mut dir = Vec3::new(rng.f32() - 0.5, rng.f32() - 0.5, rng.f32() - 0.5);
+        // some rays in a plane of the grid
+        if rng.u8(0..4) == 0 {
+            dir[rng.usize(0..3)] = 0.0;
+        }
+        dir.normalize()
+    }
+
+    #[test]
+    fn skipping_finds_the_same_hits() {
+        let mut rng = fastrand::Rng::with_seed(4);
+        let mut hits = 0;
+        for _ in 0..10 {
+            let solids = random_solids(&mut rng);
+            for _ in 0..500 {
+                let start = Vec3::new(rng.f32(), rng.f32(), rng.f32()) * 80.0 - 8.0;
+                let dir = random_dir(&mut rng);
+                let max_dist = rng.f32() * 100.0;
+                let expected = cast_ray(start, dir, max_dist, |pos| solids.contains(&pos));
+                let skipped =
+                    cast_ray_skipping(start, dir, max_dist, |pos| test_cell(&solids, pos));
+                match (expected, skipped) {
+                    (None, None) => {}
+                    (Some(a), Some(b)) => {
+                        assert_eq!((a.pos, a.face), (b.pos, b.face), "from {start} along {dir}");
+                        hits += 1;
+                    }
+                    _ => panic!("from {start} along {dir}: only one cast hit"),
+                }
+            }
+        }
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn marching_yields_each_voxel_entered() {
+        // a stack of voxels along +X, with a ray that barely drifts off the axis
+        let stack = ["stone", "dirt", "grass"];
+        let get_voxel = |pos: IVec3| match (pos.y, pos.z) {
+            (0, 0) => stack.get((pos.x - 2) as usize).copied().unwrap_or("air"),
+            _ => "air",
+        };
+        let dir = vec3(1.0, 0.01, 0.02).normalize();
+        let marched: Vec<_> = march_voxels(Vec3::splat(0.5), dir, 5.0, get_voxel).collect();
+        let expected = [
+            (IVec3::new(1, 0, 0), "air"),
+            (IVec3::new(2, 0, 0), "stone"),
+            (IVec3::new(3, 0, 0), "dirt"),
+            (IVec3::new(4, 0, 0), "grass"),
+            (IVec3::new(5, 0, 0), "air"),
+            (IVec3::new(6, 0, 0), "air"),
+        ];
+        assert_eq!(marched, expected);
+    }
+}
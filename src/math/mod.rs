@@ -101,29 +101,70 @@ pub fn walk_line(a: IVec3, b: IVec3) -> impl Iterator<Item = IVec3> {
     std::iter::once(a).chain(walker)
 }
 
-pub fn rand_cardinal_dir() -> IVec3 {
+/// The direction toward the sun, with +Y up, +X east and -Z north,
+/// at `latitude` (in degrees), `day_of_year` (0 to 365) and `time_of_day` (in hours since
+/// midnight, local solar time). Uses the usual approximation of the sun's declination.
+pub fn sun_dir(latitude: f32, day_of_year: f32, time_of_day: f32) -> Vec3 {
+    let lat = latitude.to_radians();
+    let decl =
+        (-23.44f32).to_radians() * (std::f32::consts::TAU / 365.0 * (day_of_year + 10.0)).cos();
+    let hour_angle = (15.0 * (time_of_day - 12.0)).to_radians();
+
+    let east = -decl.cos() * hour_angle.sin();
+    let north = lat.cos() * decl.sin() - lat.sin() * decl.cos() * hour_angle.cos();
+    let up = lat.sin() * decl.sin() + lat.cos() * decl.cos() * hour_angle.cos();
+    vec3(east, up, -north)
+}
+
+pub fn rand_cardinal_dir(rng: &mut fastrand::Rng) -> IVec3 {
     [
         ivec3(-1, 0, 0),
         ivec3(1, 0, 0),
         ivec3(0, 0, -1),
         ivec3(0, 0, 1),
-    ][fastrand::usize(0..4)]
+    ][rng.usize(0..4)]
 }
 
-pub fn rand_dir() -> Vec3 {
-    fn rand_norm() -> f32 {
-        let theta = 2.0 * 3.14159265 * fastrand::f32();
-        let rho = (-2.0 * fastrand::f32().ln()).sqrt();
+pub fn rand_dir(rng: &mut fastrand::Rng) -> Vec3 {
+    fn rand_norm(rng: &mut fastrand::Rng) -> f32 {
+        let theta = 2.0 * 3.14159265 * rng.f32();
+        let rho = (-2.0 * rng.f32().ln()).sqrt();
         return rho * theta.cos();
     }
 
-    let x = rand_norm();
-    let y = rand_norm();
-    let z = rand_norm();
+    let x = rand_norm(rng);
+    let y = rand_norm(rng);
+    let z = rand_norm(rng);
     vec3(x, y, z).normalize()
 }
 
-pub fn rand_hem_dir(norm: Vec3) -> Vec3 {
-    let dir = rand_dir();
+pub fn rand_hem_dir(norm: Vec3, rng: &mut fastrand::Rng) -> Vec3 {
+    let dir = rand_dir(rng);
     dir * norm.dot(dir).signum()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sun_peaks_at_noon() {
+        for (latitude, day_of_year) in [(0.0, 80.0), (45.0, 172.0), (45.0, 355.0), (-30.0, 10.0)] {
+            // sampled every 10 minutes over a day
+            let samples: Vec<(f32, Vec3)> = (0..144)
+                .map(|i| {
+                    let hour = i as f32 / 6.0;
+                    (hour, sun_dir(latitude, day_of_year, hour))
+                })
+                .collect();
+            let (_, midnight) = samples[0];
+            assert!(midnight.y < 0.0, "sun is up at midnight: {midnight}");
+            let (noon, _) = samples
+                .iter()
+                .copied()
+                .max_by(|(_, a), (_, b)| a.y.total_cmp(&b.y))
+                .unwrap();
+            assert_eq!(noon, 12.0, "highest at {noon}h, at latitude {latitude}");
+        }
+    }
+}
@@ -1,18 +1,147 @@
 use crate::gpu::CamData;
-use crate::input::{InputState, Key};
+use crate::input::{Action, InputState};
 use crate::math::aabb::Aabb;
-use crate::math::dda::{axis_rot_to_ray, cast_ray, HitResult};
+use crate::math::dda::{axis_rot_to_ray, cast_ray_skipping, march_voxels, HitResult, RayCell};
 use crate::world::{Voxel, World};
-use glam::{vec2, vec3, BVec3, Mat4, Vec2, Vec3};
+use glam::{vec2, vec3, BVec3, IVec3, Mat4, Vec2, Vec3};
 
 const GRAVITY: f32 = -0.060;
+const JUMP_VEL: f32 = 0.6;
+/// How fast holding jump swims upwards in a liquid.
+const SWIM_VEL: f32 = 0.15;
+/// How much of gravity a liquid cancels out, so the player sinks slowly.
+const BUOYANCY: f32 = 0.8;
+/// How close to a voxel surface the player has to be, while on the ground, to snap onto it.
+const GROUND_SNAP_DIST: f32 = 0.01;
+
+/// How far survival mode lets the player reach, whatever `Player::reach` is.
+const SURVIVAL_REACH: f32 = 5.0;
+/// How many updates the break button has to be held on a voxel to break it in survival mode.
+const SURVIVAL_BREAK_FRAMES: u32 = 15;
+
+/// Gates what the player is allowed to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameMode {
+    /// Flight, instant breaking, unlimited voxels, and reach as far as `Player::reach`.
+    Creative,
+    /// No flight, breaking takes time, placing uses up voxels, and reach is limited.
+    Survival,
+}
+impl GameMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Creative => "creative",
+            Self::Survival => "survival",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "creative" => Some(Self::Creative),
+            "survival" => Some(Self::Survival),
+            _ => None,
+        }
+    }
+
+    pub fn can_fly(self) -> bool {
+        self == Self::Creative
+    }
+
+    /// The furthest the player can reach, capping `Player::reach`.
+    pub fn max_reach(self) -> f32 {
+        match self {
+            Self::Creative => f32::INFINITY,
+            Self::Survival => SURVIVAL_REACH,
+        }
+    }
+
+    /// How many updates the break button has to be held on a voxel to break it.
+    /// 0 breaks it as soon as it's pressed.
+    pub fn break_frames(self) -> u32 {
+        match self {
+            Self::Creative => 0,
+            Self::Survival => SURVIVAL_BREAK_FRAMES,
+        }
+    }
+
+    /// Whether placing voxels leaves the inventory alone, instead of using them up.
+    pub fn unlimited_voxels(self) -> bool {
+        self == Self::Creative
+    }
+}
+
+/// How cursor movement turns into camera rotation.
+#[derive(Clone)]
+pub struct MouseSettings {
+    pub sensitivity: f32,
+    /// How much faster cursor movement scales up rotation, per pixel of movement
+    /// in a frame. 0 keeps the response linear.
+    pub acceleration: f32,
+    /// The most acceleration can multiply rotation by.
+    pub max_acceleration: f32,
+    /// How much of the previous movement carries into the next, for slow and
+    /// fast movements. 0 disables smoothing.
+    pub slow_smoothing: f32,
+    pub fast_smoothing: f32,
+    /// The cursor movement in a frame (in pixels) at which smoothing is fully `fast_smoothing`.
+    pub fast_threshold: f32,
+}
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.4,
+            acceleration: 0.0,
+            max_acceleration: 3.0,
+            slow_smoothing: 0.0,
+            fast_smoothing: 0.0,
+            fast_threshold: 50.0,
+        }
+    }
+}
+impl MouseSettings {
+    /// The factor rotation is scaled by for a cursor movement of `delta` pixels.
+    pub fn gain(&self, delta: Vec2) -> f32 {
+        (1.0 + self.acceleration * delta.length()).min(self.max_acceleration.max(1.0))
+    }
+
+    /// The smoothing to use for a cursor movement of `delta` pixels.
+    pub fn smoothing(&self, delta: Vec2) -> f32 {
+        let t = (delta.length() / self.fast_threshold.max(f32::EPSILON)).min(1.0);
+        (self.slow_smoothing + (self.fast_smoothing - self.slow_smoothing) * t).clamp(0.0, 0.99)
+    }
+}
 
 #[derive(Clone)]
 pub struct Player {
     pub fov: f32,
+    /// The distances of the near and far clip planes.
+    pub near: f32,
+    pub far: f32,
 
+    pub mode: GameMode,
+    /// The voxel being mined, and for how many updates, when breaking takes time.
+    pub mining: Option<(IVec3, u32)>,
     pub flying: bool,
+    /// Fly through solid voxels, without any collision. For looking around inside terrain.
+    pub noclip: bool,
     pub on_ground: bool,
+    /// Treat the edge of the loaded world as a wall, instead of falling out of it.
+    pub world_barrier: bool,
+    /// Jump over one voxel tall obstacles when walking into them,
+    /// instead of stepping straight up onto them.
+    pub auto_jump: bool,
+    /// How much liquids slow the player, from 0 (not at all) to 1 (by their full `viscosity`).
+    pub liquid_drag: f32,
+    /// Whether the player overlapped a liquid in the last update.
+    pub in_liquid: bool,
+    /// How far away (in voxels) the player can place and break voxels.
+    pub reach: f32,
+    pub mouse: MouseSettings,
+    /// The cursor movement left after smoothing, carried into the next frame.
+    smoothed_cursor_delta: Vec2,
+    /// Set when noclip was just turned off, so the next movement lets the player
+    /// out of the voxels they were left inside, instead of ejecting them.
+    left_noclip: bool,
 
     pub pos: Vec3,
     // (in degrees)
@@ -24,9 +153,22 @@ impl Player {
     pub fn new(pos: Vec3, speed: f32) -> Self {
         Self {
             fov: 70.0,
+            near: 0.001,
+            far: 1000.0,
 
+            mode: GameMode::Creative,
+            mining: None,
             flying: false,
+            noclip: false,
             on_ground: false,
+            world_barrier: true,
+            auto_jump: false,
+            liquid_drag: 1.0,
+            in_liquid: false,
+            reach: 5.0,
+            mouse: MouseSettings::default(),
+            smoothed_cursor_delta: Vec2::ZERO,
+            left_noclip: false,
 
             pos,
             rot: Vec3::ZERO,
@@ -35,17 +177,36 @@ impl Player {
         }
     }
 
+    /// Switches game mode, landing the player if the new mode can't fly.
+    pub fn set_mode(&mut self, mode: GameMode) {
+        self.mode = mode;
+        self.mining = None;
+        if !mode.can_fly() {
+            self.flying = false;
+            self.noclip = false;
+        }
+    }
+
+    /// Whether the player ignores gravity, either flying or in noclip.
+    pub fn is_flying(&self) -> bool {
+        self.flying || self.noclip
+    }
+
     pub fn handle_cursor_movement(&mut self, t_delta: f32, delta: Vec2) {
-        const SENSITIVITY: f32 = 0.4;
-        let delta = delta * t_delta;
+        let smoothing = self.mouse.smoothing(delta);
+        self.smoothed_cursor_delta =
+            self.smoothed_cursor_delta * smoothing + delta * (1.0 - smoothing);
+        let delta = self.smoothed_cursor_delta;
+        let delta = delta * self.mouse.gain(delta) * t_delta;
+        let sensitivity = self.mouse.sensitivity;
 
         // in model space, the camera is looking negative along the Z axis, so
         // moving the cursor up/down corresponds to rotation about the X axis
-        self.rot.x += SENSITIVITY * delta.y;
+        self.rot.x += sensitivity * delta.y;
         self.rot.x = self.rot.x.clamp(-90.0, 90.0);
 
         // moving the cursor left/right corresponds to rotation about the Y axis
-        self.rot.y -= SENSITIVITY * delta.x;
+        self.rot.y -= sensitivity * delta.x;
 
         // the camera does not rotate about the Z axis. That would be like tilting your head
     }
@@ -65,59 +226,81 @@ impl Player {
     }
 
     pub fn update(&mut self, t_delta: f32, input: &InputState, world: &World) {
-        let dx = self.rot.y.to_radians().sin() * self.speed;
-        let dz = self.rot.y.to_radians().cos() * self.speed;
+        // liquids slow walking and falling down, by their viscosity
+        let viscosity = world.liquid_viscosity(&self.create_aabb());
+        self.in_liquid = viscosity.is_some() && !self.is_flying();
+        let drag = match viscosity {
+            Some(viscosity) if !self.is_flying() => 1.0 - self.liquid_drag * (1.0 - viscosity),
+            _ => 1.0,
+        };
+        let dx = self.rot.y.to_radians().sin() * self.speed * drag;
+        let dz = self.rot.y.to_radians().cos() * self.speed * drag;
 
-        if input.cursor_delta != Vec2::ZERO {
+        // smoothing can keep turning the camera after the cursor stops
+        if input.cursor_delta != Vec2::ZERO || self.smoothed_cursor_delta != Vec2::ZERO {
             self.handle_cursor_movement(t_delta, input.cursor_delta);
         }
 
-        if self.flying {
+        if self.is_flying() {
             self.vel.y = 0.0;
-        }
-        if !self.flying {
+        } else {
             self.apply_acc(vec3(0.0, GRAVITY, 0.0));
         }
-        self.vel *= 0.96;
+        if self.in_liquid {
+            self.apply_acc(vec3(0.0, -GRAVITY * BUOYANCY, 0.0));
+        }
+        self.vel *= 0.96 * drag;
 
         let mut frame_vel = self.vel;
 
-        if input.key_pressed(Key::Z) {
+        if input.action_pressed(Action::ToggleFly) && self.mode.can_fly() {
             self.flying = !self.flying;
             if self.flying {
                 self.vel = Vec3::ZERO;
                 return;
             }
         }
+        if input.action_pressed(Action::ToggleNoclip) && self.mode.can_fly() {
+            self.noclip = !self.noclip;
+            self.left_noclip = !self.noclip;
+            // don't carry momentum out of noclip, into whatever the player is inside of
+            self.vel = Vec3::ZERO;
+            return;
+        }
 
-        if input.key_down(Key::W) {
+        if input.action_down(Action::MoveForward) {
             frame_vel.x += -dx;
             frame_vel.z += -dz;
         }
-        if input.key_down(Key::S) {
+        if input.action_down(Action::MoveBack) {
             frame_vel.x += dx;
             frame_vel.z += dz;
         }
-        if input.key_down(Key::D) {
+        if input.action_down(Action::MoveRight) {
             frame_vel.x += dz;
             frame_vel.z += -dx;
         }
-        if input.key_down(Key::A) {
+        if input.action_down(Action::MoveLeft) {
             frame_vel.x += -dz;
             frame_vel.z += dx;
         }
-        if self.flying {
-            if input.key_down(Key::Space) {
+        if self.is_flying() {
+            if input.action_down(Action::Jump) {
                 frame_vel.y += self.speed;
             }
-            if input.key_down(Key::LShift) {
+            if input.action_down(Action::Descend) {
                 frame_vel.y += -self.speed;
             }
+        } else if self.in_liquid {
+            if input.action_down(Action::Jump) {
+                self.vel.y = self.vel.y.max(SWIM_VEL);
+                frame_vel.y = self.vel.y;
+            }
         } else {
-            if input.key_down(Key::Space) && self.on_ground {
-                self.vel.y = 0.6;
+            if input.action_down(Action::Jump) && self.on_ground {
+                self.vel.y = JUMP_VEL;
                 self.on_ground = false;
-                frame_vel.y = 0.6;
+                frame_vel.y = JUMP_VEL;
             }
         }
         self.attempt_movement(world, frame_vel * t_delta);
@@ -141,15 +324,56 @@ impl Player {
     }
 
     pub fn create_proj_mat(&self, aspect: f32) -> Mat4 {
-        Mat4::perspective_rh(self.fov.to_radians(), aspect, 0.001, 1000.0)
+        // keep the planes apart, or the inverse projection becomes degenerate
+        let near = self.near.max(f32::EPSILON);
+        let far = self.far.max(near * 2.0);
+        Mat4::perspective_rh(self.fov.to_radians(), aspect, near, far)
+    }
+
+    /// The eye position, pulled down toward the center of the player if the
+    /// eye would otherwise be inside a solid voxel, so the camera never renders
+    /// from within a wall.
+    /// In noclip the eye is used as is, to look around inside terrain.
+    pub fn camera_pos(&self, world: &World) -> Vec3 {
+        const STEP: f32 = 0.05;
+        if self.noclip {
+            return self.eye_pos();
+        }
+        let solid = |pos: Vec3| {
+            world
+                .get_voxel(pos.floor().as_ivec3())
+                .map(Voxel::is_solid)
+                .unwrap_or(false)
+        };
+        let center = self.create_aabb().center();
+        let eye = self.eye_pos();
+        let dist = center.distance(eye);
+        let dir = (eye - center) / dist;
+
+        let mut safe = center;
+        let mut t = STEP;
+        while t < dist {
+            let pos = center + dir * t;
+            if solid(pos) {
+                return safe;
+            }
+            safe = pos;
+            t += STEP;
+        }
+        match solid(eye) {
+            true => safe,
+            false => eye,
+        }
     }
 
-    pub fn create_cam_data(&self, proj_size: Vec2) -> CamData {
-        let inv_view_mat = self.create_view_mat();
+    pub fn create_cam_data(&self, proj_size: Vec2, world: &World) -> CamData {
+        let cam_pos = self.camera_pos(world);
+        let inv_view_mat =
+            Mat4::from_translation(cam_pos - self.eye_pos()) * self.create_view_mat();
         let inv_proj_mat = self.create_proj_mat(proj_size.x / proj_size.y).inverse();
 
         CamData {
-            pos: self.eye_pos(),
+            pos: cam_pos,
             inv_view_mat,
             inv_proj_mat,
             proj_size: vec2(proj_size.x, proj_size.y),
@@ -158,7 +382,7 @@ impl Player {
     }
 
     fn attempt_movement(&mut self, world: &World, mv: Vec3) {
-        if self.flying {
+        if self.flying || self.noclip {
             self.pos += mv;
             return;
         }
@@ -168,8 +392,15 @@ impl Player {
             eq: BVec3,
         }
 
+        let world_barrier = self.world_barrier;
+        let left_noclip = std::mem::take(&mut self.left_noclip);
         let clip_movement = |world: &World, bbox: Aabb, mv: Vec3| -> ClippedMovement {
-            let world_bboxs = world.get_collisions_w(&bbox.expand(mv));
+            let mut world_bboxs = world.get_collisions_w(&bbox.expand(mv), world_barrier);
+            // Right after leaving noclip, the voxels the player is inside don't block,
+            // so they aren't ejected out of them.
+            if left_noclip {
+                world_bboxs.retain(|world_bbox| !world_bbox.intersects(&bbox));
+            }
 
             let mut result = mv;
             for world_bbox in &world_bboxs {
@@ -201,12 +432,29 @@ impl Player {
 
             // if you can move furthur in any directrion when one space higher, then we should jump
             if up_mv_clipped.result.abs().cmpgt(mv_clipped.abs()).any() {
-                self.pos += vec3(0.0, 1.1, 0.0);
+                match self.auto_jump {
+                    true if self.on_ground => {
+                        self.vel.y = JUMP_VEL;
+                        self.on_ground = false;
+                    }
+                    // already mid-jump
+                    true => {}
+                    false => self.pos += vec3(0.0, 1.1, 0.0),
+                }
             }
         }
 
         self.on_ground = self.vel.y == 0.0 && mv.y < 0.0;
         self.pos += mv_clipped;
+
+        // Collision resolution leaves the player hovering a tiny, varying distance
+        // above the ground, so snap onto the voxel surface to keep the camera still.
+        if self.on_ground {
+            let surface = self.pos.y.round();
+            if (self.pos.y - surface).abs() < GROUND_SNAP_DIST {
+                self.pos.y = surface;
+            }
+        }
     }
 
     pub fn facing(&self) -> Vec3 {
@@ -217,16 +465,375 @@ impl Player {
         ))
     }
 
+    /// The compass bearing the player faces, in degrees clockwise from north (-Z),
+    /// with east being +X.
+    pub fn bearing(&self) -> f32 {
+        let dir = self.facing();
+        dir.x.atan2(-dir.z).to_degrees().rem_euclid(360.0)
+    }
+
+    /// Turns the player toward whichever of `directions` evenly spaced yaws has
+    /// the most open space ahead of the eye, looking up to `max_dist` voxels.
+    /// Keeps the current rotation if every direction is equally open.
+    pub fn face_open_space(&mut self, world: &World, directions: u32, max_dist: f32) {
+        let eye = self.eye_pos();
+        let open_dist = |yaw: f32| {
+            let dir = axis_rot_to_ray(vec3(0.0, yaw.to_radians(), 0.0));
+            let hit = cast_solid_ray(world, eye, dir, max_dist);
+            match hit {
+                Some(hit) => (hit.pos.as_vec3() + 0.5).distance(eye),
+                None => max_dist,
+            }
+        };
+
+        let mut best: Option<(f32, f32)> = None;
+        let current_dist = open_dist(self.rot.y);
+        for i in 1..directions {
+            let yaw = self.rot.y + i as f32 * 360.0 / directions as f32;
+            let dist = open_dist(yaw);
+            if dist > best.map_or(current_dist, |(_, d)| d) {
+                best = Some((yaw, dist));
+            }
+        }
+        if let Some((yaw, _)) = best {
+            self.rot = vec3(0.0, yaw.rem_euclid(360.0), self.rot.z);
+        }
+    }
+
+    /// Counts how long the break button has been held on `target`, for game modes
+    /// where breaking takes time. Returns whether it's been held long enough to break it.
+    pub fn mine(&mut self, target: Option<IVec3>, holding: bool) -> bool {
+        let (Some(pos), true) = (target, holding) else {
+            self.mining = None;
+            return false;
+        };
+        let frames = match self.mining {
+            Some((prev, frames)) if prev == pos => frames + 1,
+            _ => 1,
+        };
+        if frames >= self.mode.break_frames() {
+            self.mining = None;
+            return true;
+        }
+        self.mining = Some((pos, frames));
+        false
+    }
+
     pub fn cast_ray(&self, world: &World) -> Option<HitResult> {
-        cast_ray(
-            self.eye_pos(),
-            axis_rot_to_ray(vec3(
-                self.rot.x.to_radians(),
-                self.rot.y.to_radians(),
-                self.rot.z.to_radians(),
-            )),
-            100.0,
-            |pos| world.get_voxel(pos).map(Voxel::is_solid).unwrap_or(false),
-        )
+        let reach = self.reach.min(self.mode.max_reach());
+        cast_solid_ray(world, self.eye_pos(), self.facing(), reach)
+    }
+
+    /// Collects up to `max_hits` non-air voxels along the player's view ray,
+    /// nearest first. Used by the x-ray debug view.
+    pub fn xray(&self, world: &World, max_hits: usize) -> Vec<(IVec3, Voxel)> {
+        march_voxels(self.eye_pos(), self.facing(), 100.0, |pos| {
+            world.get_voxel(pos).unwrap_or(Voxel::AIR)
+        })
+        .filter(|(_, voxel)| *voxel != Voxel::AIR)
+        .take(max_hits)
+        .collect()
+    }
+}
+
+/// Casts a ray against the solid voxels of `world`, skipping across empty SVO nodes.
+fn cast_solid_ray(world: &World, start: Vec3, dir: Vec3, max_dist: f32) -> Option<HitResult> {
+    cast_ray_skipping(start, dir, max_dist, |pos| {
+        match world.get_voxel_node(pos) {
+            Ok((voxel, _, _)) if voxel.is_solid() => RayCell::Hit,
+            Ok((_, min, max)) => RayCell::Empty { min, max },
+            Err(_) => RayCell::Empty {
+                min: pos,
+                max: pos + 1,
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Binding;
+    use crate::world::NODES_PER_CHUNK;
+    use glam::{ivec3, vec4};
+
+    fn pressing(action: Action) -> InputState {
+        let mut input = InputState::default();
+        let Binding::Key(key) = input.bindings.get(action) else {
+            panic!("{action:?} isn't bound to a key");
+        };
+        input.pressed_keys.insert(key);
+        input.down_keys.insert(key);
+        input
+    }
+
+    #[test]
+    fn survival_disables_flight() {
+        let world = World::new(NODES_PER_CHUNK, 1);
+        let mut player = Player::new(vec3(0.0, 200.0, 0.0), 0.1);
+        player.flying = true;
+        player.set_mode(GameMode::Survival);
+        assert!(!player.is_flying());
+        player.update(1.0, &pressing(Action::ToggleFly), &world);
+        assert!(!player.flying);
+
+        player.set_mode(GameMode::Creative);
+        player.update(1.0, &pressing(Action::ToggleFly), &world);
+        assert!(player.flying);
+    }
+
+    #[test]
+    fn survival_enforces_mining_time() {
+        let target = Some(ivec3(1, 2, 3));
+        let mut player = Player::new(Vec3::ZERO, 0.1);
+        assert!(player.mine(target, true), "creative breaks at once");
+
+        player.set_mode(GameMode::Survival);
+        for _ in 1..SURVIVAL_BREAK_FRAMES {
+            assert!(!player.mine(target, true));
+        }
+        assert!(player.mine(target, true));
+
+        // letting go, or looking at another voxel, starts over
+        for _ in 1..SURVIVAL_BREAK_FRAMES {
+            assert!(!player.mine(target, true));
+        }
+        assert!(!player.mine(target, false));
+        assert!(!player.mine(Some(ivec3(1, 2, 4)), true));
+        assert_eq!(player.mining, Some((ivec3(1, 2, 4), 1)));
+    }
+
+    #[test]
+    fn edge_barrier_keeps_walking_players_in_the_world() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        world
+            .set_voxels_collected(IVec3::ZERO, ivec3(31, 3, 31), Voxel::STONE)
+            .unwrap();
+        let mut input = pressing(Action::MoveForward);
+        input.pressed_keys.clear();
+
+        for barrier in [true, false] {
+            let mut player = Player::new(vec3(16.0, 4.0, 16.0), 0.1);
+            player.world_barrier = barrier;
+            // facing +X
+            player.rot.y = -90.0;
+            for _ in 0..400 {
+                player.update(1.0, &input, &world);
+            }
+            let edge = world.max().x as f32;
+            match barrier {
+                true => {
+                    assert!(
+                        player.create_aabb().to.x <= edge,
+                        "walked out to {}",
+                        player.pos
+                    );
+                    assert!(player.pos.y >= 4.0, "fell to {}", player.pos);
+                }
+                false => assert!(player.pos.x > edge, "stopped at {}", player.pos),
+            }
+        }
+    }
+
+    #[test]
+    fn eye_is_pulled_out_of_walls() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        let player = Player::new(vec3(16.5, 4.0, 16.5), 0.1);
+        assert_eq!(player.camera_pos(&world), player.eye_pos());
+
+        // a ceiling the eye pokes into, like when bobbing under a low roof
+        let ceiling = player.eye_pos().floor().as_ivec3();
+        world.set_voxel(ceiling, Voxel::STONE, |_| {}).unwrap();
+        let cam = player.camera_pos(&world);
+        assert!(
+            cam.y < ceiling.y as f32,
+            "camera at {cam} is in the ceiling"
+        );
+        assert!(cam.y > player.create_aabb().center().y);
+        assert_eq!(world.get_voxel(cam.floor().as_ivec3()).unwrap(), Voxel::AIR);
+    }
+
+    #[test]
+    fn spawn_faces_away_from_a_wall() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        world
+            .set_voxels_collected(IVec3::ZERO, ivec3(31, 3, 31), Voxel::STONE)
+            .unwrap();
+        // a wall right in front of the default facing, toward -Z
+        world
+            .set_voxels_collected(ivec3(0, 4, 14), ivec3(31, 31, 14), Voxel::STONE)
+            .unwrap();
+        let mut player = Player::new(vec3(16.5, 4.0, 16.5), 0.1);
+        assert!(player.facing().z < -0.99);
+
+        player.face_open_space(&world, 8, 20.0);
+        assert!(player.facing().z > 0.0, "still facing {}", player.facing());
+
+        // nothing to turn toward in an open world
+        let mut player = Player::new(vec3(16.5, 4.0, 16.5), 0.1);
+        player.face_open_space(&World::new(NODES_PER_CHUNK, 1), 8, 20.0);
+        assert_eq!(player.rot, Vec3::ZERO);
+    }
+
+    #[test]
+    fn standing_still_on_the_ground_doesnt_jitter() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        world
+            .set_voxels_collected(IVec3::ZERO, ivec3(31, 3, 31), Voxel::STONE)
+            .unwrap();
+        let input = InputState::default();
+        // dropped from just above the floor, at uneven frame times
+        let mut player = Player::new(vec3(16.5, 4.3, 16.5), 0.1);
+        for frame in 0..600 {
+            player.update(1.0 / (50 + frame % 20) as f32, &input, &world);
+            if frame >= 120 {
+                assert!(player.on_ground, "left the ground on frame {frame}");
+                assert!(
+                    (player.pos.y - 4.0).abs() < 1e-5,
+                    "at {} on frame {frame}",
+                    player.pos.y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn acceleration_scales_fast_cursor_movement() {
+        // yaw after moving the cursor `dx` pixels in one frame
+        let turn = |mouse: &MouseSettings, dx: f32| {
+            let mut player = Player::new(Vec3::ZERO, 0.1);
+            player.mouse = mouse.clone();
+            player.handle_cursor_movement(1.0, vec2(dx, 0.0));
+            player.rot.y
+        };
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+
+        // linear by default
+        let linear = MouseSettings::default();
+        assert!(close(turn(&linear, 2.0), -0.8));
+        assert!(close(turn(&linear, 40.0), -16.0));
+
+        let accelerated = MouseSettings {
+            acceleration: 0.05,
+            max_acceleration: 3.0,
+            ..linear
+        };
+        assert!(close(turn(&accelerated, 2.0), -0.4 * 2.0 * 1.1));
+        assert!(close(turn(&accelerated, 20.0), -0.4 * 20.0 * 2.0));
+        // capped at max_acceleration
+        assert!(close(turn(&accelerated, 100.0), -0.4 * 100.0 * 3.0));
+    }
+
+    #[test]
+    fn noclip_passes_through_walls() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        world
+            .set_voxels_collected(IVec3::ZERO, ivec3(31, 3, 31), Voxel::STONE)
+            .unwrap();
+        world
+            .set_voxels_collected(ivec3(20, 4, 0), ivec3(21, 12, 31), Voxel::STONE)
+            .unwrap();
+        let mut input = pressing(Action::MoveForward);
+        input.pressed_keys.clear();
+
+        for noclip in [true, false] {
+            let mut player = Player::new(vec3(16.5, 4.0, 16.5), 0.1);
+            player.noclip = noclip;
+            // facing +X
+            player.rot.y = -90.0;
+            for _ in 0..100 {
+                player.update(1.0, &input, &world);
+            }
+            match noclip {
+                true => assert!(player.pos.x > 22.0, "stopped at {}", player.pos),
+                false => assert!(player.pos.x < 20.0, "went through to {}", player.pos),
+            }
+        }
+    }
+
+    #[test]
+    fn camera_rays_match_the_view() {
+        let world = World::new(NODES_PER_CHUNK, 1);
+        let diagonal = world.size3().as_vec3().length();
+        let proj_size = vec2(160.0, 90.0);
+        let aspect = proj_size.x / proj_size.y;
+
+        // a ray through a point in NDC, built the way `screen_pos_to_ray` in the shaders does
+        let ray_dir = |cam: &CamData, ndc: Vec2| {
+            let clip = vec4(ndc.x, ndc.y, -1.0, 1.0);
+            let eye = cam.inv_proj_mat.transpose() * clip;
+            let eye = vec4(eye.x, eye.y, -1.0, 0.0);
+            (cam.inv_view_mat.transpose() * eye).truncate().normalize()
+        };
+
+        for (near, far) in [(0.001, 1000.0), (0.1, diagonal), (0.0, 0.0), (1.0, 1e6)] {
+            for (pitch, yaw) in [(0.0, 0.0), (30.0, -90.0), (-60.0, 135.0)] {
+                let mut player = Player::new(vec3(16.5, 200.0, 16.5), 0.1);
+                (player.near, player.far) = (near, far);
+                player.rot = vec3(pitch, yaw, 0.0);
+                let cam = player.create_cam_data(proj_size, &world);
+
+                let (pitch, yaw) = (f32::to_radians(pitch), f32::to_radians(yaw));
+                let forward = vec3(
+                    -yaw.sin() * pitch.cos(),
+                    -pitch.sin(),
+                    -yaw.cos() * pitch.cos(),
+                );
+                let half_fov = (player.fov / 2.0).to_radians();
+                let corner = (half_fov.tan() * (1.0 + aspect * aspect).sqrt()).atan();
+                for (ndc, angle) in [
+                    (Vec2::ZERO, 0.0),
+                    (vec2(0.0, 1.0), half_fov),
+                    (vec2(-1.0, 0.0), (half_fov.tan() * aspect).atan()),
+                    (vec2(1.0, -1.0), corner),
+                ] {
+                    let dir = ray_dir(&cam, ndc);
+                    assert!(
+                        (dir.angle_between(forward) - angle).abs() < 1e-3,
+                        "ray through {ndc} is {dir}, forward is {forward}, near {near} far {far}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn auto_jump_jumps_onto_one_voxel_steps() {
+        let mut input = pressing(Action::MoveForward);
+        input.pressed_keys.clear();
+        for step_height in [1, 2] {
+            let mut world = World::new(NODES_PER_CHUNK, 1);
+            world
+                .set_voxels_collected(IVec3::ZERO, ivec3(31, 3, 31), Voxel::STONE)
+                .unwrap();
+            world
+                .set_voxels_collected(
+                    ivec3(20, 4, 0),
+                    ivec3(31, 3 + step_height, 31),
+                    Voxel::STONE,
+                )
+                .unwrap();
+
+            let mut player = Player::new(vec3(16.5, 4.0, 16.5), 0.1);
+            player.auto_jump = true;
+            // facing +X
+            player.rot.y = -90.0;
+            let mut jumped = false;
+            for _ in 0..300 {
+                player.update(1.0, &input, &world);
+                jumped |= player.vel.y > 0.0;
+            }
+            match step_height {
+                1 => {
+                    assert!(jumped, "never jumped, at {}", player.pos);
+                    assert!(player.pos.x > 20.0, "stopped at {}", player.pos);
+                    assert!((player.pos.y - 5.0).abs() < 1e-5, "ended at {}", player.pos);
+                }
+                _ => {
+                    assert!(!jumped, "jumped at a wall");
+                    assert!(player.pos.x < 20.0, "went through to {}", player.pos);
+                }
+            }
+        }
     }
 }
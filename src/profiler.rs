@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+/// CPU time spent on world operations during the last update.
+/// Only recorded when the `profiling` feature is enabled.
+#[derive(Clone, Debug, Default)]
+pub struct FrameTimings {
+    pub move_world: Duration,
+    pub build_chunks: Duration,
+    pub place_features: Duration,
+    pub upload_chunks: Duration,
+}
+impl FrameTimings {
+    pub fn total(&self) -> Duration {
+        self.move_world + self.build_chunks + self.place_features + self.upload_chunks
+    }
+}
+
+/// Evaluates `$body`, adding the time it took to the `Duration` at `$total`.
+/// Without the `profiling` feature this is just `$body`.
+macro_rules! profile_scope {
+    ($total:expr, $body:expr) => {{
+        #[cfg(feature = "profiling")]
+        let start = std::time::Instant::now();
+        let rs = $body;
+        #[cfg(feature = "profiling")]
+        {
+            $total += start.elapsed();
+        }
+        rs
+    }};
+}
+pub(crate) use profile_scope;
+
+#[cfg(all(test, feature = "profiling"))]
+mod tests {
+    use super::*;
+    use crate::world::{gen::WorldGen, World, NODES_PER_CHUNK};
+    use glam::ivec3;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn profile_scope_records_time() {
+        crate::world::noise::init_gradients();
+        let gen = WorldGen::new(7);
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        let (sender, _receiver) = channel();
+        let min = ivec3(0, gen.terrain_height(0, 0) - 16, 0);
+
+        let mut timings = FrameTimings::default();
+        profile_scope!(timings.build_chunks, {
+            gen.build_chunk(world.chunks[0].clone(), min, &mut world, sender)
+                .unwrap()
+        });
+        assert!(timings.build_chunks > Duration::ZERO);
+        assert_eq!(timings.total(), timings.build_chunks);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::gpu::Settings;
+use std::time::Duration;
+
+/// An expensive shader feature the quality manager can turn off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QualityFeature {
+    /// Lighting from nearby emissive voxels (`local_light_radius`).
+    LocalLights,
+    /// Several shadow rays per hit (`sun_softness`).
+    SoftShadows,
+    /// Short rays around each hit to darken corners (`ao_samples`).
+    AmbientOcclusion,
+    Clouds,
+    /// Ray bounces past the first.
+    ExtraBounces,
+    /// Samples per pixel past the first.
+    ExtraSamples,
+}
+impl QualityFeature {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::LocalLights => "local lights",
+            Self::SoftShadows => "soft shadows",
+            Self::AmbientOcclusion => "ambient occlusion",
+            Self::Clouds => "clouds",
+            Self::ExtraBounces => "extra bounces",
+            Self::ExtraSamples => "extra samples",
+        }
+    }
+
+    /// Turns this feature off in `settings`.
+    fn disable(self, settings: &mut Settings) {
+        match self {
+            Self::LocalLights => settings.local_light_radius = 0,
+            Self::SoftShadows => settings.sun_softness = 0.0,
+            Self::AmbientOcclusion => settings.ao_samples = 0,
+            Self::Clouds => settings.cloud_coverage = 0.0,
+            Self::ExtraBounces => settings.max_ray_bounces = settings.max_ray_bounces.min(1),
+            Self::ExtraSamples => settings.samples_per_pixel = settings.samples_per_pixel.min(1),
+        }
+    }
+}
+
+/// Turns off shader features, in priority order, while frames take longer than
+/// the target frame time, and turns them back on once there's headroom again.
+pub struct QualityManager {
+    pub enabled: bool,
+    /// The features to turn off, first to last.
+    pub priority: Vec<QualityFeature>,
+    /// How many features from the start of `priority` are off.
+    disabled: usize,
+    /// A smoothed frame time, in seconds.
+    avg_frame_time: f32,
+    /// Frames measured since the last feature was turned on or off.
+    settle_frames: u32,
+}
+impl Default for QualityManager {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            priority: vec![
+                QualityFeature::ExtraSamples,
+                QualityFeature::LocalLights,
+                QualityFeature::SoftShadows,
+                QualityFeature::AmbientOcclusion,
+                QualityFeature::Clouds,
+                QualityFeature::ExtraBounces,
+            ],
+            disabled: 0,
+            avg_frame_time: 0.0,
+            settle_frames: 0,
+        }
+    }
+}
+impl QualityManager {
+    /// How much the smoothed frame time follows each new frame.
+    const SMOOTHING: f32 = 0.1;
+    /// How many frames to measure after a change before making another.
+    const SETTLE_FRAMES: u32 = 30;
+    /// Features are only turned off once frames take this fraction longer than the target,
+    /// so frame times hovering around it don't toggle a feature back and forth.
+    const TOLERANCE: f32 = 0.1;
+    /// Features are turned back on once frames take less than this fraction of the target.
+    const HEADROOM: f32 = 0.7;
+
+    /// The features currently turned off.
+    pub fn disabled(&self) -> &[QualityFeature] {
+        &self.priority[..self.disabled.min(self.priority.len())]
+    }
+
+    /// Turns the disabled features off in `settings`.
+    pub fn apply(&self, settings: &mut Settings) {
+        for feature in self.disabled() {
+            feature.disable(settings);
+        }
+    }
+
+    /// Records how long the last frame's work took, returning whether a feature was
+    /// turned on or off (so the settings have to be written again).
+    pub fn update(&mut self, frame_time: Duration, target_fps: u32) -> bool {
+        let frame_time = frame_time.as_secs_f32();
+        self.avg_frame_time = match self.avg_frame_time {
+            0.0 => frame_time,
+            avg => avg + (frame_time - avg) * Self::SMOOTHING,
+        };
+        self.settle_frames = self.settle_frames.saturating_add(1);
+
+        let prev_disabled = self.disabled;
+        if !self.enabled || target_fps == 0 {
+            self.disabled = 0;
+        } else if self.settle_frames >= Self::SETTLE_FRAMES {
+            let target_time = 1.0 / target_fps as f32;
+            if self.avg_frame_time > target_time * (1.0 + Self::TOLERANCE)
+                && self.disabled < self.priority.len()
+            {
+                self.disabled += 1;
+            } else if self.avg_frame_time < target_time * Self::HEADROOM && self.disabled > 0 {
+                self.disabled -= 1;
+            }
+        }
+        if self.disabled == prev_disabled {
+            return false;
+        }
+        // frame times with the old features say nothing about the new ones
+        self.avg_frame_time = 0.0;
+        self.settle_frames = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `frames` frames that each take `frame_time` seconds at a 60 FPS target.
+    fn run(quality: &mut QualityManager, frame_time: f64, frames: u32) {
+        for _ in 0..frames {
+            quality.update(Duration::from_secs_f64(frame_time), 60);
+        }
+    }
+
+    #[test]
+    fn disables_in_priority_order() {
+        let mut quality = QualityManager {
+            enabled: true,
+            ..Default::default()
+        };
+        let priority = quality.priority.clone();
+        for count in 1..=priority.len() {
+            run(&mut quality, 0.1, QualityManager::SETTLE_FRAMES);
+            assert_eq!(quality.disabled(), &priority[..count]);
+        }
+        // and back on, last disabled first
+        for count in (0..priority.len()).rev() {
+            run(&mut quality, 0.001, QualityManager::SETTLE_FRAMES);
+            assert_eq!(quality.disabled(), &priority[..count]);
+        }
+    }
+
+    #[test]
+    fn tolerates_frames_near_the_target() {
+        let mut quality = QualityManager {
+            enabled: true,
+            ..Default::default()
+        };
+        run(
+            &mut quality,
+            1.05 / 60.0,
+            10 * QualityManager::SETTLE_FRAMES,
+        );
+        assert!(quality.disabled().is_empty());
+    }
+
+    #[test]
+    fn disabled_manager_keeps_every_feature() {
+        let mut quality = QualityManager::default();
+        run(&mut quality, 0.1, 10 * QualityManager::SETTLE_FRAMES);
+        assert!(quality.disabled().is_empty());
+    }
+}
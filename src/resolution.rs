@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+/// Scales the render resolution (`vertical_samples`) to keep the framerate near a target.
+pub struct DynamicResolution {
+    pub enabled: bool,
+    pub target_fps: u32,
+    /// The bounds for `vertical_samples`.
+    pub min_samples: u32,
+    pub max_samples: u32,
+    /// A smoothed frame time, in seconds.
+    avg_frame_time: f32,
+    /// Frames measured since the resolution last changed.
+    settle_frames: u32,
+}
+impl Default for DynamicResolution {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_fps: 60,
+            min_samples: 200,
+            max_samples: 1200,
+            avg_frame_time: 0.0,
+            settle_frames: 0,
+        }
+    }
+}
+impl DynamicResolution {
+    /// How much the smoothed frame time follows each new frame.
+    const SMOOTHING: f32 = 0.1;
+    /// How far from the target the framerate has to drift before the resolution changes,
+    /// so it doesn't change (and reset the accumulated image) every frame.
+    const TOLERANCE: f32 = 0.1;
+    /// The most the resolution can change by in a single frame.
+    const MAX_STEP: f32 = 0.1;
+    /// How many frames to measure at a new resolution before changing it again.
+    const SETTLE_FRAMES: u32 = 10;
+
+    /// Records how long the last frame's work took, returning the new number of
+    /// vertical samples if the resolution should change.
+    pub fn update(&mut self, frame_time: Duration, samples: u32) -> Option<u32> {
+        let frame_time = frame_time.as_secs_f32();
+        self.avg_frame_time = match self.avg_frame_time {
+            0.0 => frame_time,
+            avg => avg + (frame_time - avg) * Self::SMOOTHING,
+        };
+        self.settle_frames = self.settle_frames.saturating_add(1);
+        if !self.enabled || self.target_fps == 0 || self.settle_frames < Self::SETTLE_FRAMES {
+            return None;
+        }
+
+        let target_time = 1.0 / self.target_fps as f32;
+        let ratio = target_time / self.avg_frame_time;
+        if (ratio - 1.0).abs() < Self::TOLERANCE {
+            return None;
+        }
+        // The cost of a frame grows with the pixel count, the square of the resolution.
+        let scale = ratio
+            .sqrt()
+            .clamp(1.0 - Self::MAX_STEP, 1.0 + Self::MAX_STEP);
+        let new_samples = ((samples as f32 * scale) as u32)
+            .clamp(self.min_samples, self.max_samples.max(self.min_samples));
+        if new_samples == samples {
+            return None;
+        }
+        // frame times at the old resolution say nothing about the new one
+        self.avg_frame_time = 0.0;
+        self.settle_frames = 0;
+        Some(new_samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converges_on_target() {
+        let mut res = DynamicResolution {
+            enabled: true,
+            ..Default::default()
+        };
+        // a frame costs the same per pixel, and 600 samples take exactly the target time
+        let target_time = 1.0 / res.target_fps as f64;
+        let frame_time = |samples: u32| target_time * (samples as f64 / 600.0).powi(2);
+
+        let mut samples = res.max_samples;
+        let mut last_change = 0;
+        for frame in 0..1000 {
+            let time = Duration::from_secs_f64(frame_time(samples));
+            if let Some(new_samples) = res.update(time, samples) {
+                samples = new_samples;
+                last_change = frame;
+            }
+        }
+        assert!(last_change < 500, "still changing at frame {last_change}");
+        let ratio = frame_time(samples) / target_time;
+        assert!(
+            (ratio - 1.0).abs() < 0.15,
+            "settled {ratio}x off the target"
+        );
+    }
+
+    #[test]
+    fn stays_within_bounds() {
+        let mut res = DynamicResolution {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut samples = 600;
+        for _ in 0..500 {
+            if let Some(new_samples) = res.update(Duration::from_secs(1), samples) {
+                samples = new_samples;
+            }
+        }
+        assert_eq!(samples, res.min_samples);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::gpu::{texture::Texture, Gpu, ScreenSettings, ToneMap};
+use half::f16;
+use image::{Rgba, RgbaImage};
+use std::path::PathBuf;
+use std::time::SystemTime;
+use wgpu::TextureFormat;
+
+/// The directory screenshots are saved to, in the working directory.
+pub const SCREENSHOTS_DIR: &str = "screenshots";
+
+/// Saves `tex` to a PNG in `SCREENSHOTS_DIR` named after the current time,
+/// tone mapped with `settings` like `screen_shader.wgsl` does, and returns its path.
+pub fn save(gpu: &Gpu, tex: &Texture, settings: &ScreenSettings) -> Result<PathBuf, String> {
+    let texels = tex.read_to_cpu(gpu);
+    let colors: Vec<[f32; 3]> = match tex.handle.format() {
+        TextureFormat::Rgba16Float => texels
+            .chunks_exact(8)
+            .map(|texel| {
+                let channel = |i: usize| f16::from_le_bytes([texel[i], texel[i + 1]]).to_f32();
+                [channel(0), channel(2), channel(4)]
+            })
+            .collect(),
+        TextureFormat::Rgba8Unorm => texels
+            .chunks_exact(4)
+            .map(|texel| [texel[0], texel[1], texel[2]].map(|c| c as f32 / 255.0))
+            .collect(),
+        format => return Err(format!("can't save a texture of format {format:?}")),
+    };
+
+    // average the supersampled texels of each pixel, like the screen shader
+    let n = settings.supersample.max(1);
+    let size = tex.size() / n;
+    let srgb = gpu.surface_config.format.is_srgb();
+    let image = RgbaImage::from_fn(size.x, size.y, |x, y| {
+        let mut sum = [0.0; 3];
+        for sy in 0..n {
+            for sx in 0..n {
+                let color = colors[((y * n + sy) * tex.size().x + x * n + sx) as usize];
+                (0..3).for_each(|i| sum[i] += color[i]);
+            }
+        }
+        let [r, g, b] = tone_map(settings, sum.map(|c| c / (n * n) as f32)).map(|v| {
+            // the screen stores sRGB encoded colors, and so should the PNG
+            let v = if srgb { linear_to_srgb(v) } else { v };
+            (v.clamp(0.0, 1.0) * 255.0).round() as u8
+        });
+        // the alpha channel holds depth, not coverage
+        Rgba([r, g, b, 255])
+    });
+
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let path = PathBuf::from(SCREENSHOTS_DIR).join(format!("{}.png", time.as_millis()));
+    std::fs::create_dir_all(SCREENSHOTS_DIR).map_err(|err| err.to_string())?;
+    image.save(&path).map_err(|err| err.to_string())?;
+    Ok(path)
+}
+
+/// The same mapping as `tone_map` in `screen_shader.wgsl`.
+fn tone_map(settings: &ScreenSettings, color: [f32; 3]) -> [f32; 3] {
+    let exposure = settings.exposure.exp2();
+    let gamma = settings.gamma.max(0.01);
+    color.map(|c| {
+        let c = c.max(0.0) * exposure;
+        let c = match settings.tone_map {
+            x if x == ToneMap::Reinhard as u32 => c / (c + 1.0),
+            x if x == ToneMap::Aces as u32 => {
+                ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+            _ => c.min(1.0),
+        };
+        c.powf(1.0 / gamma)
+    })
+}
+
+fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -0,0 +1,62 @@
+use glam::IVec3;
+
+/// A box of voxels, picked by its two opposite corners.
+#[derive(Clone, Default)]
+pub struct Selection {
+    first: Option<IVec3>,
+    second: Option<IVec3>,
+}
+impl Selection {
+    /// Sets the next corner. Picking after both corners are set starts a new selection.
+    pub fn pick(&mut self, pos: IVec3) {
+        match (self.first, self.second) {
+            (Some(_), None) => self.second = Some(pos),
+            _ => {
+                *self = Self {
+                    first: Some(pos),
+                    second: None,
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The corners picked so far.
+    pub fn corners(&self) -> (Option<IVec3>, Option<IVec3>) {
+        (self.first, self.second)
+    }
+
+    /// The selected region as `(min, max)`, both inclusive, once both corners are picked.
+    pub fn region(&self) -> Option<(IVec3, IVec3)> {
+        let (a, b) = (self.first?, self.second?);
+        Some((a.min(b), a.max(b)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::ivec3;
+
+    #[test]
+    fn picked_corners_make_a_normalized_region() {
+        let mut selection = Selection::default();
+        selection.pick(ivec3(5, -2, 9));
+        assert_eq!(selection.region(), None);
+        selection.pick(ivec3(-3, 7, 9));
+        assert_eq!(selection.region(), Some((ivec3(-3, -2, 9), ivec3(5, 7, 9))));
+
+        // picking again redefines the corners from scratch
+        selection.pick(ivec3(1, 1, 1));
+        assert_eq!(selection.corners(), (Some(ivec3(1, 1, 1)), None));
+        assert_eq!(selection.region(), None);
+        selection.pick(ivec3(0, 4, -6));
+        assert_eq!(selection.region(), Some((ivec3(0, 1, -6), ivec3(1, 4, 1))));
+
+        selection.clear();
+        assert_eq!(selection.corners(), (None, None));
+    }
+}
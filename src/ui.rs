@@ -1,8 +1,12 @@
 use crate::gpu::Settings as ShaderSettings;
+use crate::inventory::Inventory;
+use crate::math::aabb::Aabb;
+use crate::player::GameMode;
 use crate::world::data::Material;
+use crate::world::{vox_to_chunk_pos, PlaceMode, Voxel};
 use crate::{FrameInput, GameState, UpdateResult};
 use egui::*;
-use glam::vec3;
+use glam::{vec3, Mat3, Vec3};
 
 #[derive(Default)]
 pub struct UiResult {
@@ -30,9 +34,224 @@ pub fn draw_ui(
     egui::SidePanel::left("left").frame(frame).show(ctx, |ui| {
         left_panel_ui(state, frame_i, update, ui, &mut result);
     });
+    if state.show_compass {
+        draw_compass(state, ctx);
+    }
+    draw_hotbar(state, ctx);
+    let mut wireframes = vec![];
+    if state.show_hitboxes {
+        wireframes.extend(hitboxes(state));
+    }
+    wireframes.extend(selection_box(state));
+    draw_wireframes(state, ctx, &wireframes);
+    if state.console.open {
+        egui::TopBottomPanel::bottom("console")
+            .frame(frame)
+            .show(ctx, |ui| console_ui(state, ui));
+    }
     result
 }
 
+/// Shows the player's coordinates, chunk and compass bearing in the top right corner.
+fn draw_compass(state: &GameState, ctx: &Context) {
+    const DIRECTIONS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    let pos = state.player.pos;
+    let chunk = vox_to_chunk_pos(pos.floor().as_ivec3());
+    let bearing = state.player.bearing();
+    let direction = DIRECTIONS[((bearing / 45.0).round() as usize) % DIRECTIONS.len()];
+
+    Area::new("compass")
+        .anchor(Align2::RIGHT_TOP, vec2(-10.0, 10.0))
+        .interactable(false)
+        .show(ctx, |ui| {
+            let text = format!(
+                "{:.1} / {:.1} / {:.1}\nchunk {} / {} / {}\n{direction} ({bearing:.0}°)",
+                pos.x, pos.y, pos.z, chunk.x, chunk.y, chunk.z,
+            );
+            ui.label(RichText::new(text).monospace().color(Color32::WHITE));
+        });
+}
+
+/// The active inventory row, along the bottom of the screen.
+fn draw_hotbar(state: &GameState, ctx: &Context) {
+    let inventory = &state.inventory;
+    let start = inventory.active_row() * Inventory::ROW_LEN;
+    Area::new("hotbar")
+        .anchor(Align2::CENTER_BOTTOM, vec2(0.0, -10.0))
+        .interactable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for idx in start..start + Inventory::ROW_LEN {
+                    let name = inventory.get(idx).map_or("", |v| state.voxels.name(v));
+                    let color = match idx == inventory.selected_idx() {
+                        true => Color32::YELLOW,
+                        false => Color32::WHITE,
+                    };
+                    let mut text = format!("{}: {name}", idx - start + 1);
+                    // placing uses voxels up, so show how many are left
+                    if !state.player.mode.unlimited_voxels() && inventory.get(idx).is_some() {
+                        text += &format!(" x{}", inventory.count(idx));
+                    }
+                    ui.label(RichText::new(text).monospace().color(color));
+                }
+            });
+        });
+}
+
+/// Every inventory slot, clicking one to select it, and a picker to put any voxel in the selected slot.
+fn inventory_ui(state: &mut GameState, ui: &mut Ui) {
+    let inventory = &mut state.inventory;
+    for row in 0..Inventory::ROWS {
+        ui.horizontal_wrapped(|ui| {
+            for idx in row * Inventory::ROW_LEN..(row + 1) * Inventory::ROW_LEN {
+                let name = inventory.get(idx).map_or("-", |v| state.voxels.name(v));
+                if ui
+                    .selectable_label(idx == inventory.selected_idx(), name)
+                    .clicked()
+                {
+                    inventory.select(idx);
+                }
+            }
+        });
+    }
+    let idx = inventory.selected_idx();
+    let mut slot = inventory.get(idx).unwrap_or(Voxel::AIR);
+    ComboBox::from_label("in selected slot")
+        .selected_text(match slot {
+            Voxel::AIR => "nothing",
+            voxel => state.voxels.name(voxel),
+        })
+        .show_ui(ui, |ui| {
+            ui.selectable_value(&mut slot, Voxel::AIR, "nothing");
+            for id in 1..state.voxels.len() {
+                let voxel = Voxel(id as u16);
+                ui.selectable_value(&mut slot, voxel, state.voxels.name(voxel));
+            }
+        });
+    inventory.set(idx, slot);
+}
+
+/// The player's collision box, and the solid voxels around it.
+fn hitboxes(state: &GameState) -> Vec<(Aabb, Color32)> {
+    let player_aabb = state.player.create_aabb();
+    let nearby = Aabb::new(player_aabb.from - 1.0, player_aabb.to + 1.0);
+    let mut boxes: Vec<_> = (state.world.get_collisions_w(&nearby, false).into_iter())
+        .map(|aabb| (aabb, Color32::YELLOW))
+        .collect();
+    boxes.push((player_aabb, Color32::RED));
+    boxes
+}
+
+/// The selected region, or just its first corner while the second isn't picked yet.
+fn selection_box(state: &GameState) -> Option<(Aabb, Color32)> {
+    let (min, max) = match state.selection.corners() {
+        (Some(a), Some(b)) => (a.min(b), a.max(b)),
+        (Some(a), None) => (a, a),
+        _ => return None,
+    };
+    let aabb = Aabb::new(min.as_vec3(), (max + 1).as_vec3());
+    Some((aabb, Color32::LIGHT_BLUE))
+}
+
+/// Draws `boxes` as wireframes over the rendered world.
+fn draw_wireframes(state: &GameState, ctx: &Context, boxes: &[(Aabb, Color32)]) {
+    if boxes.is_empty() {
+        return;
+    }
+    let screen = ctx.screen_rect();
+    let player = &state.player;
+    let cam_pos = player.camera_pos(&state.world);
+    let cam_rot = Mat3::from_mat4(player.create_view_mat());
+    let inv_proj = player
+        .create_proj_mat(screen.width() / screen.height())
+        .inverse();
+    let (scale_x, scale_y) = (inv_proj.x_axis.x, inv_proj.y_axis.y);
+
+    let project = |p: Vec3| {
+        let cam_space = cam_rot * (p - cam_pos);
+        if cam_space.z >= -0.01 {
+            // behind the camera
+            return None;
+        }
+        let x = cam_space.x / -cam_space.z / scale_x;
+        let y = -cam_space.y / -cam_space.z / scale_y;
+        Some(pos2(
+            screen.left() + (x + 1.0) * 0.5 * screen.width(),
+            screen.top() + (y + 1.0) * 0.5 * screen.height(),
+        ))
+    };
+    let painter = ctx.layer_painter(LayerId::background());
+    let draw_aabb = |aabb: &Aabb, color: Color32| {
+        let corner = |i: usize| {
+            vec3(
+                if i & 1 == 0 { aabb.from.x } else { aabb.to.x },
+                if i & 2 == 0 { aabb.from.y } else { aabb.to.y },
+                if i & 4 == 0 { aabb.from.z } else { aabb.to.z },
+            )
+        };
+        // every pair of corners that differ along exactly one axis is an edge
+        for a in 0..8 {
+            for bit in [1, 2, 4] {
+                if a & bit != 0 {
+                    continue;
+                }
+                let (Some(p0), Some(p1)) = (project(corner(a)), project(corner(a | bit))) else {
+                    continue;
+                };
+                painter.line_segment([p0, p1], Stroke::new(1.0, color));
+            }
+        }
+    };
+
+    for (aabb, color) in boxes {
+        draw_aabb(aabb, *color);
+    }
+}
+
+fn console_ui(state: &mut GameState, ui: &mut Ui) {
+    ScrollArea::vertical()
+        .max_height(200.0)
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            for line in &state.console.output {
+                ui.label(RichText::new(line).monospace().color(Color32::WHITE));
+            }
+        });
+
+    let (up, down, enter) = ui.input(|i| {
+        (
+            i.key_pressed(egui::Key::ArrowUp),
+            i.key_pressed(egui::Key::ArrowDown),
+            i.key_pressed(egui::Key::Enter),
+        )
+    });
+    if up {
+        state.console.history_prev();
+    }
+    if down {
+        state.console.history_next();
+    }
+
+    let edit = ui.add(
+        TextEdit::singleline(&mut state.console.input)
+            .font(TextStyle::Monospace)
+            .desired_width(f32::INFINITY),
+    );
+    // The toggle key also types a character into the focused text field.
+    state.console.input.retain(|c| c != '`');
+
+    if edit.lost_focus() && enter {
+        if let Some(line) = state.console.submit() {
+            match crate::console::execute(state, &line) {
+                Ok(out) if out.is_empty() => {}
+                Ok(out) => state.console.print(out),
+                Err(err) => state.console.print(err),
+            }
+        }
+    }
+    edit.request_focus();
+}
+
 fn left_panel_ui(
     state: &mut GameState,
     frame: &FrameInput,
@@ -77,13 +296,25 @@ fn left_panel_ui(
         ui.label(RichText::new(label).color(color));
     }
 
-    let in_hand = crate::INVENTORY[state.inv_sel as usize];
+    let in_hand = state.inventory.selected();
     let white = Color32::WHITE;
 
     ui.add_space(3.0);
     label(ui, &format!("fps: {}", frame.fps), white);
+    value_u32(ui, "max fps (0 = unlimited)", &mut state.max_fps, 0, 240);
+    let mut vsync = state.gpu.vsync();
+    if toggle_bool(ui, "vsync", &mut vsync) {
+        state.gpu.set_vsync(vsync);
+    }
     ui.add_space(3.0);
-    label(ui, &format!("place: {:?}", in_hand.display_name()), white);
+    label(
+        ui,
+        &match in_hand {
+            Some(voxel) => format!("place: {:?}", state.voxels.name(voxel)),
+            None => "place: nothing".to_owned(),
+        },
+        white,
+    );
     ui.add_space(3.0);
 
     let (pos, dir) = (state.player.pos, state.player.facing());
@@ -101,10 +332,99 @@ fn left_panel_ui(
         if dir.z < 0.0 { "-Z" } else { "+Z" },
     );
     label(ui, &facing, white);
-    toggle_bool(ui, "flying (Z)", &mut state.player.flying);
+    let light = state
+        .world
+        .light_level(state.player.eye_pos().floor().as_ivec3());
+    label(ui, &format!("light level: {light}"), white);
+    if let Some(still) = &state.still {
+        let frames = state.frame_count.min(still.target_frames);
+        label(
+            ui,
+            &format!("still: {frames}/{} frames", still.target_frames),
+            white,
+        );
+        if ui.button("stop still").clicked() {
+            state.still = None;
+        }
+    }
+    toggle_bool(ui, "compass", &mut state.show_compass);
+    ui.horizontal(|ui| {
+        ui.label("game mode:");
+        let mut mode = state.player.mode;
+        ui.radio_value(&mut mode, GameMode::Creative, GameMode::Creative.name());
+        ui.radio_value(&mut mode, GameMode::Survival, GameMode::Survival.name());
+        if mode != state.player.mode {
+            state.player.set_mode(mode);
+        }
+    });
+    if let Some((_, frames)) = state.player.mining {
+        let needed = state.player.mode.break_frames();
+        label(ui, &format!("mining: {frames}/{needed}"), white);
+    }
+    ui.add_enabled_ui(state.player.mode.can_fly(), |ui| {
+        toggle_bool(ui, "flying (Z)", &mut state.player.flying);
+        toggle_bool(ui, "noclip (X)", &mut state.player.noclip);
+    });
+    toggle_bool(ui, "world edge barrier", &mut state.player.world_barrier);
+    toggle_bool(ui, "auto jump", &mut state.player.auto_jump);
+    value_f32(ui, "liquid drag", &mut state.player.liquid_drag, 0.0, 1.0);
+    value_u32(ui, "brush radius", &mut state.brush_radius, 0, 16);
+    ui.horizontal(|ui| {
+        ui.label("place over:");
+        let mode = &mut state.place_mode;
+        ui.radio_value(mode, PlaceMode::ReplaceEmpty, "empty");
+        ui.radio_value(mode, PlaceMode::ReplaceAny, "any");
+        let matching = matches!(mode, PlaceMode::ReplaceMatching(_));
+        if ui.radio(matching, "targeted").clicked() {
+            // match whatever the player is looking at
+            if let Some(hit) = state.player.cast_ray(&state.world) {
+                let target = state.world.get_voxel(hit.pos).unwrap_or(Voxel::AIR);
+                *mode = PlaceMode::ReplaceMatching(target);
+            }
+        }
+        if let PlaceMode::ReplaceMatching(target) = *mode {
+            ui.label(state.voxels.name(target));
+        }
+    });
     value_f32(ui, "speed", &mut state.player.speed, 0.1, 10.0);
+    value_f32(ui, "reach", &mut state.player.reach, 1.0, 100.0);
+    value_f32(ui, "near plane", &mut state.player.near, 0.0001, 1.0);
+    value_f32(ui, "far plane", &mut state.player.far, 10.0, 10_000.0);
+    ui.collapsing("mouse", |ui| {
+        let mouse = &mut state.player.mouse;
+        value_f32(ui, "sensitivity", &mut mouse.sensitivity, 0.01, 2.0);
+        value_f32(ui, "acceleration", &mut mouse.acceleration, 0.0, 0.2);
+        value_f32(
+            ui,
+            "max acceleration",
+            &mut mouse.max_acceleration,
+            1.0,
+            10.0,
+        );
+        value_f32(ui, "slow smoothing", &mut mouse.slow_smoothing, 0.0, 0.95);
+        value_f32(ui, "fast smoothing", &mut mouse.fast_smoothing, 0.0, 0.95);
+        value_f32(ui, "fast threshold", &mut mouse.fast_threshold, 1.0, 200.0);
+    });
+
+    ui.collapsing("inventory", |ui| inventory_ui(state, ui));
 
     value_u32(ui, "max chunk builders", &mut state.max_threads, 1, 32);
+    label(
+        ui,
+        &format!(
+            "loaded chunks: {}/{}",
+            state.world.loaded_chunks().count(),
+            state.world.chunk_count()
+        ),
+        white,
+    );
+    value_f32(
+        ui,
+        "build view weight",
+        &mut state.build_view_weight,
+        0.0,
+        1.0,
+    );
     label(
         ui,
         &format!("chunk builders: {}", state.chunk_builders.len()),
@@ -115,40 +435,156 @@ fn left_panel_ui(
         &format!("queued features: {}", state.features_queue.len()),
         white,
     );
+    if cfg!(feature = "profiling") {
+        let timings = state.frame_timings();
+        let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+        label(
+            ui,
+            &format!(
+                "world update: {:.2}ms (move {:.2}, build {:.2}, features {:.2}, upload {:.2})",
+                ms(timings.total()),
+                ms(timings.move_world),
+                ms(timings.build_chunks),
+                ms(timings.place_features),
+                ms(timings.upload_chunks),
+            ),
+            white,
+        );
+    }
+    if state.paused {
+        label(ui, "SIMULATION PAUSED", Color32::YELLOW);
+    }
+    toggle_bool(ui, "pause simulation (P)", &mut state.paused);
     toggle_bool(ui, "move world (N)", &mut state.move_world);
     toggle_bool(ui, "build chunks (M)", &mut state.build_chunks);
+    toggle_bool(ui, "x-ray", &mut state.xray);
+    toggle_bool(ui, "hitboxes (F4)", &mut state.show_hitboxes);
+    if state.xray {
+        for (pos, voxel) in state.player.xray(&state.world, 8) {
+            label(
+                ui,
+                &format!(
+                    "{}/{}/{}: {}",
+                    pos.x,
+                    pos.y,
+                    pos.z,
+                    state.voxels.name(voxel)
+                ),
+                white,
+            );
+        }
+    }
 
     ui.separator();
     let mut changed = false;
 
     ui.collapsing("shader", |ui| {
+        let mut split = state.split_screen();
+        if toggle_bool(ui, "split screen", &mut split) {
+            state.settings.split_screen = split as u32;
+            // both halves start out the same, to change one of them
+            state.split_settings = state.settings;
+            state.editing_split = false;
+            changed = true;
+        }
+        if split {
+            toggle_bool(ui, "edit the right half", &mut state.editing_split);
+        }
         let ShaderSettings {
             max_ray_bounces,
             sky_color,
             sun_pos,
             sun_intensity,
-            show_step_count,
             samples_per_pixel,
+            debug_mode,
+            cloud_coverage,
+            cloud_altitude,
+            taa_blend,
+            sun_softness,
+            write_gbuffer,
+            local_light_radius,
+            ao_samples,
+            void_color,
+            ao_radius,
+            rr_min_bounces,
+            emissive_lighting,
             ..
-        } = &mut state.settings;
+        } = match split && state.editing_split {
+            true => &mut state.split_settings,
+            false => &mut state.settings,
+        };
 
         if toggle_bool(ui, "path tracing", &mut state.path_tracing) {
             state
                 .gpu_res
-                .resize_result_texture(&state.gpu, state.gpu_res.result_texture.size());
+                .resize_result_texture(&state.gpu, state.gpu_res.result_size());
             state.frame_count = 0;
         }
-        changed |= toggle_u32(ui, "show ray steps", show_step_count);
+        ui.add_space(SPACING);
+        if ui
+            .button(format!("debug view: {} (F3)", state.debug_mode.name()))
+            .clicked()
+        {
+            state.debug_mode = state.debug_mode.next();
+            *debug_mode = state.debug_mode as u32;
+            changed = true;
+        }
         changed |= value_u32(ui, "max ray bounces", max_ray_bounces, 0, 20);
+        changed |= value_u32(ui, "min bounces before RR", rr_min_bounces, 0, 20);
         changed |= value_u32(ui, "samples/pixel", samples_per_pixel, 0, 20);
+        changed |= value_u32(
+            ui,
+            "max samples/dispatch",
+            &mut state.max_samples_per_dispatch,
+            1,
+            20,
+        );
         changed |= color_picker(ui, "sky color", sky_color);
+        changed |= color_picker(ui, "void color", void_color);
         changed |= value_f32(ui, "sun intensity", sun_intensity, 0.0, 100.0);
+        ui.add_space(SPACING);
+        let mut screen_changed = false;
+        if ui
+            .button(format!("tone mapping: {}", state.tone_map.name()))
+            .clicked()
+        {
+            state.tone_map = state.tone_map.next();
+            state.screen_settings.tone_map = state.tone_map as u32;
+            screen_changed = true;
+        }
+        let screen = &mut state.screen_settings;
+        screen_changed |= value_f32(ui, "exposure (stops)", &mut screen.exposure, -4.0, 4.0);
+        screen_changed |= value_f32(ui, "gamma", &mut screen.gamma, 0.5, 2.5);
+        if screen_changed {
+            // only affects the final blit, so the accumulated samples stay valid
+            let buffer = &state.gpu_res.screen_shader.settings;
+            buffer.write(&state.gpu, &state.screen_settings);
+        }
+        changed |= value_f32(ui, "cloud coverage", cloud_coverage, 0.0, 1.0);
+        changed |= value_f32(ui, "cloud altitude", cloud_altitude, 0.0, 1000.0);
+        changed |= value_f32(ui, "TAA blend", taa_blend, 0.0, 0.95);
+        changed |= value_f32(ui, "sun softness", sun_softness, 0.0, 0.5);
+        changed |= value_u32(ui, "local light radius", local_light_radius, 0, 8);
+        if toggle_u32(ui, "emissive lighting (path)", emissive_lighting) {
+            changed = true;
+            // emitters are only sampled within the local light radius
+            if *emissive_lighting != 0 && *local_light_radius == 0 {
+                *local_light_radius = 3;
+            }
+        }
+        changed |= value_u32(ui, "AO samples", ao_samples, 0, 16);
+        changed |= value_f32(ui, "AO radius", ao_radius, 0.1, 8.0);
+        let mut gbuffer = *write_gbuffer != 0;
+        if toggle_bool(ui, "write g-buffer", &mut gbuffer) {
+            *write_gbuffer = gbuffer as u32;
+            changed = true;
+        }
         if value_f32(ui, "sun pos", &mut state.sun_angle, 0.0, 360.0) {
             changed = true;
             *sun_pos = vec3(
                 state.sun_angle.to_radians().sin() * 500.0,
                 state.sun_angle.to_radians().cos() * 500.0,
-                state.world.size() as f32 * 0.5,
+                state.world.size3().z as f32 * 0.5,
             )
             .to_array();
             result.clear_result = true;
@@ -162,11 +598,62 @@ fn left_panel_ui(
         ) {
             result.clear_result = true;
         }
+        let mut supersample = state.settings.supersample;
+        if value_u32(ui, "supersampling", &mut supersample, 1, 4) {
+            state.set_supersample(supersample);
+        }
+        ui.checkbox(&mut state.dynamic_res.enabled, "dynamic resolution");
+        ui.checkbox(&mut state.quality.enabled, "adaptive quality");
+        if state.dynamic_res.enabled || state.quality.enabled {
+            value_u32(ui, "target fps", &mut state.dynamic_res.target_fps, 10, 240);
+        }
+        if state.dynamic_res.enabled {
+            let res = &mut state.dynamic_res;
+            value_u32(ui, "min vertical samples", &mut res.min_samples, 50, 2000);
+            value_u32(ui, "max vertical samples", &mut res.max_samples, 50, 2000);
+        }
+        if state.quality.enabled {
+            let disabled = state.quality.disabled().len();
+            ui.label("features, first turned off first:");
+            let priority = &mut state.quality.priority;
+            for i in 0..priority.len() {
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(i > 0, Button::new("^")).clicked() {
+                        priority.swap(i - 1, i);
+                    }
+                    let color = match i < disabled {
+                        true => Color32::GRAY,
+                        false => Color32::WHITE,
+                    };
+                    label(ui, priority[i].name(), color);
+                });
+            }
+        }
+    });
+
+    ui.separator();
+
+    ui.collapsing("sun", |ui| {
+        let mut sun_changed = toggle_bool(ui, "from time of day", &mut state.sun_from_time);
+        if state.sun_from_time {
+            sun_changed |= value_f32(ui, "latitude", &mut state.latitude, -90.0, 90.0);
+            sun_changed |= value_f32(ui, "day of year", &mut state.day_of_year, 0.0, 365.0);
+            sun_changed |= value_f32(ui, "time of day", &mut state.time_of_day, 0.0, 24.0);
+            value_f32(ui, "minutes/frame", &mut state.day_cycle_speed, 0.0, 60.0);
+        }
+        if sun_changed && state.sun_from_time {
+            state.update_sun_pos();
+            changed = true;
+        }
     });
 
     ui.separator();
 
     ui.collapsing("visuals", |ui| {
+        let Some(in_hand) = in_hand else {
+            ui.label("(select a voxel to edit its material)");
+            return;
+        };
         let mut changed2 = false;
 
         let Material {
@@ -177,8 +664,10 @@ fn left_panel_ui(
             polish_color,
             polish_scatter,
             translucency,
+            metallic,
+            roughness,
             ..
-        } = &mut state.voxel_materials[in_hand.0 as usize];
+        } = state.voxels.material_mut(in_hand);
 
         changed2 |= value_f32(ui, "scatter", scatter, 0.0, 1.0);
         changed2 |= value_f32(ui, "emission", emission, 0.0, 10.0);
@@ -187,20 +676,21 @@ fn left_panel_ui(
         changed2 |= color_picker(ui, "color", color);
         changed2 |= color_picker(ui, "polish color", polish_color);
         changed2 |= value_f32(ui, "translucency", translucency, 0.0, 1.0);
+        changed2 |= value_f32(ui, "metallic", metallic, 0.0, 1.0);
+        changed2 |= value_f32(ui, "roughness", roughness, 0.0, 1.0);
 
         if changed2 {
             state.gpu_res.buffers.voxel_materials.write_slice(
                 &state.gpu,
                 0,
-                &state.voxel_materials,
+                state.voxels.materials(),
             );
             result.clear_result = true;
         }
     });
 
     if changed {
-        let settings = &state.settings;
-        state.gpu_res.buffers.settings.write(&state.gpu, settings);
+        state.write_settings();
         result.clear_result = true;
     }
 }
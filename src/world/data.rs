@@ -1,3 +1,6 @@
+use super::Voxel;
+use std::collections::HashMap;
+
 pub static VOXEL_NAMES: &[&str] = &[
     "Air",
     "Stone",
@@ -45,8 +48,8 @@ pub static VOXEL_MATERIALS: &[Material] = &[
     Material::solid([0.06, 0.04, 0.00], 1.0),                     // Spruce Wood
     Material::solid([0.04, 0.22, 0.00], 1.0),                     // Spruce Leaves
     Material::solid([0.0, 0.30, 0.0], 1.0),                       // Cactus
-    Material::solid([0.83, 0.68, 0.22], 0.3),                     // Gold
-    Material::solid([1.0; 3], 0.0),                               // Mirror
+    Material::solid([0.83, 0.68, 0.22], 0.3).metallic(1.0),       // Gold
+    Material::solid([1.0; 3], 0.0).metallic(1.0),                 // Mirror
     Material::solid([1.0; 3], 1.0).emit(5.0),                     // Bright
 ];
 
@@ -61,6 +64,9 @@ pub struct Material {
     pub translucency: f32,
     pub polish_color: [f32; 3],
     pub polish_scatter: f32,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub _padding: [u32; 2],
 }
 impl Material {
     pub const ZERO: Self = Self {
@@ -72,6 +78,9 @@ impl Material {
         translucency: 0.0,
         polish_color: [0.0; 3],
         polish_scatter: 0.0,
+        metallic: 0.0,
+        roughness: 0.0,
+        _padding: [0; 2],
     };
 
     pub const fn empty() -> Self {
@@ -80,13 +89,26 @@ impl Material {
         rs
     }
 
+    /// A dielectric material. `scatter` is also used as the roughness,
+    /// so that scatter-based materials keep roughly the same look.
     pub const fn solid(color: [f32; 3], scatter: f32) -> Self {
         let mut rs = Self::ZERO;
         rs.color = color;
         rs.scatter = scatter;
+        rs.roughness = scatter;
         rs
     }
 
+    pub const fn metallic(mut self, metallic: f32) -> Self {
+        self.metallic = metallic;
+        self
+    }
+
+    pub const fn roughness(mut self, roughness: f32) -> Self {
+        self.roughness = roughness;
+        self
+    }
+
     pub const fn translucent(mut self, t: f32) -> Self {
         self.translucency = t;
         self
@@ -104,3 +126,75 @@ impl Material {
         self
     }
 }
+
+/// Resolves the built-in voxel types by name, and holds their materials so they
+/// can be edited at runtime. Ids are the `Voxel` constants, so `Voxel::AIR` is 0.
+#[derive(Clone)]
+pub struct VoxelRegistry {
+    materials: Vec<Material>,
+    ids: HashMap<String, Voxel>,
+}
+impl Default for VoxelRegistry {
+    fn default() -> Self {
+        let ids = VOXEL_NAMES
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (Self::key(name), Voxel(id as u16)))
+            .collect();
+        Self {
+            materials: VOXEL_MATERIALS.to_vec(),
+            ids,
+        }
+    }
+}
+impl VoxelRegistry {
+    fn key(name: &str) -> String {
+        name.trim().replace('_', " ").to_lowercase()
+    }
+
+    /// Finds a voxel by name, ignoring case and treating `_` as a space.
+    pub fn get(&self, name: &str) -> Option<Voxel> {
+        self.ids.get(&Self::key(name)).copied()
+    }
+
+    pub fn name(&self, voxel: Voxel) -> &'static str {
+        VOXEL_NAMES.get(voxel.0 as usize).unwrap_or(&"Unknown")
+    }
+
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    pub fn material_mut(&mut self, voxel: Voxel) -> &mut Material {
+        &mut self.materials[voxel.0 as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_names() {
+        let voxels = VoxelRegistry::default();
+        assert_eq!(voxels.len(), VOXEL_NAMES.len());
+        assert_eq!(voxels.get("air"), Some(Voxel::AIR));
+        assert_eq!(voxels.get("Stone"), Some(Voxel::STONE));
+        assert_eq!(voxels.get(" oak_leaves "), Some(Voxel::OAK_LEAVES));
+        assert_eq!(voxels.get("Spruce Wood"), Some(Voxel::SPRUCE_WOOD));
+        assert_eq!(voxels.get("unobtainium"), None);
+        for id in 0..voxels.len() {
+            let voxel = Voxel(id as u16);
+            assert_eq!(voxels.get(voxels.name(voxel)), Some(voxel));
+        }
+        assert_eq!(voxels.name(Voxel(voxels.len() as u16)), "Unknown");
+    }
+}
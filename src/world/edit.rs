@@ -0,0 +1,189 @@
+use super::snapshot::WorldSnapshot;
+use super::{
+    coalesce_ranges, vox_to_chunk_pos, NodeAddr, PlaceMode, Voxel, World, WorldErr, CHUNK_SIZE,
+};
+use glam::{ivec3, IVec3};
+use std::ops::Range;
+
+/// A batch of edits, made through `World::edit`.
+/// Each chunk is locked (and snapshotted, to roll back) the first time the batch touches it.
+pub struct WorldEdit<'a> {
+    world: &'a mut World,
+    owner: &'a str,
+    /// The allocs of the chunks locked so far.
+    locked: Vec<u32>,
+    snapshots: Vec<WorldSnapshot>,
+    changed: Vec<Range<NodeAddr>>,
+}
+impl<'a> WorldEdit<'a> {
+    /// Locks and snapshots every chunk overlapping the region between `min` and `max` (inclusive)
+    /// that isn't already. Chunks outside of the world are skipped.
+    pub fn lock_region(&mut self, min: IVec3, max: IVec3) -> Result<(), WorldErr> {
+        let (min_chunk, max_chunk) = (
+            vox_to_chunk_pos(min.min(max)),
+            vox_to_chunk_pos(min.max(max)),
+        );
+        for x in min_chunk.x..=max_chunk.x {
+            for y in min_chunk.y..=max_chunk.y {
+                for z in min_chunk.z..=max_chunk.z {
+                    let Some(idx) = self.world.chunk_idx(ivec3(x, y, z)) else {
+                        continue;
+                    };
+                    let alloc = self.world.chunks[idx as usize].alloc;
+                    if self.locked.contains(&alloc) {
+                        continue;
+                    }
+                    if !self.world.lock_chunk(alloc, self.owner) {
+                        return Err(WorldErr::Locked);
+                    }
+                    self.locked.push(alloc);
+                    let chunk_min = ivec3(x, y, z) * CHUNK_SIZE as i32;
+                    self.snapshots
+                        .push(self.world.snapshot(chunk_min, chunk_min));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_voxel(&self, pos: IVec3) -> Result<Voxel, WorldErr> {
+        self.world.get_voxel(pos)
+    }
+
+    pub fn set_voxel(&mut self, pos: IVec3, voxel: Voxel) -> Result<(), WorldErr> {
+        self.lock_region(pos, pos)?;
+        let changed = &mut self.changed;
+        self.world
+            .set_voxel(pos, voxel, |range| changed.push(range))
+    }
+
+    /// Like `World::fill_box`.
+    pub fn fill_box(
+        &mut self,
+        a: IVec3,
+        b: IVec3,
+        voxel: Voxel,
+        mode: PlaceMode,
+    ) -> Result<(), WorldErr> {
+        self.lock_region(a, b)?;
+        let changed = &mut self.changed;
+        self.world
+            .fill_box(a, b, voxel, mode, |range| changed.push(range))
+    }
+
+    /// Like `World::set_voxels`.
+    pub fn set_voxels(&mut self, a: IVec3, b: IVec3, voxel: Voxel) -> Result<(), WorldErr> {
+        self.lock_region(a, b)?;
+        let changed = &mut self.changed;
+        self.world
+            .set_voxels(a, b, voxel, |range| changed.push(range))
+    }
+
+    /// Like `World::set_line`.
+    pub fn set_line(
+        &mut self,
+        a: IVec3,
+        b: IVec3,
+        voxel: Voxel,
+        mode: PlaceMode,
+    ) -> Result<(), WorldErr> {
+        self.lock_region(a, b)?;
+        let changed = &mut self.changed;
+        self.world
+            .set_line(a, b, voxel, mode, |range| changed.push(range))
+    }
+
+    /// Like `World::set_sphere`.
+    pub fn set_sphere(
+        &mut self,
+        pos: IVec3,
+        r: u32,
+        voxel: Voxel,
+        decay: f32,
+        mode: PlaceMode,
+    ) -> Result<(), WorldErr> {
+        self.lock_region(pos - r as i32, pos + r as i32)?;
+        let changed = &mut self.changed;
+        self.world
+            .set_sphere(pos, r, voxel, decay, mode, |range| changed.push(range))
+    }
+    /// Like `World::fill_sphere`.
+    pub fn fill_sphere(&mut self, pos: IVec3, r: u32, voxel: Voxel) -> Result<(), WorldErr> {
+        self.lock_region(pos - r as i32, pos + r as i32)?;
+        let changed = &mut self.changed;
+        self.world
+            .fill_sphere(pos, r, voxel, |range| changed.push(range))
+    }
+}
+
+/// Batched edits, that change several voxels (possibly across chunks) all at once or not at all.
+impl World {
+    /// Runs `f`, locking every chunk it touches until it's done so other threads locking
+    /// them never see a half finished edit.
+    /// Returns the changed node ranges, to upload all together. If `f` fails, every
+    /// touched chunk is rolled back and nothing needs to be uploaded.
+    pub fn edit(
+        &mut self,
+        owner: &str,
+        f: impl FnOnce(&mut WorldEdit) -> Result<(), WorldErr>,
+    ) -> Result<Vec<Range<NodeAddr>>, WorldErr> {
+        let mut edit = WorldEdit {
+            world: self,
+            owner,
+            locked: vec![],
+            snapshots: vec![],
+            changed: vec![],
+        };
+        let rs = f(&mut edit);
+        let WorldEdit {
+            locked,
+            snapshots,
+            changed,
+            ..
+        } = edit;
+        if rs.is_err() {
+            for snapshot in snapshots {
+                self.restore(snapshot);
+            }
+        }
+        for alloc in locked {
+            self.unlock_chunk(alloc);
+        }
+        rs.map(|_| coalesce_ranges(changed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::NODES_PER_CHUNK;
+    use glam::UVec3;
+
+    #[test]
+    fn edit_across_a_building_chunk_rolls_back() {
+        let mut world = World::new_dims(2 * NODES_PER_CHUNK, UVec3::new(2, 1, 1));
+        let (a, b) = (ivec3(31, 4, 4), ivec3(32, 4, 4));
+        let building = world.chunks[world.chunk_idx(ivec3(1, 0, 0)).unwrap() as usize].alloc;
+
+        // a chunk builder holds its chunk's lock until it's done
+        assert!(world.lock_chunk(building, "chunk builder"));
+        let rs = world.edit("test", |edit| {
+            edit.set_voxel(a, Voxel::STONE)?;
+            edit.set_voxel(b, Voxel::STONE)
+        });
+        assert!(matches!(rs, Err(WorldErr::Locked)));
+        assert_eq!(world.get_voxel(a).unwrap(), Voxel::AIR);
+        world.unlock_chunk(building);
+
+        // once it's done, the same edit goes through, having released every lock
+        let changed = world
+            .edit("test", |edit| {
+                edit.set_line(a, b, Voxel::STONE, PlaceMode::ReplaceAny)
+            })
+            .unwrap();
+        assert!(!changed.is_empty());
+        assert_eq!(world.get_voxel(a).unwrap(), Voxel::STONE);
+        assert_eq!(world.get_voxel(b).unwrap(), Voxel::STONE);
+        assert!(world.lock_chunk(0, "test") && world.lock_chunk(1, "test"));
+    }
+}
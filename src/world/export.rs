@@ -0,0 +1,293 @@
+use super::{data::VOXEL_MATERIALS, vox_to_chunk_pos, Voxel, World, CHUNK_SIZE};
+use glam::{ivec3, IVec3};
+use std::io::Write;
+use std::path::Path;
+
+/// The largest model size a MagicaVoxel `.vox` file can hold along each axis.
+pub const VOX_MAX_SIZE: i32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A MagicaVoxel `.vox` model, with a palette made from the voxel materials.
+    Vox,
+    /// The size of the region as 3 little-endian `u32`s,
+    /// followed by every voxel id as a little-endian `u16`, X first, then Y, then Z.
+    Dense,
+}
+impl ExportFormat {
+    /// Picks the format from a file extension, defaulting to `Dense`.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("vox") => Self::Vox,
+            _ => Self::Dense,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportErr {
+    Io(std::io::Error),
+    /// Part of the region isn't loaded in the world.
+    Oob,
+    /// The region is larger than a `.vox` model can be.
+    TooLarge(IVec3),
+}
+impl From<std::io::Error> for ExportErr {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl std::fmt::Display for ExportErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Oob => f.write_str("region isn't fully loaded"),
+            Self::TooLarge(size) => write!(
+                f,
+                "region {}x{}x{} exceeds the .vox limit of {VOX_MAX_SIZE}",
+                size.x, size.y, size.z
+            ),
+        }
+    }
+}
+
+/// Export regions of the world to other tools.
+impl World {
+    /// Writes the voxels between `min` and `max` (inclusive) to a file at `path`.
+    pub fn export_region(
+        &self,
+        min: IVec3,
+        max: IVec3,
+        path: impl AsRef<Path>,
+        format: ExportFormat,
+    ) -> Result<(), ExportErr> {
+        let (min, max) = (min.min(max), min.max(max));
+        if min.cmplt(self.min()).any() || max.cmpge(self.max()).any() {
+            return Err(ExportErr::Oob);
+        }
+        let bytes = match format {
+            ExportFormat::Vox => self.encode_vox(min, max)?,
+            ExportFormat::Dense => self.encode_dense(min, max)?,
+        };
+        std::fs::File::create(path)?.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Shrinks the region between `min` and `max` (inclusive) to the
+    /// non-air voxels of the chunks it overlaps, or `None` if they're all air.
+    pub fn crop_region(&self, min: IVec3, max: IVec3) -> Option<(IVec3, IVec3)> {
+        let (min, max) = (min.min(max), min.max(max));
+        let mut bounds: Option<(IVec3, IVec3)> = None;
+        for x in vox_to_chunk_pos(min).x..=vox_to_chunk_pos(max).x {
+            for y in vox_to_chunk_pos(min).y..=vox_to_chunk_pos(max).y {
+                for z in vox_to_chunk_pos(min).z..=vox_to_chunk_pos(max).z {
+                    let pos = ivec3(x, y, z);
+                    let Some(idx) = self.chunk_idx(pos) else {
+                        continue;
+                    };
+                    let chunk = &self.chunks[idx as usize];
+                    let Some((c_min, c_max)) = self.chunk_content_bounds(chunk) else {
+                        continue;
+                    };
+                    let origin = pos * CHUNK_SIZE as i32;
+                    let (c_min, c_max) = (origin + c_min.as_ivec3(), origin + c_max.as_ivec3() - 1);
+                    bounds = Some(match bounds {
+                        Some((b_min, b_max)) => (b_min.min(c_min), b_max.max(c_max)),
+                        None => (c_min, c_max),
+                    });
+                }
+            }
+        }
+        let (c_min, c_max) = bounds?;
+        let (min, max) = (min.max(c_min), max.min(c_max));
+        min.cmple(max).all().then_some((min, max))
+    }
+
+    fn region_voxel(&self, pos: IVec3) -> Result<Voxel, ExportErr> {
+        self.get_voxel(pos).map_err(|_| ExportErr::Oob)
+    }
+
+    fn encode_dense(&self, min: IVec3, max: IVec3) -> Result<Vec<u8>, ExportErr> {
+        let size = (max - min + IVec3::ONE).as_uvec3();
+        let mut bytes =
+            Vec::with_capacity(12 + size.x as usize * size.y as usize * size.z as usize * 2);
+        for dim in size.to_array() {
+            bytes.extend_from_slice(&dim.to_le_bytes());
+        }
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let voxel = self.region_voxel(ivec3(x, y, z))?;
+                    bytes.extend_from_slice(&voxel.0.to_le_bytes());
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn encode_vox(&self, min: IVec3, max: IVec3) -> Result<Vec<u8>, ExportErr> {
+        let size = max - min + IVec3::ONE;
+        if size.max_element() > VOX_MAX_SIZE {
+            return Err(ExportErr::TooLarge(size));
+        }
+
+        // .vox models are Z-up, so the world's Y and Z axes are swapped.
+        let mut xyzi = vec![0; 4];
+        let mut count: u32 = 0;
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let voxel = self.region_voxel(ivec3(x, y, z))?;
+                    if voxel == Voxel::AIR {
+                        continue;
+                    }
+                    let local = ivec3(x, y, z) - min;
+                    // palette index 0 is reserved for empty space
+                    let color_idx = voxel.0.clamp(1, 255) as u8;
+                    xyzi.extend_from_slice(&[
+                        local.x as u8,
+                        local.z as u8,
+                        local.y as u8,
+                        color_idx,
+                    ]);
+                    count += 1;
+                }
+            }
+        }
+        xyzi[0..4].copy_from_slice(&count.to_le_bytes());
+
+        let mut size_chunk = Vec::with_capacity(12);
+        for dim in [size.x, size.z, size.y] {
+            size_chunk.extend_from_slice(&(dim as u32).to_le_bytes());
+        }
+
+        // entry `i` of the RGBA chunk is palette index `i + 1`
+        let mut rgba = Vec::with_capacity(256 * 4);
+        for idx in 1..=256 {
+            let color = match VOXEL_MATERIALS.get(idx) {
+                Some(material) => material.color.map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8),
+                None => [0; 3],
+            };
+            rgba.extend_from_slice(&[color[0], color[1], color[2], 255]);
+        }
+
+        let mut children = vec![];
+        write_vox_chunk(&mut children, b"SIZE", &size_chunk, &[]);
+        write_vox_chunk(&mut children, b"XYZI", &xyzi, &[]);
+        write_vox_chunk(&mut children, b"RGBA", &rgba, &[]);
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150u32.to_le_bytes());
+        write_vox_chunk(&mut bytes, b"MAIN", &[], &children);
+        Ok(bytes)
+    }
+}
+
+fn write_vox_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(children.len() as u32).to_le_bytes());
+    out.extend_from_slice(content);
+    out.extend_from_slice(children);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::NODES_PER_CHUNK;
+    use glam::uvec3;
+    use std::collections::HashMap;
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    /// The region size and every voxel in a dense export, indexed like it's written.
+    fn import_dense(bytes: &[u8]) -> (IVec3, Vec<Voxel>) {
+        let size = [0, 4, 8].map(|at| read_u32(bytes, at) as i32);
+        let voxels = (bytes[12..].chunks_exact(2))
+            .map(|id| Voxel(u16::from_le_bytes([id[0], id[1]])))
+            .collect();
+        (IVec3::from(size), voxels)
+    }
+
+    /// The model size and non-empty voxels in a `.vox` export, swapped back to Y-up.
+    fn import_vox(bytes: &[u8]) -> (IVec3, HashMap<IVec3, Voxel>) {
+        assert_eq!(&bytes[0..4], b"VOX ");
+        assert_eq!(&bytes[8..12], b"MAIN");
+        // the children of MAIN, each an id, content size and children size, then the content
+        let (mut at, mut size, mut voxels) = (20, IVec3::ZERO, HashMap::new());
+        while at < bytes.len() {
+            let content = &bytes[at + 12..at + 12 + read_u32(bytes, at + 4) as usize];
+            match &bytes[at..at + 4] {
+                b"SIZE" => size = IVec3::from([0, 8, 4].map(|at| read_u32(content, at) as i32)),
+                b"XYZI" => {
+                    for xyzi in content[4..].chunks_exact(4) {
+                        let pos = ivec3(xyzi[0] as i32, xyzi[2] as i32, xyzi[1] as i32);
+                        voxels.insert(pos, Voxel(xyzi[3] as u16));
+                    }
+                }
+                _ => {}
+            }
+            at += 12 + content.len();
+        }
+        (size, voxels)
+    }
+
+    /// A world with a few random voxels, and the region around them.
+    fn test_region() -> (World, IVec3, IVec3) {
+        let mut rng = fastrand::Rng::with_seed(3);
+        let mut world = World::new_dims(2 * NODES_PER_CHUNK, uvec3(2, 1, 1));
+        for _ in 0..500 {
+            let pos = ivec3(rng.i32(20..50), rng.i32(4..20), rng.i32(10..30));
+            world.set_voxel(pos, Voxel(rng.u16(1..23)), |_| {}).unwrap();
+        }
+        (world, ivec3(18, 2, 8), ivec3(51, 21, 31))
+    }
+
+    fn export(world: &World, min: IVec3, max: IVec3, format: ExportFormat) -> Vec<u8> {
+        let path =
+            std::env::temp_dir().join(format!("vrt-export-{}-{format:?}", std::process::id()));
+        world.export_region(min, max, &path, format).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        _ = std::fs::remove_file(&path);
+        bytes
+    }
+
+    #[test]
+    fn dense_exports_round_trip() {
+        let (world, min, max) = test_region();
+        let (size, voxels) = import_dense(&export(&world, min, max, ExportFormat::Dense));
+        assert_eq!(size, max - min + IVec3::ONE);
+        let mut imported = voxels.into_iter();
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let pos = ivec3(x, y, z);
+                    assert_eq!(
+                        imported.next(),
+                        Some(world.get_voxel(pos).unwrap()),
+                        "at {pos}"
+                    );
+                }
+            }
+        }
+        assert_eq!(imported.next(), None);
+    }
+
+    #[test]
+    fn vox_exports_round_trip() {
+        let (world, min, max) = test_region();
+        let (size, voxels) = import_vox(&export(&world, min, max, ExportFormat::Vox));
+        assert_eq!(size, max - min + IVec3::ONE);
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    let pos = ivec3(x, y, z);
+                    let imported = voxels.get(&(pos - min)).copied().unwrap_or(Voxel::AIR);
+                    assert_eq!(imported, world.get_voxel(pos).unwrap(), "at {pos}");
+                }
+            }
+        }
+    }
+}
@@ -1,14 +1,19 @@
 use super::{
-    noise::NoiseMap, ChunkHeader, FoundNode, Node, NodeAlloc, Voxel, World, WorldErr, CHUNK_DEPTH,
+    noise::{NoiseMap, NoiseMap3D},
+    vox_to_chunk_pos, ChunkHeader, FoundNode, Node, NodeAlloc, Voxel, World, WorldErr, CHUNK_DEPTH,
     CHUNK_SIZE,
 };
 use crate::math::{rand_cardinal_dir, rand_hem_dir};
 use glam::{ivec3, uvec3, vec2, IVec3, Vec3};
-use std::{ops::Range, sync::mpsc::Sender};
+use std::{
+    ops::Range,
+    sync::mpsc::{channel, Sender},
+    time::{Duration, Instant},
+};
 
-fn randf32(range: Range<f32>) -> f32 {
+fn randf32(range: Range<f32>, rng: &mut fastrand::Rng) -> f32 {
     let size = range.end - range.start;
-    fastrand::f32() * size + range.start
+    rng.f32() * size + range.start
 }
 
 struct NoiseMaps {
@@ -20,18 +25,62 @@ struct NoiseMaps {
     temp: NoiseMap,
     moisture: NoiseMap,
     vegetation: NoiseMap,
+    caves: NoiseMap3D,
 }
 impl NoiseMaps {
-    pub fn from_seed(seed: i64) -> Self {
+    pub fn from_seeds(seeds: &GenSeeds) -> Self {
+        let GenSeeds {
+            terrain,
+            biomes,
+            features,
+            caves,
+        } = *seeds;
         Self {
-            height: NoiseMap::new(seed.wrapping_mul(4326742), 0.003, 2.5),
-            freq: NoiseMap::new(seed.wrapping_mul(927144), 0.0001, 7.0),
-            scale: NoiseMap::new(seed.wrapping_mul(43265), 0.003, 40.0),
-            bumps: NoiseMap::new(seed.wrapping_mul(76324), 0.15, 4.0),
-            mountains: NoiseMap::new(seed.wrapping_mul(72316423), 0.001, 40.0),
-            temp: NoiseMap::new(seed.wrapping_mul(83226), 0.0004, 1.0),
-            moisture: NoiseMap::new(seed.wrapping_mul(2345632), 0.0004, 1.0),
-            vegetation: NoiseMap::new(seed.wrapping_mul(53252), 0.001, 1.0),
+            height: NoiseMap::new(terrain.wrapping_mul(4326742), 0.003, 2.5),
+            freq: NoiseMap::new(terrain.wrapping_mul(927144), 0.0001, 7.0),
+            scale: NoiseMap::new(terrain.wrapping_mul(43265), 0.003, 40.0),
+            bumps: NoiseMap::new(terrain.wrapping_mul(76324), 0.15, 4.0),
+            mountains: NoiseMap::new(terrain.wrapping_mul(72316423), 0.001, 40.0),
+            temp: NoiseMap::new(biomes.wrapping_mul(83226), 0.0004, 1.0),
+            moisture: NoiseMap::new(biomes.wrapping_mul(2345632), 0.0004, 1.0),
+            vegetation: NoiseMap::new(features.wrapping_mul(53252), 0.001, 1.0),
+            caves: NoiseMap3D::new(caves.wrapping_mul(61283), DEFAULT_CAVE_FREQ, 1.0),
+        }
+    }
+}
+
+const DEFAULT_CAVE_FREQ: f64 = 0.03;
+/// How far below the surface (in voxels) caves fade in, so they rarely break through it.
+const CAVE_SURFACE_FADE: i32 = 8;
+
+/// Independent seeds for each part of generation, so changing one
+/// (like which features spawn) doesn't disturb the others (like the terrain shape).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GenSeeds {
+    /// The shape of the terrain.
+    pub terrain: i64,
+    /// Temperature and moisture, which pick the surface voxels.
+    pub biomes: i64,
+    /// Where trees and cacti are placed, and how they grow.
+    pub features: i64,
+    /// Where caves are carved out of the terrain.
+    pub caves: i64,
+}
+impl GenSeeds {
+    /// Derives every seed from a single master seed.
+    pub fn from_master(seed: i64) -> Self {
+        // a splitmix64 step per category, so the derived seeds are unrelated
+        let derive = |category: u64| {
+            let mut z = (seed as u64).wrapping_add(category.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            (z ^ (z >> 31)) as i64
+        };
+        Self {
+            terrain: derive(1),
+            biomes: derive(2),
+            features: derive(3),
+            caves: derive(4),
         }
     }
 }
@@ -109,9 +158,81 @@ fn set_voxel_in_region(
     Ok(())
 }
 
+/// How long each phase of `WorldGen::populate_timed` took, and how much it generated.
+#[derive(Clone, Debug, Default)]
+pub struct GenStats {
+    /// Sampling the noise for the terrain height of each column.
+    pub noise: Duration,
+    /// Filling chunks with stone, dirt and surface voxels (including biome noise).
+    pub fill: Duration,
+    /// Placing trees, cacti and other features.
+    pub place_features: Duration,
+    pub chunks: u32,
+    pub features: u32,
+    pub voxels_set: u64,
+}
+impl GenStats {
+    pub fn total(&self) -> Duration {
+        self.noise + self.fill + self.place_features
+    }
+}
+
+/// What kind of terrain a `WorldGen` builds.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub enum WorldPreset {
+    /// Noise based terrain, with biomes and features.
+    #[default]
+    Normal,
+    /// Flat layers of voxels, listed from the bottom up as `(voxel, thickness)`,
+    /// starting at y=0. Everything below is stone, everything above is air.
+    Flat(Vec<(Voxel, u32)>),
+    /// Nothing but air.
+    Void,
+}
+impl WorldPreset {
+    /// A few layers of stone, topped with dirt and grass.
+    pub fn superflat() -> Self {
+        Self::Flat(vec![
+            (Voxel::STONE, 12),
+            (Voxel::DIRT, 3),
+            (Voxel::GRASS, 1),
+        ])
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "normal" => Some(Self::Normal),
+            "flat" => Some(Self::superflat()),
+            "void" => Some(Self::Void),
+            _ => None,
+        }
+    }
+}
+
+/// The voxel at height `y` in a flat world made of `layers`.
+fn flat_voxel(layers: &[(Voxel, u32)], y: i32) -> Voxel {
+    if y < 0 {
+        return Voxel::STONE;
+    }
+    let mut top = 0;
+    for (voxel, thickness) in layers {
+        top += *thickness as i32;
+        if y < top {
+            return *voxel;
+        }
+    }
+    Voxel::AIR
+}
+
 pub struct WorldGen {
     maps: NoiseMaps,
     seed: i64,
+    seeds: GenSeeds,
+    sea_level: i32,
+    cave_freq: f64,
+    cave_threshold: f32,
+    cave_floor_y: i32,
+    preset: WorldPreset,
     oak_tree_gen: TreeGen,
     birch_tree_gen: TreeGen,
     spruce_tree_gen: SpruceTreeGen,
@@ -142,10 +263,17 @@ impl WorldGen {
             bottom_branch: 4..8,
         };
         let cactus_gen = CactusGen { height: 2..7 };
-        let maps = NoiseMaps::from_seed(seed);
+        let seeds = GenSeeds::from_master(seed);
+        let maps = NoiseMaps::from_seeds(&seeds);
         Self {
             maps,
             seed,
+            seeds,
+            sea_level: 26,
+            cave_freq: DEFAULT_CAVE_FREQ,
+            cave_threshold: 0.72,
+            cave_floor_y: -64,
+            preset: WorldPreset::Normal,
             birch_tree_gen,
             oak_tree_gen,
             spruce_tree_gen,
@@ -157,31 +285,155 @@ impl WorldGen {
         self.seed
     }
 
+    /// A random number generator for placing features in the chunk at `chunk_min`,
+    /// so the same chunk always generates the same features for a given seed.
+    pub fn chunk_rng(&self, chunk_min: IVec3) -> fastrand::Rng {
+        let pos = chunk_min.as_i64vec3();
+        let hash = (self.seeds.features as u64)
+            ^ (pos.x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+            ^ (pos.y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+            ^ (pos.z as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+        fastrand::Rng::with_seed(hash)
+    }
+
+    pub fn seeds(&self) -> GenSeeds {
+        self.seeds
+    }
+
+    /// Replaces the seeds derived from the master seed, to tune one part of generation.
+    pub fn with_seeds(mut self, seeds: GenSeeds) -> Self {
+        self.seeds = seeds;
+        self.maps = NoiseMaps::from_seeds(&seeds);
+        self.maps.caves.freq = self.cave_freq;
+        self
+    }
+
+    /// Terrain below this height is flooded with water.
+    pub fn sea_level(&self) -> i32 {
+        self.sea_level
+    }
+
+    pub fn with_sea_level(mut self, sea_level: i32) -> Self {
+        self.sea_level = sea_level;
+        self
+    }
+
+    /// How often caves occur, as the frequency of the 3D noise that carves them.
+    pub fn with_cave_freq(mut self, freq: f64) -> Self {
+        self.cave_freq = freq;
+        self.maps.caves.freq = freq;
+        self
+    }
+
+    /// Solid voxels where the cave noise (from 0 to 1) is above `threshold` are carved out.
+    /// Lower thresholds make bigger caves, and 1 disables them.
+    pub fn with_cave_threshold(mut self, threshold: f32) -> Self {
+        self.cave_threshold = threshold;
+        self
+    }
+
+    /// Caves aren't carved below this height.
+    pub fn with_cave_floor_y(mut self, y: i32) -> Self {
+        self.cave_floor_y = y;
+        self
+    }
+
+    /// The lowest height the cave in the column at `x`, `z` (with its surface at `h`)
+    /// runs up from, unbroken, through the sea floor, stopping at `min_y`.
+    /// Only caves from there up are flooded, so caverns sealed off from the sea stay dry.
+    fn flooded_cave_bottom(&self, x: i32, z: i32, h: i32, min_y: i32) -> i32 {
+        if h >= self.sea_level {
+            // there's no sea above this column
+            return i32::MAX;
+        }
+        let mut y = h + 1;
+        while y > min_y && self.is_cave(ivec3(x, y - 1, z), h - (y - 1)) {
+            y -= 1;
+        }
+        y
+    }
+
+    /// Whether caves could be carved anywhere below `max_y`.
+    fn caves_below(&self, max_y: i32) -> bool {
+        self.cave_threshold < 1.0 && max_y > self.cave_floor_y
+    }
+
+    /// Whether a cave hollows out the solid voxel at `pos`, `depth` voxels below the surface.
+    fn is_cave(&self, pos: IVec3, depth: i32) -> bool {
+        if self.cave_threshold >= 1.0 || pos.y < self.cave_floor_y {
+            return false;
+        }
+        // the threshold rises towards the surface, so only the biggest caves break through it
+        let fade = (CAVE_SURFACE_FADE - depth).max(0) as f32 / CAVE_SURFACE_FADE as f32;
+        let threshold = self.cave_threshold + (1.0 - self.cave_threshold) * fade.min(1.0) * 0.75;
+        self.maps.caves.get(pos.as_vec3()) > threshold
+    }
+
+    pub fn preset(&self) -> &WorldPreset {
+        &self.preset
+    }
+
+    pub fn with_preset(mut self, preset: WorldPreset) -> Self {
+        self.preset = preset;
+        self
+    }
+
     pub fn chunk_voxel(&self, min: IVec3, max: IVec3) -> Option<Voxel> {
-        if max.y < 0 {
+        match &self.preset {
+            WorldPreset::Normal => {}
+            WorldPreset::Void => return Some(Voxel::AIR),
+            WorldPreset::Flat(layers) => {
+                // the chunk is uniform if every height in it falls in the same layer
+                let voxel = flat_voxel(layers, min.y);
+                let uniform = (min.y..max.y).all(|y| flat_voxel(layers, y) == voxel);
+                return uniform.then_some(voxel);
+            }
+        }
+        if max.y < 0 && !self.caves_below(max.y) {
             return Some(Voxel::STONE);
         }
-        if min.y > 128 {
+        if min.y > 128.max(self.sea_level) {
             return Some(Voxel::AIR);
         }
         None
     }
 
-    pub fn sample_terrain(&self, pos: IVec3) -> (Voxel, bool) {
-        let pos2 = vec2(pos.x as f32, pos.z as f32);
+    /// The height of the terrain surface in the column at `x`, `z`.
+    pub fn terrain_height(&self, x: i32, z: i32) -> i32 {
+        let pos2 = vec2(x as f32, z as f32);
         let h_freq = self.maps.freq.get(pos2);
         let h_scale = self.maps.scale.get(pos2);
-        let h = (self.maps.height.get(pos2 * h_freq) * h_scale
+        (self.maps.height.get(pos2 * h_freq) * h_scale
             + self.maps.bumps.get(pos2)
-            + self.maps.mountains.get(pos2)) as i32;
+            + self.maps.mountains.get(pos2)) as i32
+    }
+
+    /// The surface heights of every column in the chunk at `min`, indexed by `x + z * CHUNK_SIZE`.
+    fn column_heights(&self, min: IVec3) -> Vec<i32> {
+        let mut heights = Vec::with_capacity((CHUNK_SIZE * CHUNK_SIZE) as usize);
+        for z in 0i32..CHUNK_SIZE as i32 {
+            for x in 0i32..CHUNK_SIZE as i32 {
+                heights.push(self.terrain_height(x + min.x, z + min.z));
+            }
+        }
+        heights
+    }
+
+    pub fn sample_terrain(&self, pos: IVec3) -> (Voxel, bool) {
+        self.sample_column(pos, self.terrain_height(pos.x, pos.z))
+    }
+
+    /// Samples the terrain at `pos`, in a column with surface height `h`.
+    fn sample_column(&self, pos: IVec3, h: i32) -> (Voxel, bool) {
+        let pos2 = vec2(pos.x as f32, pos.z as f32);
 
-        if h < 26 {
+        if h < self.sea_level {
             return (
                 match pos.y {
                     v if v < h - 4 => Voxel::STONE,
                     v if v < h => Voxel::DIRT,
                     v if v == h => Voxel::SAND,
-                    v if v < 26 => Voxel::WATER,
+                    v if v < self.sea_level => Voxel::WATER,
                     _ => Voxel::AIR,
                 },
                 false,
@@ -220,6 +472,9 @@ impl WorldGen {
         nodes: &mut [Node],
         features: Sender<Feature>,
     ) -> Result<(), WorldErr> {
+        let heights = self.column_heights(origin);
+        let mut rng = self.chunk_rng(origin);
+
         for x in 0i32..CHUNK_SIZE as i32 {
             for z in 0i32..CHUNK_SIZE as i32 {
                 let world_xz = glam::ivec2(x + origin.x, z + origin.z);
@@ -228,7 +483,8 @@ impl WorldGen {
                 for y in 0i32..CHUNK_SIZE as i32 {
                     let (world_pos, local_pos) = (ivec3(x, y, z) + origin, ivec3(x, y, z));
 
-                    let (voxel, is_surface) = self.sample_terrain(world_pos);
+                    let h = heights[(x + z * CHUNK_SIZE as i32) as usize];
+                    let (voxel, is_surface) = self.sample_column(world_pos, h);
                     if voxel == Voxel::AIR {
                         // if the sampler returned air,
                         // then there arn't going to be any more solid blocks
@@ -240,18 +496,20 @@ impl WorldGen {
                         continue;
                     }
 
-                    if voxel == Voxel::GRASS && fastrand::f32() < 0.005 * vegetation {
-                        match fastrand::u8(0..2) {
-                            0 => _ = features.send(self.oak_tree_gen.generate(world_pos)),
-                            1 => _ = features.send(self.birch_tree_gen.generate(world_pos)),
+                    if voxel == Voxel::GRASS && rng.f32() < 0.005 * vegetation {
+                        match rng.u8(0..2) {
+                            0 => _ = features.send(self.oak_tree_gen.generate(world_pos, &mut rng)),
+                            1 => {
+                                _ = features.send(self.birch_tree_gen.generate(world_pos, &mut rng))
+                            }
                             _ => unreachable!(),
                         }
                     }
-                    if voxel == Voxel::SAND && fastrand::f32() < 0.01 * vegetation {
-                        _ = features.send(self.cactus_gen.generate(world_pos));
+                    if voxel == Voxel::SAND && rng.f32() < 0.01 * vegetation {
+                        _ = features.send(self.cactus_gen.generate(world_pos, &mut rng));
                     }
-                    if voxel == Voxel::SNOW && fastrand::f32() < 0.003 * vegetation {
-                        _ = features.send(self.spruce_tree_gen.generate(world_pos));
+                    if voxel == Voxel::SNOW && rng.f32() < 0.003 * vegetation {
+                        _ = features.send(self.spruce_tree_gen.generate(world_pos, &mut rng));
                     }
                 }
             }
@@ -266,43 +524,197 @@ impl WorldGen {
         world: &mut World,
         features: Sender<Feature>,
     ) -> Result<(), WorldErr> {
+        let heights = match &self.preset {
+            WorldPreset::Normal => self.column_heights(min),
+            _ => vec![],
+        };
+        self.fill_chunk(chunk, min, &heights, world, features)
+            .map(|_| ())
+    }
+
+    /// Fills the chunk at `min`, given the surface `heights` from `column_heights`.
+    /// Returns how many voxels were set.
+    fn fill_chunk(
+        &self,
+        chunk: ChunkHeader,
+        min: IVec3,
+        heights: &[i32],
+        world: &mut World,
+        features: Sender<Feature>,
+    ) -> Result<u64, WorldErr> {
+        match &self.preset {
+            WorldPreset::Normal => {}
+            WorldPreset::Void => return Ok(0),
+            WorldPreset::Flat(layers) => return self.build_flat_chunk(chunk, min, world, layers),
+        }
+        let top = heights.iter().copied().max().unwrap_or(i32::MIN);
+        if top < min.y && self.sea_level <= min.y {
+            // the whole chunk is above the terrain and the sea, so it stays air
+            return Ok(0);
+        }
+        let bottom = heights.iter().copied().min().unwrap_or(i32::MAX);
+        let mut rng = self.chunk_rng(min);
+        let max_y = min.y + CHUNK_SIZE as i32;
+        if bottom - 4 >= max_y && !self.caves_below(max_y) {
+            // the whole chunk is deep underground, so a single stone node represents it
+            *world.mut_node(chunk.root) = Node::new(Voxel::STONE);
+            return Ok((CHUNK_SIZE as u64).pow(3));
+        }
+        let mut voxels = vec![Voxel::AIR; CHUNK_SIZE.pow(3) as usize];
+        let mut voxels_set = 0;
+
         for x in 0i32..CHUNK_SIZE as i32 {
             for z in 0i32..CHUNK_SIZE as i32 {
                 let world_xz = glam::ivec2(x + min.x, z + min.z);
                 let vegetation = self.maps.vegetation.get(world_xz.as_vec2());
+                let h = heights[(x + z * CHUNK_SIZE as i32) as usize];
+                let flooded_from = self.flooded_cave_bottom(world_xz.x, world_xz.y, h, min.y);
 
                 for y in 0i32..CHUNK_SIZE as i32 {
                     let (world_pos, local_pos) = (ivec3(x, y, z) + min, ivec3(x, y, z));
 
-                    let (voxel, is_surface) = self.sample_terrain(world_pos);
+                    let (mut voxel, is_surface) = self.sample_column(world_pos, h);
                     if voxel == Voxel::AIR {
                         // if the sampler returned air,
                         // then there arn't going to be any more solid blocks
                         break;
                     }
-                    world.set_voxel_in_chunk(chunk.clone(), local_pos, voxel, |_| {})?;
+                    if voxel != Voxel::WATER && self.is_cave(world_pos, h - world_pos.y) {
+                        if world_pos.y < flooded_from {
+                            // chunks start out as air, so there's nothing to carve
+                            continue;
+                        }
+                        // the cave opens up into the sea above it
+                        voxel = Voxel::WATER;
+                    }
+                    let idx = local_pos.x
+                        + (local_pos.y + local_pos.z * CHUNK_SIZE as i32) * CHUNK_SIZE as i32;
+                    voxels[idx as usize] = voxel;
+                    voxels_set += 1;
 
                     if !is_surface {
                         continue;
                     }
 
-                    if voxel == Voxel::GRASS && fastrand::f32() < 0.005 * vegetation {
-                        match fastrand::u8(0..2) {
-                            0 => _ = features.send(self.oak_tree_gen.generate(world_pos)),
-                            1 => _ = features.send(self.birch_tree_gen.generate(world_pos)),
+                    if voxel == Voxel::GRASS && rng.f32() < 0.005 * vegetation {
+                        match rng.u8(0..2) {
+                            0 => _ = features.send(self.oak_tree_gen.generate(world_pos, &mut rng)),
+                            1 => {
+                                _ = features.send(self.birch_tree_gen.generate(world_pos, &mut rng))
+                            }
                             _ => unreachable!(),
                         }
                     }
-                    if voxel == Voxel::SAND && fastrand::f32() < 0.01 * vegetation {
-                        _ = features.send(self.cactus_gen.generate(world_pos));
+                    if voxel == Voxel::SAND && rng.f32() < 0.01 * vegetation {
+                        _ = features.send(self.cactus_gen.generate(world_pos, &mut rng));
                     }
-                    if voxel == Voxel::SNOW && fastrand::f32() < 0.003 * vegetation {
-                        _ = features.send(self.spruce_tree_gen.generate(world_pos));
+                    if voxel == Voxel::SNOW && rng.f32() < 0.003 * vegetation {
+                        _ = features.send(self.spruce_tree_gen.generate(world_pos, &mut rng));
                     }
                 }
             }
         }
-        Ok(())
+        // written all at once instead of voxel by voxel, so the air above the surface
+        // and the stone below it stay as few large nodes as possible
+        world.set_chunk_voxels(&chunk, &voxels)?;
+        Ok(voxels_set)
+    }
+
+    /// Generates every chunk of `world` between `min` and `max` (exclusive) on this thread,
+    /// then places their features, timing each phase. Used to profile generation
+    /// without a window or GPU.
+    pub fn populate_timed(&self, min: IVec3, max: IVec3, world: &mut World) -> GenStats {
+        let mut stats = GenStats::default();
+        let (feature_sender, feature_receiver) = channel();
+        let chunk_size = IVec3::splat(CHUNK_SIZE as i32);
+        let (min_chunk, max_chunk) = (vox_to_chunk_pos(min), vox_to_chunk_pos(max - 1));
+
+        for z in min_chunk.z..=max_chunk.z {
+            for y in min_chunk.y..=max_chunk.y {
+                for x in min_chunk.x..=max_chunk.x {
+                    let Some(idx) = world.chunk_idx(ivec3(x, y, z)) else {
+                        continue;
+                    };
+                    let chunk = world.chunks[idx as usize].clone();
+                    let chunk_min = ivec3(x, y, z) * chunk_size;
+                    world.reset_alloc(chunk.alloc);
+                    *world.mut_node(chunk.root) = Node::new(Voxel::AIR);
+                    stats.chunks += 1;
+
+                    if let Some(voxel) = self.chunk_voxel(chunk_min, chunk_min + chunk_size) {
+                        *world.mut_node(chunk.root) = Node::new(voxel);
+                        if !voxel.is_empty() {
+                            stats.voxels_set += (CHUNK_SIZE as u64).pow(3);
+                        }
+                        world.set_chunk_loaded(&chunk, true);
+                        continue;
+                    }
+
+                    let start = Instant::now();
+                    let heights = match &self.preset {
+                        WorldPreset::Normal => self.column_heights(chunk_min),
+                        _ => vec![],
+                    };
+                    stats.noise += start.elapsed();
+
+                    let start = Instant::now();
+                    let filled = self.fill_chunk(
+                        chunk.clone(),
+                        chunk_min,
+                        &heights,
+                        world,
+                        feature_sender.clone(),
+                    );
+                    stats.fill += start.elapsed();
+                    match filled {
+                        Ok(voxels_set) => {
+                            stats.voxels_set += voxels_set;
+                            world.set_chunk_loaded(&chunk, true);
+                        }
+                        Err(err) => log::warn!("failed to generate chunk {x} {y} {z}: {err:?}"),
+                    }
+                }
+            }
+        }
+        std::mem::drop(feature_sender);
+
+        let start = Instant::now();
+        for feature in feature_receiver.try_iter() {
+            if feature.is_obstructed(world) {
+                continue;
+            }
+            stats.features += 1;
+            feature.place(|pos, voxel| {
+                if world.set_voxel(pos, voxel, |_| {}).is_ok() {
+                    stats.voxels_set += 1;
+                }
+            });
+        }
+        stats.place_features += start.elapsed();
+        stats
+    }
+
+    fn build_flat_chunk(
+        &self,
+        chunk: ChunkHeader,
+        min: IVec3,
+        world: &mut World,
+        layers: &[(Voxel, u32)],
+    ) -> Result<u64, WorldErr> {
+        let mut voxels_set = 0;
+        for y in 0i32..CHUNK_SIZE as i32 {
+            let voxel = flat_voxel(layers, min.y + y);
+            if voxel == Voxel::AIR {
+                continue;
+            }
+            for x in 0i32..CHUNK_SIZE as i32 {
+                for z in 0i32..CHUNK_SIZE as i32 {
+                    world.set_voxel_in_chunk(chunk.clone(), ivec3(x, y, z), voxel, |_| {})?;
+                    voxels_set += 1;
+                }
+            }
+        }
+        Ok(voxels_set)
     }
 }
 
@@ -428,12 +840,18 @@ impl Shape {
 pub struct Feature {
     bounds: [IVec3; 2],
     shapes: Vec<Shape>,
+    created: Instant,
+    /// The surface voxel the feature grows from. Voxels at or below it
+    /// may overlap the terrain.
+    anchor: Option<IVec3>,
 }
 impl Default for Feature {
     fn default() -> Self {
         Self {
             bounds: [IVec3::MAX, IVec3::MIN],
             shapes: vec![],
+            created: Instant::now(),
+            anchor: None,
         }
     }
 }
@@ -454,6 +872,30 @@ impl Feature {
         self.bounds[1]
     }
 
+    fn anchored_at(mut self, surface: IVec3) -> Self {
+        self.anchor = Some(surface);
+        self
+    }
+
+    /// Whether placing this feature would overwrite anything already in the world
+    /// above its anchor, like terrain or another feature.
+    pub fn is_obstructed(&self, world: &World) -> bool {
+        let ground = self.anchor.map_or(i32::MIN, |anchor| anchor.y);
+        let mut obstructed = false;
+        self.place(|pos, _| {
+            if obstructed || pos.y <= ground {
+                return;
+            }
+            obstructed = !world.get_voxel(pos).map_or(true, Voxel::is_empty);
+        });
+        obstructed
+    }
+
+    /// How long ago this feature was generated.
+    pub fn age(&self) -> Duration {
+        self.created.elapsed()
+    }
+
     pub fn sphere(&mut self, center: IVec3, r: u32, voxel: Voxel, _decay: f32) {
         self.push_shape(Shape::Sphere { center, r, voxel })
     }
@@ -478,6 +920,23 @@ impl Feature {
     }
 }
 
+/// The most features that can wait to be placed at once.
+pub const MAX_QUEUED_FEATURES: usize = 4096;
+/// Features that still can't be placed after this long are dropped.
+pub const MAX_FEATURE_AGE: Duration = Duration::from_secs(30);
+
+/// Drops the features in `queue` older than `MAX_FEATURE_AGE`, then the oldest
+/// ones past `MAX_QUEUED_FEATURES`, so the queue can't grow without bound.
+/// Returns how many were dropped.
+pub fn evict_features(queue: &mut Vec<Feature>) -> usize {
+    let len = queue.len();
+    queue.retain(|feature| feature.age() < MAX_FEATURE_AGE);
+    // features are queued in the order they're received, so the oldest are first
+    let overflow = queue.len().saturating_sub(MAX_QUEUED_FEATURES);
+    queue.drain(..overflow);
+    len - queue.len()
+}
+
 #[derive(Clone)]
 pub struct TreeGen {
     pub height: Range<u32>,
@@ -489,22 +948,22 @@ pub struct TreeGen {
     pub branch_len: Range<f32>,
 }
 impl TreeGen {
-    fn generate(&self, surface: IVec3) -> Feature {
+    fn generate(&self, surface: IVec3, rng: &mut fastrand::Rng) -> Feature {
         let mut rs = Feature::default();
-        let height = fastrand::u32(self.height.clone());
+        let height = rng.u32(self.height.clone());
         let top = surface + ivec3(0, height as i32, 0);
 
         let branch_count = match height {
             ..=8 => 0,
-            _ => fastrand::u32(self.branch_count.clone()),
+            _ => rng.u32(self.branch_count.clone()),
         };
         rs.sphere(top, 5, self.leaves_voxel, self.leaves_decay);
 
         for _ in 0..branch_count {
-            let branch_h = (randf32(self.branch_height.clone()) * height as f32) as u32;
-            let branch_len = randf32(self.branch_len.clone());
+            let branch_h = (randf32(self.branch_height.clone(), rng) * height as f32) as u32;
+            let branch_len = randf32(self.branch_len.clone(), rng);
 
-            let branch_dir = rand_hem_dir(Vec3::Y);
+            let branch_dir = rand_hem_dir(Vec3::Y, rng);
             let start = ivec3(surface.x, surface.y + branch_h as i32, surface.z);
             let end = (start.as_vec3() + branch_dir * branch_len).as_ivec3();
 
@@ -512,7 +971,7 @@ impl TreeGen {
             rs.line([start, end], self.wood_voxel);
         }
         rs.line([surface, top], self.wood_voxel);
-        rs
+        rs.anchored_at(surface)
     }
 }
 
@@ -522,10 +981,10 @@ struct SpruceTreeGen {
     bottom_branch: Range<u32>,
 }
 impl SpruceTreeGen {
-    fn generate(&self, pos: IVec3) -> Feature {
+    fn generate(&self, pos: IVec3, rng: &mut fastrand::Rng) -> Feature {
         let mut rs = Feature::default();
-        let offset = fastrand::u32(self.bottom_branch.clone()) as i32;
-        let height = offset + fastrand::u32(self.height.clone()) as i32;
+        let offset = rng.u32(self.bottom_branch.clone()) as i32;
+        let height = offset + rng.u32(self.height.clone()) as i32;
 
         let mut y = height;
         let mut r: i32 = 1;
@@ -537,7 +996,7 @@ impl SpruceTreeGen {
         }
         let top = pos + ivec3(0, height - 1, 0);
         rs.line([pos, top], Voxel::SPRUCE_WOOD);
-        rs
+        rs.anchored_at(pos)
     }
 }
 
@@ -546,17 +1005,17 @@ struct CactusGen {
     height: Range<u32>,
 }
 impl CactusGen {
-    fn generate(&self, pos: IVec3) -> Feature {
-        let mut rs = Feature::default();
-        let pos = pos + IVec3::Y;
-        let height = fastrand::u32(self.height.clone()) as i32;
-        let splits = if height > 3 { fastrand::u32(0..4) } else { 0 };
+    fn generate(&self, surface: IVec3, rng: &mut fastrand::Rng) -> Feature {
+        let mut rs = Feature::default().anchored_at(surface);
+        let pos = surface + IVec3::Y;
+        let height = rng.u32(self.height.clone()) as i32;
+        let splits = if height > 3 { rng.u32(0..4) } else { 0 };
 
         rs.line([pos, pos + IVec3::Y * height], Voxel::CACTUS);
         for _ in 0..splits {
-            let split_h = fastrand::i32(1..height);
-            let split_len = fastrand::i32(1..4);
-            let dir = rand_cardinal_dir();
+            let split_h = rng.i32(1..height);
+            let split_len = rng.i32(1..4);
+            let dir = rand_cardinal_dir(rng);
 
             rs.voxel(pos + IVec3::Y * split_h + dir, Voxel::CACTUS);
             let branch_min = pos + IVec3::Y * split_h + dir * 2;
@@ -566,3 +1025,345 @@ impl CactusGen {
         rs
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::NODES_PER_CHUNK;
+
+    #[test]
+    fn cave_seed_leaves_surface_unchanged() {
+        crate::world::noise::init_gradients();
+        let gen = WorldGen::new(7);
+        let mut seeds = gen.seeds();
+        seeds.caves = seeds.caves.wrapping_add(1);
+        let other = WorldGen::new(7).with_seeds(seeds);
+
+        let mut caves_moved = false;
+        for x in (-200..200).step_by(7) {
+            for z in (-200..200).step_by(11) {
+                let h = gen.terrain_height(x, z);
+                assert_eq!(h, other.terrain_height(x, z));
+                let surface = ivec3(x, h, z);
+                assert_eq!(gen.sample_terrain(surface), other.sample_terrain(surface));
+
+                let deep = ivec3(x, h - 20, z);
+                caves_moved |= gen.is_cave(deep, 20) != other.is_cave(deep, 20);
+            }
+        }
+        assert!(caves_moved, "the cave seed should move the caves");
+    }
+
+    #[test]
+    fn feature_seed_leaves_terrain_unchanged() {
+        let seeds = GenSeeds::from_master(7);
+        assert_eq!(seeds, GenSeeds::from_master(7));
+        assert_ne!(seeds, GenSeeds::from_master(8));
+        let all = [seeds.terrain, seeds.biomes, seeds.features, seeds.caves];
+        assert!((1..4).all(|i| !all[..i].contains(&all[i])));
+
+        crate::world::noise::init_gradients();
+        let gen = WorldGen::new(7);
+        let other = WorldGen::new(7).with_seeds(GenSeeds {
+            features: seeds.features.wrapping_add(1),
+            ..seeds
+        });
+        let mut features_moved = false;
+        for i in 0..10 {
+            let (x, z) = (i * 97, i * -61);
+            let min = ivec3(x, gen.terrain_height(x + 16, z + 16) - 16, z);
+            let (world, other_world) = (build_one(&gen, min), build_one(&other, min));
+            for idx in 0..CHUNK_SIZE.pow(3) as i32 {
+                let pos = ivec3(idx % 32, (idx / 32) % 32, idx / 1024);
+                assert_eq!(
+                    world.get_voxel(pos).unwrap(),
+                    other_world.get_voxel(pos).unwrap(),
+                    "at {}",
+                    min + pos
+                );
+            }
+            features_moved |= chunk_features(&gen, min) != chunk_features(&other, min);
+        }
+        assert!(features_moved, "the feature seed should move the features");
+    }
+
+    #[test]
+    fn minimal_build_matches_voxel_writes() {
+        crate::world::noise::init_gradients();
+        let gen = WorldGen::new(7);
+        let mut built = World::new(NODES_PER_CHUNK, 1);
+        let mut reference = World::new(NODES_PER_CHUNK, 1);
+        let chunk = built.chunks[0].clone();
+        let size = CHUNK_SIZE as i32;
+        let chunk_positions =
+            || (0..size.pow(3)).map(move |i| ivec3(i % size, (i / size) % size, i / (size * size)));
+
+        // chunks holding the surface, from a sliver at the bottom to mostly ground
+        for (x, z) in [(0, 0), (100, -40), (-300, 250)] {
+            for offset in [-4, -16, -28] {
+                let min = ivec3(x, gen.terrain_height(x, z) + offset, z);
+                built.reset_alloc(chunk.alloc);
+                let (sender, _receiver) = channel();
+                gen.build_chunk(chunk.clone(), min, &mut built, sender)
+                    .unwrap();
+
+                // the same voxels, written one at a time
+                reference.reset_alloc(chunk.alloc);
+                *reference.mut_node(chunk.root) = Node::new(Voxel::AIR);
+                for pos in chunk_positions() {
+                    let world_pos = min + pos;
+                    let h = gen.terrain_height(world_pos.x, world_pos.z);
+                    let (mut voxel, _) = gen.sample_column(world_pos, h);
+                    if voxel != Voxel::WATER && gen.is_cave(world_pos, h - world_pos.y) {
+                        let flooded_from =
+                            gen.flooded_cave_bottom(world_pos.x, world_pos.z, h, min.y);
+                        voxel = match world_pos.y < flooded_from {
+                            true => Voxel::AIR,
+                            false => Voxel::WATER,
+                        };
+                    }
+                    if voxel == Voxel::AIR {
+                        continue;
+                    }
+                    reference
+                        .set_voxel_in_chunk(chunk.clone(), pos, voxel, |_| {})
+                        .unwrap();
+                }
+
+                for pos in chunk_positions() {
+                    assert_eq!(
+                        built.get_voxel(pos).unwrap(),
+                        reference.get_voxel(pos).unwrap(),
+                        "at {}",
+                        min + pos
+                    );
+                }
+                let used = |world: &World| world.allocs[0].next - world.allocs[0].range.start;
+                assert!(used(&built) <= used(&reference));
+            }
+        }
+    }
+
+    /// Builds the chunk at `min` into a one chunk world.
+    fn build_one(gen: &WorldGen, min: IVec3) -> World {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        let (sender, _receiver) = channel();
+        let chunk = world.chunks[0].clone();
+        gen.build_chunk(chunk.clone(), min, &mut world, sender)
+            .unwrap();
+        world.set_chunk_loaded(&chunk, true);
+        world
+    }
+
+    #[test]
+    fn column_top_matches_the_surface() {
+        crate::world::noise::init_gradients();
+        let gen = WorldGen::new(7).with_cave_threshold(1.0);
+        let min = ivec3(0, gen.terrain_height(16, 16) - 16, 0);
+        let gen = gen.with_sea_level(min.y - 100);
+        let world = build_one(&gen, min);
+
+        let mut surfaces = 0;
+        for x in 0..CHUNK_SIZE as i32 {
+            for z in 0..CHUNK_SIZE as i32 {
+                let top = world.column_top_solid(x, z);
+                let h = gen.terrain_height(min.x + x, min.z + z) - min.y;
+                let expected = match h {
+                    ..=-1 => None,
+                    0..=31 => Some(h),
+                    _ => Some(31),
+                };
+                assert_eq!(top, expected, "column {x}, {z}");
+                surfaces += (0..32).contains(&h) as u32;
+            }
+        }
+        assert!(surfaces > 0);
+    }
+
+    #[test]
+    fn flat_and_void_presets_build_their_layers() {
+        crate::world::noise::init_gradients();
+        let size = CHUNK_SIZE as i32;
+        let chunk_positions =
+            || (0..size.pow(3)).map(move |i| ivec3(i % size, (i / size) % size, i / (size * size)));
+        let flat = WorldGen::new(7).with_preset(WorldPreset::superflat());
+        for min in [ivec3(0, 0, 0), ivec3(64, -32, -96)] {
+            let world = build_one(&flat, min);
+            for pos in chunk_positions() {
+                let expected = match min.y + pos.y {
+                    ..=11 => Voxel::STONE,
+                    12..=14 => Voxel::DIRT,
+                    15 => Voxel::GRASS,
+                    _ => Voxel::AIR,
+                };
+                assert_eq!(world.get_voxel(pos).unwrap(), expected, "at {}", min + pos);
+            }
+        }
+
+        let void = WorldGen::new(7).with_preset(WorldPreset::Void);
+        for min in [ivec3(0, 0, 0), ivec3(64, -32, -96)] {
+            let world = build_one(&void, min);
+            assert!(chunk_positions().all(|pos| world.get_voxel(pos).unwrap() == Voxel::AIR));
+            assert!(chunk_features(&void, min).is_empty());
+        }
+    }
+
+    /// The voxels of every feature building the chunk at `min` generates, in order.
+    fn chunk_features(gen: &WorldGen, min: IVec3) -> Vec<Vec<(IVec3, Voxel)>> {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        let (sender, receiver) = channel();
+        gen.build_chunk(world.chunks[0].clone(), min, &mut world, sender)
+            .unwrap();
+        (receiver.try_iter())
+            .map(|feature| {
+                let mut voxels = vec![];
+                feature.place(|pos, voxel| voxels.push((pos, voxel)));
+                voxels
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rebuilding_a_chunk_places_the_same_features() {
+        crate::world::noise::init_gradients();
+        let gen = WorldGen::new(7);
+        let mut with_features = 0;
+        for i in 0..20 {
+            let (x, z) = (i * 97, i * -61);
+            let min = ivec3(x, gen.terrain_height(x + 16, z + 16) - 16, z);
+            let features = chunk_features(&gen, min);
+            assert_eq!(features, chunk_features(&gen, min), "chunk at {min}");
+            with_features += !features.is_empty() as u32;
+        }
+        assert!(with_features > 0);
+    }
+
+    #[test]
+    fn overlapping_trees_are_skipped() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        world
+            .set_voxels_collected(IVec3::ZERO, ivec3(31, 3, 31), Voxel::DIRT)
+            .unwrap();
+        let trees = TreeGen {
+            height: 6..7,
+            wood_voxel: Voxel::OAK_WOOD,
+            leaves_voxel: Voxel::OAK_LEAVES,
+            leaves_decay: 0.0,
+            branch_count: 0..1,
+            branch_height: 0.5..0.6,
+            branch_len: 1.0..2.0,
+        };
+        let mut rng = fastrand::Rng::with_seed(1);
+        let first = trees.generate(ivec3(8, 3, 16), &mut rng);
+        // its trunk grows out of the ground, which doesn't count
+        assert!(!first.is_obstructed(&world));
+        first.place(|pos, voxel| world.set_voxel(pos, voxel, |_| {}).unwrap());
+
+        // close enough for the leaves to merge
+        let overlapping = trees.generate(ivec3(12, 3, 16), &mut rng);
+        assert!(overlapping.is_obstructed(&world));
+        let apart = trees.generate(ivec3(24, 3, 16), &mut rng);
+        assert!(!apart.is_obstructed(&world));
+    }
+
+    #[test]
+    fn feature_queue_stays_bounded() {
+        // features floating in the sky, whose chunks will never load
+        let unplaceable = |age: Duration| {
+            let mut feature = Feature::default();
+            feature.voxel(ivec3(0, 100_000, 0), Voxel::OAK_LEAVES);
+            feature.created = Instant::now().checked_sub(age).unwrap();
+            feature
+        };
+        let mut queue: Vec<_> = (0..100).map(|_| unplaceable(MAX_FEATURE_AGE * 2)).collect();
+        for _ in 0..10 {
+            queue.extend((0..1000).map(|_| unplaceable(Duration::ZERO)));
+            evict_features(&mut queue);
+            assert!(queue.len() <= MAX_QUEUED_FEATURES);
+        }
+        assert_eq!(queue.len(), MAX_QUEUED_FEATURES);
+        assert!(queue.iter().all(|feature| feature.age() < MAX_FEATURE_AGE));
+    }
+
+    #[test]
+    fn low_terrain_floods_below_sea_level() {
+        crate::world::noise::init_gradients();
+        let gen = WorldGen::new(7).with_cave_threshold(1.0);
+        let min = ivec3(0, gen.terrain_height(0, 0) - 8, 0);
+        // the sea level is in the middle of the chunk, above all of its low terrain
+        let sea_level = min.y + 20;
+        let gen = gen.with_sea_level(sea_level);
+        let world = build_one(&gen, min);
+
+        let mut water = 0;
+        for x in 0..CHUNK_SIZE as i32 {
+            for z in 0..CHUNK_SIZE as i32 {
+                let h = gen.terrain_height(min.x + x, min.z + z);
+                for y in 0..CHUNK_SIZE as i32 {
+                    let voxel = world.get_voxel(ivec3(x, y, z)).unwrap();
+                    match min.y + y {
+                        world_y if world_y >= sea_level && world_y > h => {
+                            assert_eq!(voxel, Voxel::AIR)
+                        }
+                        world_y if world_y > h => {
+                            assert_eq!(voxel, Voxel::WATER);
+                            water += 1;
+                        }
+                        _ => assert_ne!(voxel, Voxel::AIR),
+                    }
+                }
+            }
+        }
+        assert!(water > 0);
+    }
+
+    #[test]
+    fn only_caves_open_to_the_sea_flood() {
+        crate::world::noise::init_gradients();
+        let gen = WorldGen::new(7).with_cave_threshold(0.3);
+        // around a cave that breaks through the sea floor, and somewhere else
+        let breach = (0..1000)
+            .map(|i| (i * 7, i * 3))
+            .find(|&(x, z)| gen.is_cave(ivec3(x, gen.terrain_height(x, z), z), 0))
+            .unwrap();
+        let (mut dry, mut flooded) = (0, 0);
+        for (x, z) in [(breach.0 - 16, breach.1 - 16), (0, 0)] {
+            let min = ivec3(x, gen.terrain_height(x + 16, z + 16) - 24, z);
+            let gen = WorldGen::new(7)
+                .with_cave_threshold(0.3)
+                .with_sea_level(min.y + 60);
+            let world = build_one(&gen, min);
+            count_caves(&gen, &world, min, &mut dry, &mut flooded);
+        }
+        assert!(dry > 0 && flooded > 0, "{dry} dry, {flooded} flooded");
+    }
+
+    /// Counts the dry and flooded cave voxels in the chunk `world` built at `min`,
+    /// checking only caves open to the sea are flooded.
+    fn count_caves(gen: &WorldGen, world: &World, min: IVec3, dry: &mut u32, flooded: &mut u32) {
+        for x in 0..CHUNK_SIZE as i32 {
+            for z in 0..CHUNK_SIZE as i32 {
+                let (wx, wz) = (min.x + x, min.z + z);
+                let h = gen.terrain_height(wx, wz);
+                let open_to_sea = |y: i32| (y..=h).all(|y| gen.is_cave(ivec3(wx, y, wz), h - y));
+                for y in 0..CHUNK_SIZE as i32 {
+                    let world_y = min.y + y;
+                    if world_y > h || !gen.is_cave(ivec3(wx, world_y, wz), h - world_y) {
+                        continue;
+                    }
+                    match world.get_voxel(ivec3(x, y, z)).unwrap() {
+                        Voxel::AIR => {
+                            assert!(!open_to_sea(world_y));
+                            *dry += 1;
+                        }
+                        Voxel::WATER => {
+                            assert!(open_to_sea(world_y));
+                            *flooded += 1;
+                        }
+                        voxel => panic!("cave holds {voxel:?}"),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use super::{vox_to_chunk_pos, World, CHUNK_SIZE};
+use crate::math::dda::cast_ray;
+use glam::{ivec3, IVec3, Vec3};
+use std::collections::VecDeque;
+
+/// The light level of the brightest emitters.
+/// Light drops by one level for every voxel it travels.
+pub const MAX_LIGHT: u8 = 15;
+
+const NEIGHBORS: [IVec3; 6] = [
+    ivec3(1, 0, 0),
+    ivec3(-1, 0, 0),
+    ivec3(0, 1, 0),
+    ivec3(0, -1, 0),
+    ivec3(0, 0, 1),
+    ivec3(0, 0, -1),
+];
+
+#[inline(always)]
+fn light_idx(local_pos: IVec3) -> usize {
+    let w = CHUNK_SIZE as usize;
+    local_pos.x as usize + local_pos.y as usize * w + local_pos.z as usize * w * w
+}
+
+/// Gameplay light levels, flood-filled from emissive voxels.
+/// Independent of the ray traced lighting.
+impl World {
+    /// The light level at `pos`, from 0 (dark) to `MAX_LIGHT`.
+    /// Positions outside of the world are dark.
+    pub fn light_level(&self, pos: IVec3) -> u8 {
+        let chunk_pos = vox_to_chunk_pos(pos);
+        let Some(chunk_idx) = self.chunk_idx(chunk_pos) else {
+            return 0;
+        };
+        let alloc = self.chunks[chunk_idx as usize].alloc;
+        match &self.lights[alloc as usize] {
+            Some(light) => light[light_idx(pos - chunk_pos * CHUNK_SIZE as i32)],
+            None => 0,
+        }
+    }
+
+    fn set_light_level(&mut self, pos: IVec3, level: u8) {
+        let chunk_pos = vox_to_chunk_pos(pos);
+        let Some(chunk_idx) = self.chunk_idx(chunk_pos) else {
+            return;
+        };
+        let alloc = self.chunks[chunk_idx as usize].alloc;
+        let light = &mut self.lights[alloc as usize];
+        if light.is_none() && level == 0 {
+            return;
+        }
+        let light =
+            light.get_or_insert_with(|| vec![0; CHUNK_SIZE.pow(3) as usize].into_boxed_slice());
+        light[light_idx(pos - chunk_pos * CHUNK_SIZE as i32)] = level;
+    }
+
+    /// Recomputes the light levels that could be affected by changing
+    /// the voxels between `min` and `max` (inclusive).
+    pub fn update_light(&mut self, min: IVec3, max: IVec3) {
+        let reach = IVec3::splat(MAX_LIGHT as i32);
+        let lo = (min - reach).max(self.min());
+        let hi = (max + reach).min(self.max() - IVec3::ONE);
+        let in_region = |pos: IVec3| pos.cmpge(lo).all() && pos.cmple(hi).all();
+
+        let mut queue = VecDeque::new();
+        // Clear the region and collect the emitters in it, as well as the
+        // lit voxels bordering it, which may shine back in.
+        for z in lo.z - 1..=hi.z + 1 {
+            for y in lo.y - 1..=hi.y + 1 {
+                for x in lo.x - 1..=hi.x + 1 {
+                    let pos = ivec3(x, y, z);
+                    if !in_region(pos) {
+                        if self.light_level(pos) > 1 {
+                            queue.push_back(pos);
+                        }
+                        continue;
+                    }
+                    let emission = self.get_voxel(pos).map_or(0, |v| v.light_emission());
+                    self.set_light_level(pos, emission);
+                    if emission > 0 {
+                        queue.push_back(pos);
+                    }
+                }
+            }
+        }
+
+        while let Some(pos) = queue.pop_front() {
+            let level = self.light_level(pos);
+            if level <= 1 {
+                continue;
+            }
+            for offset in NEIGHBORS {
+                let next = pos + offset;
+                if !in_region(next) || self.light_level(next) >= level - 1 {
+                    continue;
+                }
+                if self.get_voxel(next).map_or(true, |v| v.is_solid()) {
+                    continue;
+                }
+                self.set_light_level(next, level - 1);
+                queue.push_back(next);
+            }
+        }
+    }
+}
+
+/// Visibility of emissive voxels, as the ray tracer's local lights
+/// (`Settings::local_light_radius`) see them.
+impl World {
+    /// Whether the voxel at `light` is the first non-empty voxel a ray from `from`
+    /// towards its center reaches, so it lights `from` directly.
+    pub fn raymarch_to_light(&self, from: Vec3, light: IVec3) -> bool {
+        let to_light = light.as_vec3() + 0.5 - from;
+        let hit = cast_ray(from, to_light, to_light.length() + 1.0, |pos| {
+            self.get_voxel(pos).is_ok_and(|voxel| !voxel.is_empty())
+        });
+        hit.is_some_and(|hit| hit.pos == light)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Voxel, NODES_PER_CHUNK};
+    use glam::vec3;
+
+    #[test]
+    fn light_decays_with_distance_and_stops_at_solids() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        let source = ivec3(8, 16, 16);
+        let emission = Voxel::BRIGHT.light_emission();
+        world.set_voxel(source, Voxel::BRIGHT, |_| {}).unwrap();
+        // a wall across the whole chunk, 4 voxels from the source
+        let wall_x = source.x + 4;
+        world
+            .set_voxels_collected(ivec3(wall_x, 0, 0), ivec3(wall_x, 31, 31), Voxel::STONE)
+            .unwrap();
+        world.update_light(source, source);
+
+        assert_eq!(world.light_level(source), emission);
+        // one level less for every voxel away
+        for d in 1..emission as i32 {
+            assert_eq!(
+                world.light_level(source + ivec3(0, d, 0)),
+                emission - d as u8
+            );
+        }
+        assert_eq!(world.light_level(ivec3(wall_x - 1, 16, 16)), emission - 3);
+        // nothing gets through the wall, or lights it up
+        for y in 0..32 {
+            for z in 0..32 {
+                for x in wall_x..32 {
+                    assert_eq!(world.light_level(ivec3(x, y, z)), 0, "at {x}, {y}, {z}");
+                }
+            }
+        }
+
+        // and breaking the source puts the light out
+        world.set_voxel(source, Voxel::AIR, |_| {}).unwrap();
+        world.update_light(source, source);
+        assert_eq!(world.light_level(source + IVec3::Y), 0);
+    }
+
+    #[test]
+    fn walls_block_lights() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        let light = ivec3(16, 8, 16);
+        world.set_voxel(light, Voxel::BRIGHT, |_| {}).unwrap();
+        let from = vec3(10.5, 8.5, 16.5);
+        assert!(world.raymarch_to_light(from, light));
+        // diagonally, and from right next to it
+        assert!(world.raymarch_to_light(vec3(12.2, 11.7, 13.1), light));
+        assert!(world.raymarch_to_light(vec3(17.01, 8.5, 16.5), light));
+
+        world
+            .set_voxels_collected(ivec3(13, 0, 0), ivec3(13, 31, 31), Voxel::STONE)
+            .unwrap();
+        assert!(!world.raymarch_to_light(from, light));
+        // water doesn't block
+        world
+            .set_voxels_collected(ivec3(13, 0, 0), ivec3(13, 31, 31), Voxel::WATER)
+            .unwrap();
+        assert!(world.raymarch_to_light(from, light));
+        // an empty voxel isn't a light to reach
+        assert!(!world.raymarch_to_light(from, light + ivec3(0, 3, 0)));
+    }
+}
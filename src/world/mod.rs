@@ -1,11 +1,17 @@
 pub mod data;
+pub mod edit;
+pub mod export;
 pub mod gen;
+pub mod light;
 pub mod noise;
+pub mod save;
+pub mod snapshot;
 
 use crate::math::aabb::Aabb;
-use glam::{ivec3, IVec3, UVec3, Vec3};
+use glam::{ivec3, uvec3, IVec3, UVec3, Vec3};
 use std::ops::Range;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::time::{Duration, SystemTime};
 
 pub type NodeAddr = u32;
@@ -15,7 +21,27 @@ pub fn vox_to_chunk_pos(pos: IVec3) -> IVec3 {
     pos.div_euclid(IVec3::splat(CHUNK_SIZE as i32))
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// Sorts `ranges` and merges any that overlap or touch within the same chunk,
+/// so that uploading the result writes each node at most once.
+pub fn coalesce_ranges(mut ranges: Vec<Range<NodeAddr>>) -> Vec<Range<NodeAddr>> {
+    // chunk `i`'s nodes are `i * NODES_PER_CHUNK..(i + 1) * NODES_PER_CHUNK`
+    let chunk_of = |addr: NodeAddr| addr / NODES_PER_CHUNK;
+    ranges.sort_by_key(|range| range.start);
+    let mut result: Vec<Range<NodeAddr>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match result.last_mut() {
+            Some(last)
+                if range.start <= last.end && chunk_of(range.start) == chunk_of(last.start) =>
+            {
+                last.end = last.end.max(range.end)
+            }
+            _ => result.push(range),
+        }
+    }
+    result
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct Voxel(pub u16);
 impl Voxel {
@@ -64,6 +90,12 @@ impl Voxel {
         }
     }
 
+    /// Whether this voxel can be swum through, slowed by its `viscosity`.
+    #[inline(always)]
+    pub fn is_liquid(self) -> bool {
+        matches!(self, Self::WATER | Self::MAGMA)
+    }
+
     #[inline(always)]
     pub fn viscosity(self) -> f32 {
         match self {
@@ -75,6 +107,37 @@ impl Voxel {
             _ => 0.0,
         }
     }
+
+    /// The gameplay light level this voxel gives off (see `World::light_level`).
+    #[inline(always)]
+    pub fn light_emission(self) -> u8 {
+        match self {
+            Self::MAGMA => light::MAX_LIGHT,
+            Self::BRIGHT => light::MAX_LIGHT,
+            Self::FIRE => light::MAX_LIGHT - 1,
+            _ => 0,
+        }
+    }
+}
+
+/// Which voxels an edit is allowed to overwrite.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PlaceMode {
+    /// Only air and water, so placing never destroys anything.
+    ReplaceEmpty,
+    /// Anything.
+    ReplaceAny,
+    /// Only voxels of this type.
+    ReplaceMatching(Voxel),
+}
+impl PlaceMode {
+    pub fn can_replace(self, existing: Voxel) -> bool {
+        match self {
+            Self::ReplaceEmpty => existing.is_empty(),
+            Self::ReplaceAny => true,
+            Self::ReplaceMatching(voxel) => existing == voxel,
+        }
+    }
 }
 
 /// Represents a node in the sparse voxel octree (SVO) for each chunk.
@@ -150,6 +213,8 @@ pub enum WorldErr {
     Oob,
     ChunkOob,
     NodeAllocLimit,
+    /// A chunk's lock couldn't be acquired.
+    Locked,
 }
 
 struct FoundNode {
@@ -185,6 +250,109 @@ impl NodeAlloc {
     pub fn reset(&mut self) {
         self.next = self.range.start;
     }
+
+    /// Moves the nodes reachable from `root` to the front of this allocator's range,
+    /// rewriting child indices to match, so nodes left unreachable by edits can be reused.
+    /// Returns how many nodes were freed. Nothing moves if none can be freed.
+    pub fn compact(&mut self, nodes: &mut [Node], root: NodeAddr) -> u32 {
+        let start = self.range.start;
+        // The old address of each live group of 8 children, in breadth first order.
+        // The group at `groups[i]` moves to `start + i * 8`.
+        let mut groups = vec![];
+        let mut live = vec![];
+
+        let mut root_node = nodes[root as usize];
+        if root_node.is_split() {
+            groups.push(root_node.child_idx());
+            root_node.set_child_idx(start);
+        }
+        let mut i = 0;
+        while i < groups.len() {
+            for old in groups[i]..groups[i] + 8 {
+                let mut node = nodes[old as usize];
+                if node.is_split() {
+                    groups.push(node.child_idx());
+                    node.set_child_idx(start + (groups.len() as u32 - 1) * 8);
+                }
+                live.push(node);
+            }
+            i += 1;
+        }
+
+        let end = start + live.len() as u32;
+        let freed = self.next - end;
+        if freed == 0 {
+            return 0;
+        }
+        // every live node was read above, so it's safe to overwrite them now
+        nodes[root as usize] = root_node;
+        nodes[start as usize..end as usize].copy_from_slice(&live);
+        self.next = end;
+        freed
+    }
+}
+
+/// Iterates over the leaf nodes of a chunk's SVO. See `World::chunk_leaves`.
+pub struct ChunkLeaves<'a> {
+    nodes: &'a [Node],
+    /// Nodes left to visit, as `(idx, min, size)`.
+    stack: Vec<(NodeAddr, UVec3, u32)>,
+}
+impl<'a> Iterator for ChunkLeaves<'a> {
+    type Item = (UVec3, u32, Voxel);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((idx, min, size)) = self.stack.pop() {
+            let node = self.nodes[idx as usize];
+            if !node.is_split() {
+                return Some((min, size, node.voxel()));
+            }
+            let half = size / 2;
+            for i in 0..8 {
+                let offset = uvec3(i & 1, (i >> 1) & 1, (i >> 2) & 1) * half;
+                self.stack.push((node.child_idx() + i, min + offset, half));
+            }
+        }
+        None
+    }
+}
+
+/// Writes `voxels`, a `width`-wide cube indexed `x + (y + z * width) * width`,
+/// into `nodes[idx]` and children pushed onto `nodes`, only splitting nodes
+/// whose voxels differ. Like `World::set_chunk_voxels`, for SVOs outside the world.
+fn build_svo(
+    nodes: &mut Vec<Node>,
+    idx: usize,
+    voxels: &[Voxel],
+    width: u32,
+    min: UVec3,
+    size: u32,
+) {
+    let voxel_at = |pos: UVec3| voxels[(pos.x + (pos.y + pos.z * width) * width) as usize];
+    let first = voxel_at(min);
+    let uniform = (0..size.pow(3)).all(|i| {
+        let offset = uvec3(i % size, (i / size) % size, i / (size * size));
+        voxel_at(min + offset) == first
+    });
+    if uniform {
+        nodes[idx] = Node::new(first);
+        return;
+    }
+    let first_child = nodes.len();
+    nodes[idx] = Node::new_split(first_child as u32);
+    nodes.extend([Node::ZERO; 8]);
+    let half = size / 2;
+    for i in 0..8 {
+        let offset = uvec3(i & 1, (i >> 1) & 1, (i >> 2) & 1) * half;
+        build_svo(
+            nodes,
+            first_child + i as usize,
+            voxels,
+            width,
+            min + offset,
+            half,
+        );
+    }
 }
 
 /// The voxel-width of a chunk.
@@ -194,6 +362,10 @@ pub const CHUNK_SIZE: u32 = 32;
 /// Derived from "2^(CHUNK_DEPTH) = CHUNK_SIZE"
 pub const CHUNK_DEPTH: u32 = 5;
 
+/// The most chunks moving the world hands out to rebuild each frame (see `World::take_rebuilds`),
+/// so a teleport spreads its rebuilds over several frames instead of stalling one.
+pub const MAX_REBUILDS_PER_FRAME: usize = 64;
+
 /// The maximum number of nodes a chunk could need to represent it's state.
 /// Derived from "1 + 2^3 + 4^3 + 8^3 + 16^3 + 32^3"
 pub const NODES_PER_CHUNK: u32 = 37_449;
@@ -210,19 +382,35 @@ impl ChunkHeader {
 
 pub struct World {
     min: IVec3,
-    size_in_chunks: u32,
+    /// How many chunks the world spans along each axis.
+    size_in_chunks: UVec3,
     chunk_count: u32,
     prev_anchor_chunk: IVec3,
 
     pub chunks: Box<[ChunkHeader]>,
     pub region_locks: Box<[AtomicBool]>,
+    /// Describes who holds each of the `region_locks`, for diagnosing contention.
+    pub region_lock_owners: Box<[Mutex<Option<String>>]>,
     pub allocs: Box<[NodeAlloc]>,
     pub nodes: Box<[Node]>,
+    /// Light levels of each chunk, indexed like `allocs`.
+    /// `None` for chunks that are completely dark.
+    pub lights: Box<[Option<Box<[u8]>>]>,
+    /// Whether each chunk has been generated for its current position, indexed like `allocs`.
+    pub loaded: Box<[bool]>,
+    /// Chunks left to generate after moving the world, waiting for `take_rebuilds`.
+    rebuild_queue: Vec<IVec3>,
 }
 /// Create and clear worlds
 impl World {
+    /// A cube of `size_in_chunks` chunks along each axis.
     pub fn new(max_nodes: u32, size_in_chunks: u32) -> Self {
-        let chunk_count = size_in_chunks * size_in_chunks * size_in_chunks;
+        Self::new_dims(max_nodes, UVec3::splat(size_in_chunks))
+    }
+
+    /// A box of `size_in_chunks` chunks along each axis, for worlds that are wider than they are tall.
+    pub fn new_dims(max_nodes: u32, size_in_chunks: UVec3) -> Self {
+        let chunk_count = size_in_chunks.x * size_in_chunks.y * size_in_chunks.z;
 
         let nodes = vec![Node::ZERO; max_nodes as usize].into_boxed_slice();
 
@@ -248,6 +436,12 @@ impl World {
             .map(|_| AtomicBool::new(false))
             .collect::<Vec<_>>()
             .into_boxed_slice();
+        let region_lock_owners = (0..chunk_count)
+            .map(|_| Mutex::new(None))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        let lights = (0..chunk_count).map(|_| None).collect();
+        let loaded = vec![false; chunk_count as usize].into_boxed_slice();
         Self {
             min: IVec3::ZERO,
             size_in_chunks,
@@ -258,27 +452,21 @@ impl World {
             allocs,
             nodes,
             region_locks,
+            region_lock_owners,
+            lights,
+            loaded,
+            rebuild_queue: vec![],
         }
     }
 
-    #[inline(always)]
-    pub fn size(&self) -> u32 {
-        self.size_in_chunks * CHUNK_SIZE
-    }
-
     #[inline(always)]
     pub fn size3(&self) -> UVec3 {
-        UVec3::splat(self.size_in_chunks * CHUNK_SIZE)
-    }
-
-    #[inline(always)]
-    pub fn size_in_chunks(&self) -> u32 {
-        self.size_in_chunks
+        self.size_in_chunks * CHUNK_SIZE
     }
 
     #[inline(always)]
     pub fn size_in_chunks3(&self) -> UVec3 {
-        UVec3::splat(self.size_in_chunks)
+        self.size_in_chunks
     }
 
     #[inline(always)]
@@ -309,10 +497,10 @@ impl World {
     pub fn chunk_idx(&self, mut pos: IVec3) -> Option<u32> {
         pos -= self.min_chunk_pos();
         let w = self.size_in_chunks;
-        if pos.cmplt(IVec3::ZERO).any() || pos.cmpge(IVec3::splat(w as i32)).any() {
+        if pos.cmplt(IVec3::ZERO).any() || pos.cmpge(w.as_ivec3()).any() {
             return None;
         }
-        Some(pos.x as u32 + pos.y as u32 * w + pos.z as u32 * w * w)
+        Some(pos.x as u32 + pos.y as u32 * w.x + pos.z as u32 * w.x * w.y)
     }
 
     #[inline(always)]
@@ -340,7 +528,9 @@ impl World {
         in_bounds.then(|| ()).ok_or(WorldErr::Oob)
     }
 
-    pub fn lock_chunk(&mut self, idx: u32) {
+    /// Spins until the lock for chunk `idx` is acquired, recording `owner` as the holder.
+    /// Gives up after a second, logging who holds the lock, and returns false.
+    pub fn lock_chunk(&mut self, idx: u32, owner: &str) -> bool {
         let start_time = SystemTime::now();
         while self.region_locks[idx as usize]
             .compare_exchange(false, true, Ordering::Acquire, Ordering::SeqCst)
@@ -352,40 +542,55 @@ impl World {
                 .as_millis()
                 > 1000
             {
-                panic!("THREAD LOCK NOT BEING UNLOCKED : {idx}");
+                let holder = self.region_lock_owners[idx as usize]
+                    .lock()
+                    .map(|holder| holder.clone())
+                    .unwrap_or_default();
+                log::warn!(
+                    "{owner} gave up waiting for chunk lock {idx}, held by {}",
+                    holder.as_deref().unwrap_or("<unknown>")
+                );
+                return false;
             }
+            std::thread::yield_now();
+        }
+        if let Ok(mut holder) = self.region_lock_owners[idx as usize].lock() {
+            *holder = Some(format!("{owner} ({:?})", std::thread::current().id()));
         }
+        true
     }
     pub fn unlock_chunk(&mut self, idx: u32) {
+        if let Ok(mut holder) = self.region_lock_owners[idx as usize].lock() {
+            *holder = None;
+        }
         self.region_locks[idx as usize].store(false, Ordering::Release)
     }
 }
 /// Manage chunks
 impl World {
     pub fn rotate_chunks(&mut self, offset: IVec3) -> Vec<IVec3> {
-        let w = self.size_in_chunks;
-        let pos_as_idx =
-            |pos: IVec3| (pos.x + pos.y * w as i32 + pos.z * w as i32 * w as i32) as usize;
-        let pos_oob =
-            |pos: IVec3| pos.cmplt(IVec3::ZERO).any() || pos.cmpge(IVec3::splat(w as i32)).any();
+        let w = self.size_in_chunks.as_ivec3();
+        let pos_as_idx = |pos: IVec3| (pos.x + pos.y * w.x + pos.z * w.x * w.y) as usize;
+        let pos_oob = |pos: IVec3| pos.cmplt(IVec3::ZERO).any() || pos.cmpge(w).any();
 
         let mut new_chunks = vec![ChunkHeader::ZERO; self.chunk_count as usize].into_boxed_slice();
         let mut rebuild = vec![];
         let min_chunk = self.min_chunk_pos();
 
-        for x in 0..w {
-            for y in 0..w {
-                for z in 0..w {
-                    let pos = ivec3(x as i32, y as i32, z as i32);
+        for x in 0..w.x {
+            for y in 0..w.y {
+                for z in 0..w.z {
+                    let pos = ivec3(x, y, z);
                     let idx = pos_as_idx(pos);
                     let dst_pos = pos - offset;
-                    let dst_pos = dst_pos.rem_euclid(IVec3::splat(w as i32));
+                    let dst_pos = dst_pos.rem_euclid(w);
                     let dst_idx = pos_as_idx(dst_pos);
 
                     new_chunks[dst_idx] = self.chunks[idx].clone();
 
                     if pos_oob(pos + offset) {
                         rebuild.push(pos + min_chunk);
+                        self.loaded[self.chunks[idx].alloc as usize] = false;
                     }
                 }
             }
@@ -396,14 +601,14 @@ impl World {
 
     pub fn update(&mut self, anchor: IVec3) -> Vec<IVec3> {
         let chunk_size = IVec3::splat(CHUNK_SIZE as i32);
-        let w = self.size_in_chunks;
+        let w = self.size_in_chunks.as_ivec3();
 
         let anchor_chunk = vox_to_chunk_pos(anchor);
         if anchor_chunk == self.prev_anchor_chunk {
             return vec![];
         }
         let prev_min_chunk = self.min / chunk_size;
-        let min_chunk = anchor_chunk - IVec3::splat(w as i32 / 2);
+        let min_chunk = anchor_chunk - w / 2;
 
         if prev_min_chunk == min_chunk {
             return vec![];
@@ -411,7 +616,87 @@ impl World {
         self.min = min_chunk * chunk_size;
 
         let chunk_offset = min_chunk - prev_min_chunk;
-        self.rotate_chunks(chunk_offset)
+        let rebuild = if chunk_offset.abs().cmpge(w).any() {
+            // The new region doesn't overlap the old one (e.g. after a teleport),
+            // so every chunk has to be rebuilt and there's nothing worth rotating.
+            self.loaded.fill(false);
+            self.all_chunk_positions()
+        } else {
+            self.rotate_chunks(chunk_offset)
+        };
+        self.rebuild_queue.extend_from_slice(&rebuild);
+        rebuild
+    }
+
+    /// Hands out up to `max` of the chunks `update` left to generate, nearest to the
+    /// center of the world first. Chunks that have since left the world or been
+    /// generated are dropped.
+    pub fn take_rebuilds(&mut self, max: usize) -> Vec<IVec3> {
+        let mut queue = std::mem::take(&mut self.rebuild_queue);
+        let mut queued = std::collections::HashSet::new();
+        queue.retain(|pos| {
+            !self.is_chunk_loaded(*pos) && self.chunk_idx(*pos).is_some() && queued.insert(*pos)
+        });
+        // sorted so the nearest chunks are last, to be taken first
+        let center = self.min_chunk_pos() + self.size_in_chunks.as_ivec3() / 2;
+        queue.sort_by_key(|pos| std::cmp::Reverse((*pos - center).length_squared()));
+        let taken = queue.split_off(queue.len().saturating_sub(max));
+        self.rebuild_queue = queue;
+        taken
+    }
+
+    /// How urgently the chunk at `pos` should be built for a viewer at `eye` looking
+    /// along `facing`, lower being more urgent. Blends the distance to the chunk with
+    /// how far it is from the view direction, from 0 (only distance) to 1 (only view direction).
+    pub fn chunk_build_priority(
+        &self,
+        pos: IVec3,
+        eye: Vec3,
+        facing: Vec3,
+        view_weight: f32,
+    ) -> f32 {
+        let center = (pos * CHUNK_SIZE as i32 + CHUNK_SIZE as i32 / 2).as_vec3();
+        let to_chunk = center - eye;
+        let dist = to_chunk.length() / self.size3().max_element() as f32;
+        let dot = to_chunk.normalize_or_zero().dot(facing);
+        let view = (1.0 - dot) * 0.5;
+        dist * (1.0 - view_weight) + view * view_weight
+    }
+
+    /// Whether the chunk at `pos` is in the world and has been generated.
+    pub fn is_chunk_loaded(&self, pos: IVec3) -> bool {
+        match self.chunk_idx(pos) {
+            Some(idx) => self.loaded[self.chunks[idx as usize].alloc as usize],
+            None => false,
+        }
+    }
+
+    /// Marks `chunk` as generated (or not) for its current position.
+    pub fn set_chunk_loaded(&mut self, chunk: &ChunkHeader, loaded: bool) {
+        self.loaded[chunk.alloc as usize] = loaded;
+    }
+
+    /// The global positions and headers of every generated chunk.
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = (IVec3, &ChunkHeader)> + '_ {
+        self.all_chunk_positions().into_iter().filter_map(|pos| {
+            let chunk = &self.chunks[self.chunk_idx(pos)? as usize];
+            self.loaded[chunk.alloc as usize].then_some((pos, chunk))
+        })
+    }
+
+    /// The global positions of every chunk currently in the world.
+    pub fn all_chunk_positions(&self) -> Vec<IVec3> {
+        let w = self.size_in_chunks.as_ivec3();
+        let min_chunk = self.min_chunk_pos();
+        let mut result = Vec::with_capacity(self.chunk_count as usize);
+        for z in 0..w.z {
+            for y in 0..w.y {
+                for x in 0..w.x {
+                    result.push(min_chunk + ivec3(x, y, z));
+                }
+            }
+        }
+        result
     }
 }
 /// Find and mutate the SVO nodes that make up the world.
@@ -452,6 +737,67 @@ impl World {
         }
     }
 
+    /// Every leaf node of `chunk`'s SVO, as `(min, size, voxel)`, with `min`
+    /// relative to the chunk's minimum corner. Together they tile the chunk.
+    pub fn chunk_leaves(&self, chunk: &ChunkHeader) -> ChunkLeaves<'_> {
+        ChunkLeaves {
+            nodes: &self.nodes,
+            stack: vec![(chunk.root, UVec3::ZERO, CHUNK_SIZE)],
+        }
+    }
+
+    /// The tight bounds of the non-air voxels in `chunk`, relative to the chunk's
+    /// minimum corner, as `(min, max)` with `max` exclusive.
+    /// `None` if the chunk is all air.
+    pub fn chunk_content_bounds(&self, chunk: &ChunkHeader) -> Option<(UVec3, UVec3)> {
+        self.chunk_leaves(chunk)
+            .filter(|(_, _, voxel)| *voxel != Voxel::AIR)
+            .map(|(min, size, _)| (min, min + size))
+            .reduce(|(a_min, a_max), (b_min, b_max)| (a_min.min(b_min), a_max.max(b_max)))
+    }
+
+    /// A half-resolution copy of `chunk`'s SVO, for distant LOD or a minimap.
+    /// Each 2x2x2 group of voxels collapses to the voxel most of them hold,
+    /// with ties going to the non-air voxel with the lowest id.
+    /// The root is at index 0 and child indices point into the returned nodes.
+    pub fn downsample_chunk(&self, chunk: &ChunkHeader) -> Vec<Node> {
+        const HALF: u32 = CHUNK_SIZE / 2;
+        let mut counts = vec![Vec::<(Voxel, u32)>::new(); HALF.pow(3) as usize];
+        for (min, size, voxel) in self.chunk_leaves(chunk) {
+            // a leaf covers whole groups, or one voxel of a group
+            let (group_min, group_size, weight) = match size {
+                1 => (min / 2, 1, 1),
+                _ => (min / 2, size / 2, 8),
+            };
+            for i in 0..group_size.pow(3) {
+                let offset = uvec3(
+                    i % group_size,
+                    (i / group_size) % group_size,
+                    i / (group_size * group_size),
+                );
+                let pos = group_min + offset;
+                let group = &mut counts[(pos.x + (pos.y + pos.z * HALF) * HALF) as usize];
+                match group.iter_mut().find(|(v, _)| *v == voxel) {
+                    Some((_, count)) => *count += weight,
+                    None => group.push((voxel, weight)),
+                }
+            }
+        }
+        let voxels: Vec<Voxel> = counts
+            .iter()
+            .map(|group| {
+                let key = |(voxel, count): &(Voxel, u32)| {
+                    (*count, *voxel != Voxel::AIR, u16::MAX - voxel.0)
+                };
+                group.iter().max_by_key(|group| key(group)).unwrap().0
+            })
+            .collect();
+
+        let mut nodes = vec![Node::ZERO];
+        build_svo(&mut nodes, 0, &voxels, HALF, UVec3::ZERO, HALF);
+        nodes
+    }
+
     fn find_node(&self, pos: IVec3, max_depth: u32) -> Result<FoundNode, WorldErr> {
         let chunk_coords = vox_to_chunk_pos(pos);
         let chunk_idx = self.chunk_idx(chunk_coords).ok_or(WorldErr::ChunkOob)?;
@@ -468,9 +814,58 @@ impl World {
         Ok(first_idx)
     }
 
+    /// Replaces `chunk`'s SVO with `voxels`, indexed `x + (y + z * CHUNK_SIZE) * CHUNK_SIZE`.
+    /// Nodes are only split where their voxels differ, so a chunk that's mostly air
+    /// (like one holding a sliver of the surface) takes as few nodes as it can.
+    pub fn set_chunk_voxels(
+        &mut self,
+        chunk: &ChunkHeader,
+        voxels: &[Voxel],
+    ) -> Result<(), WorldErr> {
+        self.allocs[chunk.alloc as usize].reset();
+        self.write_voxels_node(chunk.alloc, chunk.root, voxels, UVec3::ZERO, CHUNK_SIZE)
+    }
+
+    fn write_voxels_node(
+        &mut self,
+        alloc_idx: u32,
+        idx: NodeAddr,
+        voxels: &[Voxel],
+        min: UVec3,
+        size: u32,
+    ) -> Result<(), WorldErr> {
+        let voxel_at =
+            |pos: UVec3| voxels[(pos.x + (pos.y + pos.z * CHUNK_SIZE) * CHUNK_SIZE) as usize];
+        let first = voxel_at(min);
+        let uniform = (0..size.pow(3)).all(|i| {
+            let offset = uvec3(i % size, (i / size) % size, i / (size * size));
+            voxel_at(min + offset) == first
+        });
+        if uniform {
+            *self.mut_node(idx) = Node::new(first);
+            return Ok(());
+        }
+        let first_child = self.alloc_nodes(alloc_idx, Voxel::AIR)?;
+        *self.mut_node(idx) = Node::new_split(first_child);
+        let half = size / 2;
+        for i in 0..8 {
+            let offset = uvec3(i & 1, (i >> 1) & 1, (i >> 2) & 1) * half;
+            self.write_voxels_node(alloc_idx, first_child + i, voxels, min + offset, half)?;
+        }
+        Ok(())
+    }
+
+    /// Reclaims the nodes of `chunk` that edits left unreachable. See `NodeAlloc::compact`.
+    /// The chunk's nodes must be re-uploaded if any were freed.
+    pub fn compact_chunk(&mut self, chunk: &ChunkHeader) -> u32 {
+        self.allocs[chunk.alloc as usize].compact(&mut self.nodes, chunk.root)
+    }
+
     #[inline(always)]
     pub fn reset_alloc(&mut self, alloc_idx: u32) {
-        self.allocs[alloc_idx as usize].reset()
+        self.allocs[alloc_idx as usize].reset();
+        self.lights[alloc_idx as usize] = None;
+        self.loaded[alloc_idx as usize] = false;
     }
 
     #[inline(always)]
@@ -479,6 +874,50 @@ impl World {
         Ok(self.get_node(idx).voxel())
     }
 
+    /// The voxel at `pos`, along with the bounds of the uniform SVO node it's in,
+    /// as `(voxel, min, max)` with `max` exclusive.
+    pub fn get_voxel_node(&self, pos: IVec3) -> Result<(Voxel, IVec3, IVec3), WorldErr> {
+        let FoundNode {
+            idx, center, size, ..
+        } = self.find_node(pos, CHUNK_DEPTH)?;
+        let min = match size {
+            // a voxel sized node doesn't have a center of its own
+            1 => pos,
+            size => vox_to_chunk_pos(pos) * CHUNK_SIZE as i32 + center - size as i32 / 2,
+        };
+        Ok((self.get_node(idx).voxel(), min, min + size as i32))
+    }
+
+    /// Returns the Y of the topmost solid voxel in the loaded chunks of the column at `x`/`z`,
+    /// or `None` if none of them has a solid voxel.
+    /// Chunks that haven't been generated yet are skipped, since they're empty rather
+    /// than open sky, so a higher surface may still appear once they're loaded.
+    /// Skips over whole empty nodes instead of testing every voxel.
+    pub fn column_top_solid(&self, x: i32, z: i32) -> Option<i32> {
+        let mut y = self.max().y - 1;
+        while y >= self.min().y {
+            let pos = ivec3(x, y, z);
+            let chunk_y = vox_to_chunk_pos(pos).y * CHUNK_SIZE as i32;
+            if !self.is_chunk_loaded(vox_to_chunk_pos(pos)) {
+                y = chunk_y - 1;
+                continue;
+            }
+            let FoundNode {
+                idx, center, size, ..
+            } = self.find_node(pos, CHUNK_DEPTH).ok()?;
+            if self.get_node(idx).voxel().is_solid() {
+                return Some(y);
+            }
+            // skip to just below the bottom of this node
+            let bottom = match size {
+                1 => y,
+                _ => chunk_y + center.y - size as i32 / 2,
+            };
+            y = bottom - 1;
+        }
+        None
+    }
+
     pub fn set_voxel_in_chunk(
         &mut self,
         chunk: ChunkHeader,
@@ -564,20 +1003,77 @@ impl World {
 }
 /// High-level voxel-based manipulation.
 impl World {
-    pub fn set_voxels(&mut self, a: IVec3, b: IVec3, voxel: Voxel) {
-        let min = ivec3(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
-        let max = ivec3(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
-
+    /// Sets the voxels in the box between `a` and `b` (inclusive) that `mode` allows,
+    /// skipping any outside of the world. Stops at the first voxel that can't be set.
+    pub fn fill_box(
+        &mut self,
+        a: IVec3,
+        b: IVec3,
+        voxel: Voxel,
+        mode: PlaceMode,
+        mut on_change: impl FnMut(Range<NodeAddr>),
+    ) -> Result<(), WorldErr> {
+        let (min, max) = (a.min(b), a.max(b));
         for x in min.x..=max.x {
             for y in min.y..=max.y {
                 for z in min.z..=max.z {
-                    _ = self.set_voxel(ivec3(x, y, z), voxel, |_| {});
+                    let pos = ivec3(x, y, z);
+                    if !self.get_voxel(pos).is_ok_and(|v| mode.can_replace(v)) {
+                        continue;
+                    }
+                    self.set_voxel(pos, voxel, &mut on_change)?;
                 }
             }
         }
+        Ok(())
+    }
+
+    pub fn fill_box_collected(
+        &mut self,
+        a: IVec3,
+        b: IVec3,
+        voxel: Voxel,
+        mode: PlaceMode,
+    ) -> Result<Vec<Range<NodeAddr>>, WorldErr> {
+        let mut result = vec![];
+        self.fill_box(a, b, voxel, mode, |range| result.push(range))?;
+        Ok(coalesce_ranges(result))
     }
 
-    pub fn get_collisions_w(&self, aabb: &Aabb) -> Vec<Aabb> {
+    /// Sets the voxels from `a` to `b`, skipping any outside of the world.
+    /// Stops at the first voxel that can't be set, like when a chunk runs out of nodes.
+    pub fn set_line(
+        &mut self,
+        a: IVec3,
+        b: IVec3,
+        voxel: Voxel,
+        mode: PlaceMode,
+        mut on_change: impl FnMut(Range<NodeAddr>),
+    ) -> Result<(), WorldErr> {
+        for pos in crate::math::walk_line(a, b) {
+            if !self.get_voxel(pos).is_ok_and(|v| mode.can_replace(v)) {
+                continue;
+            }
+            self.set_voxel(pos, voxel, &mut on_change)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_line_collected(
+        &mut self,
+        a: IVec3,
+        b: IVec3,
+        voxel: Voxel,
+        mode: PlaceMode,
+    ) -> Result<Vec<Range<NodeAddr>>, WorldErr> {
+        let mut result = vec![];
+        self.set_line(a, b, voxel, mode, |range| result.push(range))?;
+        Ok(coalesce_ranges(result))
+    }
+
+    /// Collects the boxes of the non-empty voxels overlapping `aabb`.
+    /// If `oob_solid` is set, positions outside of the world collide too.
+    pub fn get_collisions_w(&self, aabb: &Aabb, oob_solid: bool) -> Vec<Aabb> {
         let mut aabbs = Vec::new();
 
         let from = aabb.from.floor().as_ivec3();
@@ -588,9 +1084,13 @@ impl World {
                 for z in from.z..to.z {
                     let pos = ivec3(x, y, z);
 
-                    let voxel = self.get_voxel(pos).unwrap_or(Voxel::AIR);
+                    let voxel = match self.get_voxel(pos) {
+                        Ok(voxel) => voxel,
+                        Err(_) if oob_solid => Voxel::STONE,
+                        Err(_) => Voxel::AIR,
+                    };
 
-                    if !voxel.is_empty() {
+                    if !voxel.is_empty() && !voxel.is_liquid() {
                         let min = pos.as_vec3();
                         let max = min + 1.0;
                         aabbs.push(Aabb::new(min, max));
@@ -601,7 +1101,53 @@ impl World {
         aabbs
     }
 
+    /// The lowest `viscosity` of the liquids overlapping `aabb`, or `None` if it's not in any.
+    pub fn liquid_viscosity(&self, aabb: &Aabb) -> Option<f32> {
+        let from = aabb.from.floor().as_ivec3();
+        let to = aabb.to.ceil().as_ivec3();
+        let mut viscosity: Option<f32> = None;
+        for x in from.x..to.x {
+            for y in from.y..to.y {
+                for z in from.z..to.z {
+                    let Ok(voxel) = self.get_voxel(ivec3(x, y, z)) else {
+                        continue;
+                    };
+                    if voxel.is_liquid() {
+                        let v = voxel.viscosity();
+                        viscosity = Some(viscosity.map_or(v, |prev| prev.min(v)));
+                    }
+                }
+            }
+        }
+        viscosity
+    }
+
     pub fn sphere(&mut self, pos: IVec3, r: u32, voxel: Voxel, decay: f32) {
+        _ = self.set_sphere(pos, r, voxel, decay, PlaceMode::ReplaceAny, |_| {})
+    }
+
+    pub fn set_sphere_collected(
+        &mut self,
+        pos: IVec3,
+        r: u32,
+        voxel: Voxel,
+        decay: f32,
+        mode: PlaceMode,
+    ) -> Result<Vec<Range<NodeAddr>>, WorldErr> {
+        let mut result = vec![];
+        self.set_sphere(pos, r, voxel, decay, mode, |range| result.push(range))?;
+        Ok(coalesce_ranges(result))
+    }
+
+    pub fn set_sphere(
+        &mut self,
+        pos: IVec3,
+        r: u32,
+        voxel: Voxel,
+        decay: f32,
+        mode: PlaceMode,
+        mut on_change: impl FnMut(Range<NodeAddr>),
+    ) -> Result<(), WorldErr> {
         let pos_center = pos.as_vec3() + Vec3::splat(0.5);
         let min = pos - IVec3::splat(r as i32);
         let max = pos + IVec3::splat(r as i32);
@@ -613,13 +1159,651 @@ impl World {
                     let block_center = ivec3(x, y, z).as_vec3() + Vec3::splat(0.5);
                     let dist_sq = (block_center - pos_center).length_squared();
 
-                    if dist_sq >= r_sq || fastrand::f32() <= decay {
+                    if dist_sq >= r_sq || fastrand::f32() < decay {
+                        continue;
+                    }
+                    let existing = self.get_voxel(ivec3(x, y, z));
+                    if !existing.is_ok_and(|v| mode.can_replace(v)) {
                         continue;
                     }
 
-                    _ = self.set_voxel(ivec3(x, y, z), voxel, |_| {});
+                    self.set_voxel(ivec3(x, y, z), voxel, &mut on_change)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Sets every voxel from `a` to `b` (inclusive) to `voxel`, skipping any outside of the world.
+    /// Unlike `fill_box`, whole nodes inside the box are set at once instead of voxel by voxel,
+    /// so large regions touch far fewer nodes.
+    pub fn set_voxels(
+        &mut self,
+        a: IVec3,
+        b: IVec3,
+        voxel: Voxel,
+        on_change: impl FnMut(Range<NodeAddr>),
+    ) -> Result<(), WorldErr> {
+        let (min, max) = (a.min(b), a.max(b) + 1);
+        self.fill_region(min, max, voxel, on_change, |node_min, node_max| {
+            match (node_min.cmpge(min).all(), node_max.cmple(max).all()) {
+                _ if node_max.cmple(min).any() || node_min.cmpge(max).any() => Coverage::None,
+                (true, true) => Coverage::Full,
+                _ => Coverage::Partial,
+            }
+        })
+    }
+
+    pub fn set_voxels_collected(
+        &mut self,
+        a: IVec3,
+        b: IVec3,
+        voxel: Voxel,
+    ) -> Result<Vec<Range<NodeAddr>>, WorldErr> {
+        let mut result = vec![];
+        self.set_voxels(a, b, voxel, |range| result.push(range))?;
+        Ok(coalesce_ranges(result))
+    }
+
+    /// Sets the same voxels as `set_sphere` would with no decay, replacing anything,
+    /// but a node at a time like `set_voxels`.
+    pub fn fill_sphere(
+        &mut self,
+        pos: IVec3,
+        r: u32,
+        voxel: Voxel,
+        on_change: impl FnMut(Range<NodeAddr>),
+    ) -> Result<(), WorldErr> {
+        let pos_center = pos.as_vec3() + Vec3::splat(0.5);
+        let r_sq = r as f32 * r as f32;
+        let (min, max) = (pos - r as i32, pos + r as i32 + 1);
+        self.fill_region(min, max, voxel, on_change, |node_min, node_max| {
+            // the nearest and furthest voxel centers in the node
+            let (lo, hi) = (node_min.as_vec3() + 0.5, node_max.as_vec3() - 0.5);
+            let nearest = pos_center.clamp(lo, hi) - pos_center;
+            let furthest = (lo - pos_center).abs().max((hi - pos_center).abs());
+            if nearest.length_squared() >= r_sq {
+                Coverage::None
+            } else if furthest.length_squared() < r_sq {
+                Coverage::Full
+            } else {
+                Coverage::Partial
+            }
+        })
+    }
+
+    pub fn fill_sphere_collected(
+        &mut self,
+        pos: IVec3,
+        r: u32,
+        voxel: Voxel,
+    ) -> Result<Vec<Range<NodeAddr>>, WorldErr> {
+        let mut result = vec![];
+        self.fill_sphere(pos, r, voxel, |range| result.push(range))?;
+        Ok(coalesce_ranges(result))
+    }
+
+    /// Sets the voxels that `coverage` says are in a region within `min..max`, in every chunk
+    /// overlapping it. Nodes fully in the region become a single `voxel` leaf, and nodes
+    /// partly in it are split until they're fully in or out.
+    fn fill_region(
+        &mut self,
+        min: IVec3,
+        max: IVec3,
+        voxel: Voxel,
+        mut on_change: impl FnMut(Range<NodeAddr>),
+        coverage: impl Fn(IVec3, IVec3) -> Coverage,
+    ) -> Result<(), WorldErr> {
+        let (min_chunk, max_chunk) = (vox_to_chunk_pos(min), vox_to_chunk_pos(max - 1));
+        for x in min_chunk.x..=max_chunk.x {
+            for y in min_chunk.y..=max_chunk.y {
+                for z in min_chunk.z..=max_chunk.z {
+                    let chunk_pos = ivec3(x, y, z);
+                    let Some(idx) = self.chunk_idx(chunk_pos) else {
+                        continue;
+                    };
+                    let chunk = self.chunks[idx as usize].clone();
+                    self.fill_node(
+                        chunk.alloc,
+                        chunk.root,
+                        chunk_pos * CHUNK_SIZE as i32,
+                        CHUNK_SIZE,
+                        voxel,
+                        &mut on_change,
+                        &coverage,
+                    )?;
                 }
             }
         }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn fill_node(
+        &mut self,
+        alloc_idx: u32,
+        idx: NodeAddr,
+        min: IVec3,
+        size: u32,
+        voxel: Voxel,
+        on_change: &mut impl FnMut(Range<NodeAddr>),
+        coverage: &impl Fn(IVec3, IVec3) -> Coverage,
+    ) -> Result<(), WorldErr> {
+        let node = self.get_node(idx);
+        match coverage(min, min + size as i32) {
+            Coverage::None => return Ok(()),
+            // already filled with `voxel`
+            _ if !node.is_split() && node.voxel() == voxel => return Ok(()),
+            Coverage::Full => {
+                // any children are left unreachable, like when `set_voxel` overwrites a node
+                *self.mut_node(idx) = Node::new(voxel);
+                on_change(idx..idx + 1);
+                return Ok(());
+            }
+            Coverage::Partial => {}
+        }
+        let first_child = match node.is_split() {
+            true => node.child_idx(),
+            false => {
+                let first_child = self.alloc_nodes(alloc_idx, node.voxel())?;
+                *self.mut_node(idx) = Node::new_split(first_child);
+                on_change(idx..idx + 1);
+                on_change(first_child..first_child + 8);
+                first_child
+            }
+        };
+        let half = size / 2;
+        for i in 0..8 {
+            let offset = ivec3((i & 1) as i32, ((i >> 1) & 1) as i32, ((i >> 2) & 1) as i32);
+            let child_min = min + offset * half as i32;
+            self.fill_node(
+                alloc_idx,
+                first_child + i,
+                child_min,
+                half,
+                voxel,
+                on_change,
+                coverage,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// How much of a node a region filled by `World::fill_region` covers.
+enum Coverage {
+    None,
+    Partial,
+    Full,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    fn test_world() -> World {
+        World::new_dims(2 * NODES_PER_CHUNK, uvec3(2, 1, 1))
+    }
+
+    /// Every voxel in `world`, in x, y, z order.
+    fn all_voxels(world: &World) -> Vec<Voxel> {
+        let (min, max) = (world.min(), world.max());
+        let mut voxels = vec![];
+        for x in min.x..max.x {
+            for y in min.y..max.y {
+                for z in min.z..max.z {
+                    voxels.push(world.get_voxel(ivec3(x, y, z)).unwrap());
+                }
+            }
+        }
+        voxels
+    }
+
+    fn random_pos(rng: &mut fastrand::Rng, world: &World) -> IVec3 {
+        let (min, max) = (world.min(), world.max());
+        ivec3(
+            rng.i32(min.x..max.x),
+            rng.i32(min.y..max.y),
+            rng.i32(min.z..max.z),
+        )
+    }
+
+    /// Sets `count` random voxels of `world` to random voxel types.
+    fn scatter(rng: &mut fastrand::Rng, world: &mut World, count: usize) {
+        for _ in 0..count {
+            let pos = random_pos(rng, world);
+            world.set_voxel(pos, Voxel(rng.u16(0..23)), |_| {}).unwrap();
+        }
+    }
+
+    #[test]
+    fn random_voxels_read_back() {
+        let mut rng = fastrand::Rng::with_seed(1);
+        let mut world = test_world();
+        let mut expected = HashMap::new();
+        for _ in 0..2000 {
+            let pos = random_pos(&mut rng, &world);
+            let voxel = Voxel(rng.u16(1..23));
+            let chunk_pos = vox_to_chunk_pos(pos);
+            let chunk = world.chunks[world.chunk_idx(chunk_pos).unwrap() as usize].clone();
+            world
+                .set_voxel_in_chunk(chunk, pos - chunk_pos * CHUNK_SIZE as i32, voxel, |_| {})
+                .unwrap();
+            expected.insert(pos, voxel);
+        }
+        for (pos, voxel) in &expected {
+            assert_eq!(world.get_voxel(*pos).unwrap(), *voxel, "at {pos}");
+        }
+        // and everything else is still air
+        let set = all_voxels(&world)
+            .into_iter()
+            .filter(|v| *v != Voxel::AIR)
+            .count();
+        assert_eq!(set, expected.len());
+    }
+
+    #[test]
+    fn compaction_keeps_voxels() {
+        let mut rng = fastrand::Rng::with_seed(2);
+        let mut world = test_world();
+        scatter(&mut rng, &mut world, 3000);
+        // leaves most of the nodes split off above unreachable
+        world
+            .set_voxels(ivec3(0, 0, 0), ivec3(40, 20, 31), Voxel::STONE, |_| {})
+            .unwrap();
+        let before = all_voxels(&world);
+
+        for chunk in world.chunks.clone().iter() {
+            let next = world.allocs[chunk.alloc as usize].next;
+            let freed = world.compact_chunk(chunk);
+            assert!(freed > 0);
+            assert_eq!(world.allocs[chunk.alloc as usize].next, next - freed);
+            // compacting again has nothing left to free
+            assert_eq!(world.compact_chunk(chunk), 0);
+        }
+        assert!(before == all_voxels(&world));
+
+        // and the freed nodes can be used again
+        scatter(&mut rng, &mut world, 3000);
+    }
+
+    #[test]
+    fn region_fills_match_voxel_writes() {
+        let mut rng = fastrand::Rng::with_seed(3);
+        let mut nodes = test_world();
+        scatter(&mut rng, &mut nodes, 1000);
+        let mut voxels = test_world();
+        voxels.nodes.copy_from_slice(&nodes.nodes);
+        voxels.allocs.clone_from_slice(&nodes.allocs);
+
+        for _ in 0..10 {
+            let (a, b) = (random_pos(&mut rng, &nodes), random_pos(&mut rng, &nodes));
+            let voxel = Voxel(rng.u16(0..23));
+            nodes.set_voxels(a, b, voxel, |_| {}).unwrap();
+            voxels
+                .fill_box(a, b, voxel, PlaceMode::ReplaceAny, |_| {})
+                .unwrap();
+
+            let (pos, r) = (random_pos(&mut rng, &nodes), rng.u32(1..20));
+            let voxel = Voxel(rng.u16(0..23));
+            nodes.fill_sphere(pos, r, voxel, |_| {}).unwrap();
+            voxels
+                .set_sphere(pos, r, voxel, 0.0, PlaceMode::ReplaceAny, |_| {})
+                .unwrap();
+        }
+        assert!(all_voxels(&nodes) == all_voxels(&voxels));
+    }
+
+    #[test]
+    fn far_teleport_rebuilds_a_batch_per_frame() {
+        let mut world = World::new(64 * NODES_PER_CHUNK, 4);
+        let far = ivec3(10_000, 50, -3_000);
+        let invalidated = world.update(far);
+        assert_eq!(invalidated.len(), 64);
+
+        let mut rebuilt = HashSet::new();
+        for frame in 0.. {
+            let batch = world.take_rebuilds(16);
+            if batch.is_empty() {
+                assert_eq!(frame, 4);
+                break;
+            }
+            assert!(batch.len() <= 16);
+            for pos in batch {
+                assert!(rebuilt.insert(pos), "{pos} handed out twice");
+                let chunk = world.chunks[world.chunk_idx(pos).unwrap() as usize].clone();
+                world.set_chunk_loaded(&chunk, true);
+            }
+        }
+        assert_eq!(rebuilt.len(), 64);
+    }
+
+    #[test]
+    fn rebuilds_that_left_the_world_are_dropped() {
+        let mut world = World::new(64 * NODES_PER_CHUNK, 4);
+        world.update(ivec3(10_000, 50, -3_000));
+        let first = world.take_rebuilds(16);
+        // jumping again before the rest are taken
+        world.update(ivec3(-10_000, 50, 3_000));
+        let mut rest = vec![];
+        loop {
+            let batch = world.take_rebuilds(16);
+            if batch.is_empty() {
+                break;
+            }
+            rest.extend(batch);
+        }
+        assert!(first.iter().all(|pos| world.chunk_idx(*pos).is_none()));
+        assert_eq!(rest.len(), 64);
+        assert!(rest.iter().all(|pos| world.chunk_idx(*pos).is_some()));
+    }
+
+    #[test]
+    fn drag_lines_are_continuous() {
+        let mut rng = fastrand::Rng::with_seed(12);
+        for _ in 0..50 {
+            let mut world = test_world();
+            let (a, b) = (random_pos(&mut rng, &world), random_pos(&mut rng, &world));
+            world
+                .set_line_collected(a, b, Voxel::STONE, PlaceMode::ReplaceAny)
+                .unwrap();
+            let line: Vec<IVec3> = crate::math::walk_line(a, b).collect();
+            assert_eq!((line[0], *line.last().unwrap()), (a, b));
+            // no gaps between a voxel and the next, however fast the cursor moved
+            for pair in line.windows(2) {
+                let step = (pair[1] - pair[0]).abs();
+                assert_eq!(step.max_element(), 1, "gap from {} to {}", pair[0], pair[1]);
+            }
+            for pos in &line {
+                assert_eq!(world.get_voxel(*pos).unwrap(), Voxel::STONE, "at {pos}");
+            }
+            let set = all_voxels(&world)
+                .into_iter()
+                .filter(|v| *v != Voxel::AIR)
+                .count();
+            assert_eq!(set, line.len());
+        }
+    }
+
+    /// Asserts every node that differs from `before` is in one of `ranges`,
+    /// and that the ranges are coalesced.
+    fn assert_ranges_cover(world: &World, before: &[u32], ranges: &[Range<NodeAddr>]) {
+        for pair in ranges.windows(2) {
+            assert!(pair[0].end < pair[1].start, "{pair:?} aren't coalesced");
+        }
+        let changed = (world.nodes().iter().zip(before))
+            .enumerate()
+            .filter(|(_, (node, prev))| node.0 != **prev)
+            .map(|(idx, _)| idx as NodeAddr);
+        for idx in changed {
+            assert!(
+                ranges.iter().any(|r| r.contains(&idx)),
+                "node {idx} changed outside the ranges"
+            );
+        }
+    }
+
+    #[test]
+    fn sphere_ranges_cover_the_changed_nodes() {
+        // straddling both chunks
+        let center = ivec3(32, 16, 16);
+        let mut leaves = vec![];
+        for fill in [false, true] {
+            let mut world = test_world();
+            let (min, max) = (world.min(), world.max() - 1);
+            world.set_voxels_collected(min, max, Voxel::STONE).unwrap();
+            let before: Vec<u32> = world.nodes().iter().map(|n| n.0).collect();
+            let ranges = match fill {
+                false => {
+                    world.set_sphere_collected(center, 8, Voxel::AIR, 0.0, PlaceMode::ReplaceAny)
+                }
+                true => world.fill_sphere_collected(center, 8, Voxel::AIR),
+            };
+            assert_ranges_cover(&world, &before, &ranges.unwrap());
+            assert_eq!(world.get_voxel(center).unwrap(), Voxel::AIR);
+            assert_eq!(
+                world.get_voxel(center + ivec3(9, 0, 0)).unwrap(),
+                Voxel::STONE
+            );
+            let chunks = world.chunks.clone();
+            leaves.push(
+                chunks
+                    .iter()
+                    .flat_map(|c| world.chunk_leaves(c))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        // both carve the same hole, but filling whole nodes leaves the inside of the
+        // sphere as bigger uniform leaves
+        let voxel_count = |leaves: &[(UVec3, u32, Voxel)]| {
+            leaves
+                .iter()
+                .filter(|l| l.2 == Voxel::AIR)
+                .map(|l| l.1.pow(3))
+                .sum::<u32>()
+        };
+        assert_eq!(voxel_count(&leaves[0]), voxel_count(&leaves[1]));
+        assert!(leaves[1].len() < leaves[0].len());
+        assert!(leaves[1]
+            .iter()
+            .any(|(_, size, voxel)| *voxel == Voxel::AIR && *size > 1));
+    }
+
+    #[test]
+    fn contended_locks_give_up_without_panicking() {
+        let mut world = test_world();
+        assert!(world.lock_chunk(0, "holder"));
+        // times out instead of panicking, and leaves the lock to its holder
+        assert!(!world.lock_chunk(0, "waiter"));
+        let holder = world.region_lock_owners[0].lock().unwrap().clone();
+        assert!(holder.unwrap().starts_with("holder"));
+        assert!(world.lock_chunk(1, "waiter"));
+
+        world.unlock_chunk(0);
+        assert!(world.lock_chunk(0, "waiter"));
+        let holder = world.region_lock_owners[0].lock().unwrap().clone();
+        assert!(holder.unwrap().starts_with("waiter"));
+    }
+
+    #[test]
+    fn view_weighting_builds_chunks_ahead_first() {
+        let world = World::new(64 * NODES_PER_CHUNK, 4);
+        let eye = Vec3::splat(64.0);
+        let (ahead, behind) = (ivec3(3, 1, 1), ivec3(0, 1, 1));
+        // equidistant from the eye, looking toward +X
+        let priority = |pos, weight| world.chunk_build_priority(pos, eye, Vec3::X, weight);
+        assert_eq!(priority(ahead, 0.0), priority(behind, 0.0));
+        for weight in [0.1, 0.5, 1.0] {
+            assert!(priority(ahead, weight) < priority(behind, weight));
+        }
+    }
+
+    #[test]
+    fn column_top_skips_unloaded_chunks() {
+        let mut world = World::new_dims(2 * NODES_PER_CHUNK, uvec3(1, 2, 1));
+        let min = world.min();
+        let (low, high) = (min + ivec3(3, 5, 3), min + ivec3(3, 40, 3));
+        world.set_voxel(low, Voxel::STONE, |_| {}).unwrap();
+        world.set_voxel(high, Voxel::STONE, |_| {}).unwrap();
+        let chunk =
+            |y: i32| world.chunks[world.chunk_idx(ivec3(0, y, 0)).unwrap() as usize].clone();
+        let (bottom, top) = (chunk(0), chunk(1));
+        let column_top = |world: &World| world.column_top_solid(low.x, low.z);
+
+        assert_eq!(column_top(&world), None);
+        world.set_chunk_loaded(&bottom, true);
+        assert_eq!(column_top(&world), Some(low.y));
+        world.set_chunk_loaded(&top, true);
+        assert_eq!(column_top(&world), Some(high.y));
+        world.set_chunk_loaded(&bottom, false);
+        assert_eq!(column_top(&world), Some(high.y));
+    }
+
+    #[test]
+    fn loaded_chunks_are_exactly_the_populated_ones() {
+        let mut rng = fastrand::Rng::with_seed(13);
+        let mut world = World::new(27 * NODES_PER_CHUNK, 3);
+        world.update(ivec3(100, 100, -100));
+        assert_eq!(world.loaded_chunks().count(), 0);
+
+        let mut populated = HashSet::new();
+        for pos in world.all_chunk_positions() {
+            if rng.bool() {
+                let chunk = world.chunks[world.chunk_idx(pos).unwrap() as usize].clone();
+                world.set_chunk_loaded(&chunk, true);
+                populated.insert(pos);
+            }
+        }
+        let loaded: HashSet<IVec3> = world.loaded_chunks().map(|(pos, _)| pos).collect();
+        assert_eq!(loaded, populated);
+        assert_eq!(world.loaded_chunks().count(), populated.len());
+        for pos in world.all_chunk_positions() {
+            assert_eq!(
+                world.is_chunk_loaded(pos),
+                populated.contains(&pos),
+                "{pos}"
+            );
+        }
+        // outside of the world
+        assert!(!world.is_chunk_loaded(world.min_chunk_pos() - IVec3::ONE));
+    }
+
+    /// The voxel at `pos` in an SVO `size` voxels wide rooted at `nodes[0]`.
+    fn svo_voxel(nodes: &[Node], pos: UVec3, mut size: u32) -> Voxel {
+        let mut node = nodes[0];
+        while node.is_split() {
+            size /= 2;
+            let child = (pos / size) & 1;
+            node = nodes[(node.child_idx() + (child.x | child.y << 1 | child.z << 2)) as usize];
+        }
+        node.voxel()
+    }
+
+    #[test]
+    fn downsampling_keeps_the_majority_voxel() {
+        let mut rng = fastrand::Rng::with_seed(11);
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        let chunk = world.chunks[0].clone();
+        let idx = |pos: UVec3| (pos.x + (pos.y + pos.z * CHUNK_SIZE) * CHUNK_SIZE) as usize;
+        // solid below, noise in the middle, air above
+        let mut voxels = vec![Voxel::AIR; CHUNK_SIZE.pow(3) as usize];
+        for i in 0..CHUNK_SIZE.pow(3) {
+            let pos = uvec3(
+                i % CHUNK_SIZE,
+                (i / CHUNK_SIZE) % CHUNK_SIZE,
+                i / CHUNK_SIZE.pow(2),
+            );
+            voxels[idx(pos)] = match pos.y {
+                0..=11 => Voxel::STONE,
+                12..=19 => [Voxel::AIR, Voxel::STONE, Voxel::DIRT][rng.usize(0..3)],
+                _ => Voxel::AIR,
+            };
+        }
+        world.set_chunk_voxels(&chunk, &voxels).unwrap();
+
+        let nodes = world.downsample_chunk(&chunk);
+        let half = CHUNK_SIZE / 2;
+        for i in 0..half.pow(3) {
+            let pos = uvec3(i % half, (i / half) % half, i / half.pow(2));
+            let mut counts = [0; 3];
+            for j in 0..8 {
+                let voxel = voxels[idx(pos * 2 + uvec3(j & 1, (j >> 1) & 1, j >> 2))];
+                counts[voxel.0 as usize] += 1;
+            }
+            let most = *counts.iter().max().unwrap();
+            // ties go to the lowest non-air id
+            let expected = match counts.iter().rposition(|c| *c == most) {
+                Some(0) => Voxel::AIR,
+                _ => Voxel((1..3).find(|v| counts[*v as usize] == most).unwrap()),
+            };
+            assert_eq!(
+                svo_voxel(&nodes, pos, half),
+                expected,
+                "at {pos}, counts {counts:?}"
+            );
+        }
+        // the uniform layers stay collapsed
+        assert!(nodes.len() < half.pow(3) as usize);
+    }
+
+    #[test]
+    fn placing_respects_the_place_mode() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        let (stone, water) = (ivec3(4, 4, 4), ivec3(5, 4, 4));
+        world.set_voxel(stone, Voxel::STONE, |_| {}).unwrap();
+        world.set_voxel(water, Voxel::WATER, |_| {}).unwrap();
+
+        let mut place = |pos, mode| {
+            world
+                .set_line_collected(pos, pos, Voxel::GOLD, mode)
+                .unwrap();
+            world.get_voxel(pos).unwrap()
+        };
+        // building over a solid voxel leaves it alone
+        assert_eq!(place(stone, PlaceMode::ReplaceEmpty), Voxel::STONE);
+        assert_eq!(
+            place(stone, PlaceMode::ReplaceMatching(Voxel::DIRT)),
+            Voxel::STONE
+        );
+        assert_eq!(place(water, PlaceMode::ReplaceEmpty), Voxel::GOLD);
+        assert_eq!(
+            place(stone, PlaceMode::ReplaceMatching(Voxel::STONE)),
+            Voxel::GOLD
+        );
+        assert_eq!(place(ivec3(6, 4, 4), PlaceMode::ReplaceAny), Voxel::GOLD);
+    }
+
+    #[test]
+    fn content_bounds_fit_the_non_air_voxels() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        let chunk = world.chunks[0].clone();
+        assert_eq!(world.chunk_content_bounds(&chunk), None);
+
+        world
+            .set_voxel(ivec3(5, 9, 20), Voxel::STONE, |_| {})
+            .unwrap();
+        assert_eq!(
+            world.chunk_content_bounds(&chunk),
+            Some((uvec3(5, 9, 20), uvec3(6, 10, 21)))
+        );
+        world
+            .set_voxel(ivec3(30, 2, 21), Voxel::WATER, |_| {})
+            .unwrap();
+        assert_eq!(
+            world.chunk_content_bounds(&chunk),
+            Some((uvec3(5, 2, 20), uvec3(31, 10, 22)))
+        );
+    }
+
+    #[test]
+    fn leaves_tile_the_chunk() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        let chunk = world.chunks[0].clone();
+        let leaves: Vec<_> = world.chunk_leaves(&chunk).collect();
+        assert_eq!(leaves, vec![(UVec3::ZERO, CHUNK_SIZE, Voxel::AIR)]);
+
+        // one voxel splits a node at every depth, each split adding 7 leaves
+        world
+            .set_voxel(ivec3(5, 9, 20), Voxel::STONE, |_| {})
+            .unwrap();
+        let leaves: Vec<_> = world.chunk_leaves(&chunk).collect();
+        assert_eq!(leaves.len(), 1 + 7 * CHUNK_DEPTH as usize);
+        assert!(leaves.contains(&(uvec3(5, 9, 20), 1, Voxel::STONE)));
+
+        world
+            .set_voxels_collected(ivec3(16, 0, 0), ivec3(31, 15, 15), Voxel::DIRT)
+            .unwrap();
+        world
+            .set_voxel(ivec3(30, 30, 1), Voxel::WATER, |_| {})
+            .unwrap();
+        let mut covered = vec![0; CHUNK_SIZE.pow(3) as usize];
+        for (min, size, voxel) in world.chunk_leaves(&chunk) {
+            for i in 0..size.pow(3) {
+                let pos = min + uvec3(i % size, (i / size) % size, i / (size * size));
+                assert_eq!(world.get_voxel(pos.as_ivec3()).unwrap(), voxel, "at {pos}");
+                covered[(pos.x + (pos.y + pos.z * CHUNK_SIZE) * CHUNK_SIZE) as usize] += 1;
+            }
+        }
+        assert!(covered.iter().all(|&count| count == 1));
     }
 }
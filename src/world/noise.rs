@@ -4,7 +4,7 @@
 //!
 //! Ported to Rust by Mason Feurer (Excluding 4D noise functions)
 //!
-use glam::Vec2;
+use glam::{Vec2, Vec3};
 
 #[derive(Clone)]
 pub struct NoiseMap {
@@ -28,6 +28,67 @@ impl NoiseMap {
     }
 }
 
+#[derive(Clone)]
+pub struct NoiseMap3D {
+    noise: Box<OpenSimplexNoise>,
+    scale: f64,
+    pub freq: f64,
+}
+impl NoiseMap3D {
+    pub fn new(seed: i64, freq: f64, scale: f64) -> Self {
+        Self {
+            noise: Box::new(OpenSimplexNoise::new(seed)),
+            freq,
+            scale,
+        }
+    }
+    pub fn get(&self, pos: Vec3) -> f32 {
+        let val = self.noise.eval3d(
+            pos.x as f64 * self.freq,
+            pos.y as f64 * self.freq,
+            pos.z as f64 * self.freq,
+        );
+        (((val + 1.0) * 0.5) * self.scale) as f32
+    }
+}
+
+/// Several octaves of `NoiseMap3D` summed together, each at twice the frequency
+/// and `persistence` times the weight of the last, for detail at several sizes.
+/// Stays within `0..scale` like a single `NoiseMap3D`.
+#[derive(Clone)]
+pub struct MultiNoiseMap3D {
+    octaves: Vec<(NoiseMap3D, f64)>,
+    scale: f64,
+}
+impl MultiNoiseMap3D {
+    pub fn new(seed: i64, freq: f64, scale: f64, octaves: u32, persistence: f64) -> Self {
+        let octaves: Vec<_> = (0..octaves.max(1))
+            .map(|i| {
+                let map =
+                    NoiseMap3D::new(seed.wrapping_add(i as i64), freq * 2f64.powi(i as i32), 1.0);
+                (map, persistence.powi(i as i32))
+            })
+            .collect();
+        // so the weights sum to 1
+        let total: f64 = octaves.iter().map(|(_, weight)| weight).sum();
+        Self {
+            octaves: octaves
+                .into_iter()
+                .map(|(map, weight)| (map, weight / total))
+                .collect(),
+            scale,
+        }
+    }
+    pub fn get(&self, pos: Vec3) -> f32 {
+        let val: f64 = self
+            .octaves
+            .iter()
+            .map(|(map, weight)| map.get(pos) as f64 * weight)
+            .sum();
+        (val * self.scale) as f32
+    }
+}
+
 const STRETCH_CONSTANT_2D: f64 = -0.211324865405187; // (1/(2+1).sqrt()-1)/2;
 const SQUISH_CONSTANT_2D: f64 = 0.366025403784439; // ((2+1).sqrt()-1)/2;
 const STRETCH_CONSTANT_3D: f64 = -1.0 / 6.0; // (1/(3+1).sqrt()-1)/3;
@@ -863,7 +924,14 @@ const N3: f64 = 26.92263139946168;
 static mut GRADIENTS_2D: [Grad2; PSIZE] = [Grad2::ZERO; PSIZE];
 static mut GRADIENTS_3D: [Grad3; PSIZE] = [Grad3::ZERO; PSIZE];
 
+/// Fills the gradient tables every `OpenSimplexNoise` is built from.
+/// Only the first call does anything, so it's fine to call from several threads.
 pub fn init_gradients() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(fill_gradients);
+}
+
+fn fill_gradients() {
     let mut grad2 = [
         Grad2 {
             dx: 0.130526192220052,
@@ -1225,3 +1293,57 @@ pub fn init_gradients() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_points() -> impl Iterator<Item = Vec3> {
+        (0..2000).map(|i| {
+            let i = i as f32;
+            Vec3::new(i * 7.3 - 5000.0, i * 0.37 - 300.0, (i * 13.1).sin() * 900.0)
+        })
+    }
+
+    #[test]
+    fn deterministic_for_a_seed() {
+        init_gradients();
+        let (a, b) = (NoiseMap3D::new(5, 0.03, 1.0), NoiseMap3D::new(5, 0.03, 1.0));
+        let other = NoiseMap3D::new(6, 0.03, 1.0);
+        assert!(sample_points().all(|pos| a.get(pos) == b.get(pos)));
+        assert!(sample_points().any(|pos| a.get(pos) != other.get(pos)));
+
+        let (a, b) = (
+            MultiNoiseMap3D::new(5, 0.01, 1.0, 4, 0.5),
+            MultiNoiseMap3D::new(5, 0.01, 1.0, 4, 0.5),
+        );
+        assert!(sample_points().all(|pos| a.get(pos) == b.get(pos)));
+    }
+
+    #[test]
+    fn stays_in_range() {
+        init_gradients();
+        let single = NoiseMap3D::new(5, 0.03, 40.0);
+        let multi = MultiNoiseMap3D::new(5, 0.01, 40.0, 5, 0.5);
+        let single: Vec<_> = sample_points().map(|pos| single.get(pos)).collect();
+        let multi: Vec<_> = sample_points().map(|pos| multi.get(pos)).collect();
+        for values in [single, multi] {
+            let (min, max) = values
+                .into_iter()
+                .fold((f32::MAX, f32::MIN), |(min, max), v| {
+                    (min.min(v), max.max(v))
+                });
+            assert!(min >= 0.0 && max <= 40.0, "{min}..{max}");
+            // and isn't stuck at one value
+            assert!(max - min > 10.0, "{min}..{max}");
+        }
+    }
+
+    #[test]
+    fn one_octave_is_a_single_map() {
+        init_gradients();
+        let single = NoiseMap3D::new(5, 0.03, 2.0);
+        let multi = MultiNoiseMap3D::new(5, 0.03, 2.0, 1, 0.5);
+        assert!(sample_points().all(|pos| single.get(pos) == multi.get(pos)));
+    }
+}
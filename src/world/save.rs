@@ -0,0 +1,365 @@
+use super::data::VOXEL_NAMES;
+use super::{ChunkHeader, Node, NodeAddr, World, CHUNK_DEPTH, CHUNK_SIZE, NODES_PER_CHUNK};
+use glam::{IVec3, UVec3};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The first bytes of every saved world.
+const MAGIC: &[u8; 4] = b"VRTW";
+/// The version of the save format written by `save_to_file`.
+/// Bump it when the format changes, and keep reading the old versions in `load_from_file`.
+/// - 1: the world size is one `u32`, for cube worlds
+/// - 2: the world size is 3 `u32`s
+const SAVE_VERSION: u32 = 2;
+/// The most chunks along each axis a saved world can have, so a corrupt size can't
+/// make loading allocate an absurd amount of nodes.
+const MAX_SIZE_IN_CHUNKS: u32 = 32;
+
+#[derive(Debug)]
+pub enum SaveErr {
+    Io(std::io::Error),
+    /// The file isn't a saved world.
+    NotAWorld,
+    UnsupportedVersion(u32),
+    /// The file ends before the world does.
+    Truncated,
+    /// The file is the right length but its contents don't make a valid world.
+    Corrupt(&'static str),
+}
+impl From<std::io::Error> for SaveErr {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+impl std::fmt::Display for SaveErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::NotAWorld => f.write_str("not a saved world"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "saved with format version {version}, only up to {SAVE_VERSION} is supported"
+            ),
+            Self::Truncated => f.write_str("file is truncated"),
+            Self::Corrupt(what) => write!(f, "file is corrupt: {what}"),
+        }
+    }
+}
+impl std::error::Error for SaveErr {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Reads little-endian values off the front of a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveErr> {
+        if self.bytes.len() < len {
+            return Err(SaveErr::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveErr> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveErr> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn ivec3(&mut self) -> Result<IVec3, SaveErr> {
+        let mut v = [0; 3];
+        for c in &mut v {
+            *c = self.u32()? as i32;
+        }
+        Ok(IVec3::from_array(v))
+    }
+}
+
+/// Walks a chunk's SVO from its root, checking that it's a tree no deeper than
+/// `CHUNK_DEPTH` whose leaves are all known voxels.
+fn check_chunk_tree(nodes: &[Node], root: NodeAddr) -> Result<(), SaveErr> {
+    // the first child of every split node seen, so a cycle or shared children is caught
+    let mut visited = HashSet::new();
+    let mut stack = vec![(root, 0)];
+    while let Some((idx, depth)) = stack.pop() {
+        let node = nodes[idx as usize];
+        if !node.is_split() {
+            if node.child_idx() as usize >= VOXEL_NAMES.len() {
+                return Err(SaveErr::Corrupt("unknown voxel id"));
+            }
+            continue;
+        }
+        if depth >= CHUNK_DEPTH {
+            return Err(SaveErr::Corrupt("chunk is deeper than CHUNK_DEPTH"));
+        }
+        if !visited.insert(node.child_idx()) {
+            return Err(SaveErr::Corrupt("node reachable from two parents"));
+        }
+        for child in node.child_idx()..node.child_idx() + 8 {
+            stack.push((child, depth + 1));
+        }
+    }
+    Ok(())
+}
+
+fn push_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn push_ivec3(out: &mut Vec<u8>, v: IVec3) {
+    for c in v.to_array() {
+        push_u32(out, c as u32);
+    }
+}
+
+/// Save worlds to disk and load them back.
+///
+/// A saved world is (all little-endian):
+/// - `MAGIC`, then the format version as a `u32`
+/// - the size in chunks as 3 `u32`s, then `min` and `prev_anchor_chunk` as 3 `i32`s each
+/// - each chunk header, as its root and alloc (`u32`s)
+/// - for each alloc: whether it's loaded (`u8`), its `next` (`u32`), the used nodes
+///   from its chunk's root up to `next` (`u32`s), then whether it has light levels (`u8`)
+///   and if so, one `u8` per voxel
+impl World {
+    pub fn save_to_file(&self, path: &Path) -> Result<(), SaveErr> {
+        let mut out = vec![];
+        out.extend_from_slice(MAGIC);
+        push_u32(&mut out, SAVE_VERSION);
+        for c in self.size_in_chunks.to_array() {
+            push_u32(&mut out, c);
+        }
+        push_ivec3(&mut out, self.min);
+        push_ivec3(&mut out, self.prev_anchor_chunk);
+        for chunk in self.chunks.iter() {
+            push_u32(&mut out, chunk.root);
+            push_u32(&mut out, chunk.alloc);
+        }
+        for (idx, alloc) in self.allocs.iter().enumerate() {
+            out.push(self.loaded[idx] as u8);
+            push_u32(&mut out, alloc.next);
+            for node in &self.nodes[alloc.range.start as usize - 1..alloc.next as usize] {
+                push_u32(&mut out, node.0);
+            }
+            match &self.lights[idx] {
+                Some(light) => {
+                    out.push(1);
+                    out.extend_from_slice(light);
+                }
+                None => out.push(0),
+            }
+        }
+        std::fs::write(path, out)?;
+        Ok(())
+    }
+
+    /// Loads a world saved by `save_to_file`, checking that every node stays within
+    /// its chunk and every chunk is a valid tree, so a corrupt file can't send
+    /// traversal out of bounds or into a loop.
+    /// `SaveErr` is a `std::error::Error`, so it converts into `anyhow` or boxed errors with `?`.
+    pub fn load_from_file(path: &Path) -> Result<World, SaveErr> {
+        let bytes = std::fs::read(path)?;
+        let mut r = Reader { bytes: &bytes };
+        if r.take(MAGIC.len()).map_err(|_| SaveErr::NotAWorld)? != MAGIC {
+            return Err(SaveErr::NotAWorld);
+        }
+        let size_in_chunks = match r.u32()? {
+            1 => UVec3::splat(r.u32()?),
+            2 => r.ivec3()?.as_uvec3(),
+            version => return Err(SaveErr::UnsupportedVersion(version)),
+        };
+        if size_in_chunks.min_element() == 0 || size_in_chunks.max_element() > MAX_SIZE_IN_CHUNKS {
+            return Err(SaveErr::Corrupt("world size out of range"));
+        }
+        let chunk_count = size_in_chunks.x * size_in_chunks.y * size_in_chunks.z;
+        // every chunk takes at least its header, a root node and 3 other fields, so
+        // a truncated file is caught before allocating the world
+        if r.bytes.len() < 24 + chunk_count as usize * 18 {
+            return Err(SaveErr::Truncated);
+        }
+
+        let mut world = World::new_dims(chunk_count * NODES_PER_CHUNK, size_in_chunks);
+        world.min = r.ivec3()?;
+        world.prev_anchor_chunk = r.ivec3()?;
+        if world.min != world.min_chunk_pos() * CHUNK_SIZE as i32 {
+            return Err(SaveErr::Corrupt("world isn't aligned to chunks"));
+        }
+
+        let mut alloc_used = vec![false; chunk_count as usize];
+        for idx in 0..chunk_count as usize {
+            let (root, alloc) = (r.u32()?, r.u32()?);
+            let Some(used) = alloc_used.get_mut(alloc as usize) else {
+                return Err(SaveErr::Corrupt("chunk alloc out of range"));
+            };
+            if std::mem::replace(used, true) {
+                return Err(SaveErr::Corrupt("alloc shared by two chunks"));
+            }
+            if root + 1 != world.allocs[alloc as usize].range.start {
+                return Err(SaveErr::Corrupt("chunk root outside of its alloc"));
+            }
+            world.chunks[idx] = ChunkHeader { root, alloc };
+        }
+
+        for idx in 0..chunk_count as usize {
+            world.loaded[idx] = r.u8()? != 0;
+            let range = world.allocs[idx].range.clone();
+            let next = r.u32()?;
+            if next < range.start || next > range.end || !(next - range.start).is_multiple_of(8) {
+                return Err(SaveErr::Corrupt("alloc position out of range"));
+            }
+            world.allocs[idx].next = next;
+            for addr in range.start - 1..next {
+                let node = Node(r.u32()?);
+                if node.is_split()
+                    && (node.child_idx() < range.start || node.child_idx() + 8 > next)
+                {
+                    return Err(SaveErr::Corrupt("node child outside of its chunk"));
+                }
+                world.nodes[addr as usize] = node;
+            }
+            world.lights[idx] = match r.u8()? {
+                0 => None,
+                _ => Some(r.take(CHUNK_SIZE.pow(3) as usize)?.into()),
+            };
+        }
+        if !r.bytes.is_empty() {
+            return Err(SaveErr::Corrupt("trailing data"));
+        }
+        for chunk in world.chunks.iter() {
+            check_chunk_tree(&world.nodes, chunk.root)?;
+        }
+        Ok(world)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::Voxel;
+    use glam::ivec3;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("vrt-save-{}-{name}", std::process::id()))
+    }
+
+    fn test_world() -> World {
+        let size = UVec3::new(2, 1, 1);
+        let mut world = World::new_dims(2 * NODES_PER_CHUNK, size);
+        for (i, pos) in [ivec3(0, 0, 0), ivec3(5, 9, 31), ivec3(40, 3, 7)]
+            .into_iter()
+            .enumerate()
+        {
+            world.set_voxel(pos, Voxel(i as u16 + 1), |_| {}).unwrap();
+        }
+        world
+    }
+
+    /// Saves `world`, lets `corrupt` change the bytes, and loads them back.
+    fn reload(
+        world: &World,
+        name: &str,
+        corrupt: impl FnOnce(&mut Vec<u8>),
+    ) -> Result<World, SaveErr> {
+        let path = temp_path(name);
+        world.save_to_file(&path).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        corrupt(&mut bytes);
+        std::fs::write(&path, bytes).unwrap();
+        let result = World::load_from_file(&path);
+        _ = std::fs::remove_file(&path);
+        result
+    }
+
+    /// The byte offset of the node at `addr` in the saved first alloc.
+    fn node_offset(addr: NodeAddr) -> usize {
+        // magic, version, size, min, anchor, 2 chunk headers, loaded, next
+        let header = 4 + 4 + 12 + 12 + 12 + 2 * 8 + 1 + 4;
+        header + addr as usize * 4
+    }
+
+    #[test]
+    fn round_trip() {
+        let world = test_world();
+        let loaded = reload(&world, "round-trip", |_| {}).unwrap();
+        assert_eq!(loaded.size_in_chunks3(), world.size_in_chunks3());
+        for x in 0..64 {
+            for y in 0..32 {
+                for z in 0..32 {
+                    let pos = ivec3(x, y, z);
+                    assert_eq!(
+                        loaded.get_voxel(pos).unwrap(),
+                        world.get_voxel(pos).unwrap()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let world = test_world();
+        for len in [2, 30, node_offset(3)] {
+            let result = reload(&world, "truncated", |bytes| bytes.truncate(len));
+            assert!(
+                matches!(result, Err(SaveErr::Truncated | SaveErr::NotAWorld)),
+                "loaded a world cut to {len} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn errors_box_with_their_source() {
+        let load = || -> Result<World, Box<dyn std::error::Error>> {
+            Ok(World::load_from_file(&temp_path("missing"))?)
+        };
+        let err = load().err().unwrap();
+        let source = err.source().unwrap().downcast_ref::<std::io::Error>();
+        assert_eq!(source.unwrap().kind(), std::io::ErrorKind::NotFound);
+        assert!(std::error::Error::source(&SaveErr::Truncated).is_none());
+    }
+
+    #[test]
+    fn unknown_voxel() {
+        let world = test_world();
+        let result = reload(&world, "unknown-voxel", |bytes| {
+            // the root's first child is a group of leaves, at least one of them air
+            let first_child = world.get_node(0).child_idx();
+            let leaf = (first_child..first_child + 8)
+                .find(|&addr| !world.get_node(addr).is_split())
+                .unwrap();
+            let offset = node_offset(leaf);
+            bytes[offset..offset + 4].copy_from_slice(&(VOXEL_NAMES.len() as u32).to_le_bytes());
+        });
+        assert!(matches!(result, Err(SaveErr::Corrupt("unknown voxel id"))));
+    }
+
+    #[test]
+    fn cycle() {
+        let world = test_world();
+        let result = reload(&world, "cycle", |bytes| {
+            // point a split node back at the root's children
+            let first_child = world.get_node(0).child_idx();
+            let split = (first_child..first_child + 8)
+                .find(|&addr| world.get_node(addr).is_split())
+                .unwrap();
+            let offset = node_offset(split);
+            bytes[offset..offset + 4]
+                .copy_from_slice(&Node::new_split(first_child).0.to_le_bytes());
+        });
+        assert!(matches!(
+            result,
+            Err(SaveErr::Corrupt("node reachable from two parents"))
+        ));
+    }
+}
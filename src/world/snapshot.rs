@@ -0,0 +1,109 @@
+use super::{vox_to_chunk_pos, Node, NodeAddr, World};
+use glam::{ivec3, IVec3};
+use std::ops::Range;
+
+/// The nodes of one chunk, as they were when the snapshot was taken.
+struct ChunkSnapshot {
+    alloc: u32,
+    /// The first node of the chunk (its root).
+    start: NodeAddr,
+    /// Where the chunk's allocator was, so nodes allocated since are freed on restore.
+    next: NodeAddr,
+    nodes: Box<[Node]>,
+}
+
+/// A copy of the chunks an edit touches, to roll the edit back if it fails partway.
+pub struct WorldSnapshot {
+    chunks: Vec<ChunkSnapshot>,
+}
+
+/// Snapshot and restore regions of the world, so multi-voxel edits are all or nothing.
+impl World {
+    /// Copies the used nodes of every chunk overlapping the region between `min` and `max` (inclusive).
+    pub fn snapshot(&self, min: IVec3, max: IVec3) -> WorldSnapshot {
+        let (min_chunk, max_chunk) = (
+            vox_to_chunk_pos(min.min(max)),
+            vox_to_chunk_pos(min.max(max)),
+        );
+        let mut chunks = vec![];
+        for x in min_chunk.x..=max_chunk.x {
+            for y in min_chunk.y..=max_chunk.y {
+                for z in min_chunk.z..=max_chunk.z {
+                    let Some(idx) = self.chunk_idx(ivec3(x, y, z)) else {
+                        continue;
+                    };
+                    let chunk = &self.chunks[idx as usize];
+                    let next = self.allocs[chunk.alloc as usize].next;
+                    chunks.push(ChunkSnapshot {
+                        alloc: chunk.alloc,
+                        start: chunk.root,
+                        next,
+                        nodes: self.nodes[chunk.root as usize..next as usize].into(),
+                    });
+                }
+            }
+        }
+        WorldSnapshot { chunks }
+    }
+
+    /// Puts back the nodes saved in `snapshot`, returning the node ranges that
+    /// were restored so they can be uploaded.
+    /// The world must not have moved since the snapshot was taken.
+    pub fn restore(&mut self, snapshot: WorldSnapshot) -> Vec<Range<NodeAddr>> {
+        let mut ranges = Vec::with_capacity(snapshot.chunks.len());
+        for chunk in snapshot.chunks {
+            self.nodes[chunk.start as usize..chunk.next as usize].copy_from_slice(&chunk.nodes);
+            self.allocs[chunk.alloc as usize].next = chunk.next;
+            ranges.push(chunk.start..chunk.next);
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::{PlaceMode, Voxel, World, WorldErr, NODES_PER_CHUNK};
+    use glam::{ivec3, IVec3};
+
+    #[test]
+    fn failed_edits_roll_back() {
+        let mut world = World::new(NODES_PER_CHUNK, 1);
+        world
+            .set_voxels_collected(IVec3::ZERO, ivec3(31, 3, 31), Voxel::STONE)
+            .unwrap();
+        world
+            .set_voxel(ivec3(20, 9, 5), Voxel::WATER, |_| {})
+            .unwrap();
+        let voxels = |world: &World| -> Vec<Voxel> {
+            (0..32 * 32 * 32)
+                .map(|i| {
+                    world
+                        .get_voxel(ivec3(i % 32, (i / 32) % 32, i / 1024))
+                        .unwrap()
+                })
+                .collect()
+        };
+        let before = voxels(&world);
+        let next = world.allocs[0].next;
+        // leave room for the start of the edit, but not all of it
+        world.allocs[0].range.end = next + 64;
+
+        let (a, b) = (ivec3(0, 4, 0), ivec3(31, 31, 31));
+        let snapshot = world.snapshot(a, b);
+        let result = world.set_line_collected(a, b, Voxel::GOLD, PlaceMode::ReplaceAny);
+        assert!(matches!(result, Err(WorldErr::NodeAllocLimit)));
+        assert_eq!(
+            world.get_voxel(a).unwrap(),
+            Voxel::GOLD,
+            "the edit should fail partway"
+        );
+
+        let restored = world.restore(snapshot);
+        assert_eq!(restored, vec![0..next]);
+        assert_eq!(world.allocs[0].next, next);
+        assert!(
+            voxels(&world) == before,
+            "the world should be back to before the edit"
+        );
+    }
+}